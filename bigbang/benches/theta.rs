@@ -1,4 +1,8 @@
-use bigbang::{collisions::soft_body, AsEntity, Entity, GravTree, Responsive, SimulationResult};
+use bigbang::{
+    collisions::soft_body,
+    ics::{InitialConditions, UniformCube},
+    AsEntity, CalculateCollisions, Entity, GravTree, Responsive, SimulationResult,
+};
 use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
 use std::time;
 #[derive(Clone)]
@@ -22,6 +26,7 @@ impl AsEntity for MyEntity {
             vz: self.vz,
             radius: self.radius,
             mass: if self.radius < 1. { 0.5 } else { 105. },
+            ..Default::default()
         };
     }
 }
@@ -67,20 +72,30 @@ impl MyEntity {
     }
 }
 
+// Entities start at rest, spread evenly through a cube whose side is the number of entities
 fn initialize_data(number_of_particles: usize) -> Vec<MyEntity> {
-    let mut vec: Vec<MyEntity> = Vec::new();
-    for x in 0..number_of_particles {
-        let entity = MyEntity::new_entity(x as f64, x as f64, x as f64, 10.);
-        vec.push(entity);
+    UniformCube {
+        mass: 1.,
+        side: number_of_particles as f64,
+        virial_ratio: 0.,
     }
-    vec
+    .generate(number_of_particles, 0)
+    .iter()
+    .map(|e| MyEntity::new_entity(e.x, e.y, e.z, 10.))
+    .collect()
 }
 
 fn initialize_tree(number_of_particles: usize, theta: f64) -> GravTree<MyEntity> {
     let max_entities = 3;
     let time_step = 0.2;
-    let mut data = initialize_data(number_of_particles);
-    GravTree::new(&mut data, time_step, max_entities, theta)
+    let data = initialize_data(number_of_particles);
+    GravTree::new(
+        &data,
+        time_step,
+        max_entities,
+        theta,
+        CalculateCollisions::Yes,
+    )
 }
 
 // Theta isn't used in tree construction so it isn't varied in the benches
@@ -89,14 +104,14 @@ fn tree_construction(c: &mut Criterion) {
     group.bench_function("n=125", |b| {
         b.iter_batched(
             || initialize_data(125),
-            |mut data| GravTree::new(&mut data, 0.2, 3, 0.2),
+            |data| GravTree::new(&data, 0.2, 3, 0.2, CalculateCollisions::Yes),
             BatchSize::SmallInput,
         )
     });
     group.bench_function("n=2000", |b| {
         b.iter_batched(
             || initialize_data(2000),
-            |mut data| GravTree::new(&mut data, 0.2, 3, 0.2),
+            |data| GravTree::new(&data, 0.2, 3, 0.2, CalculateCollisions::Yes),
             BatchSize::SmallInput,
         )
     });
@@ -104,7 +119,7 @@ fn tree_construction(c: &mut Criterion) {
     group.bench_function("n=20_000", |b| {
         b.iter_batched(
             || initialize_data(20_000),
-            |mut data| GravTree::new(&mut data, 0.2, 3, 0.2),
+            |data| GravTree::new(&data, 0.2, 3, 0.2, CalculateCollisions::Yes),
             BatchSize::SmallInput,
         )
     });
@@ -0,0 +1,192 @@
+//! This module contains the boundary conditions which can be applied to a [[GravTree]] with
+//! [[GravTree::set_boundary]]. Boundaries are applied at the end of every time step, after every
+//! entity has moved.
+//!
+//! Entities which are removed by a boundary are reported by [[GravTree::get_escapers]] until the
+//! next time step.
+
+use crate::entity::Entity;
+use crate::utilities::{center_of_mass, dot, sub};
+use serde::{Deserialize, Serialize};
+
+/// An axis-aligned box, from `min` to `max`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Domain {
+    pub min: (f64, f64, f64),
+    pub max: (f64, f64, f64),
+}
+
+impl Domain {
+    pub fn new(min: (f64, f64, f64), max: (f64, f64, f64)) -> Domain {
+        Domain { min, max }
+    }
+
+    /// A cube of side `size` with one corner at the origin.
+    pub fn cube(size: f64) -> Domain {
+        Domain {
+            min: (0., 0., 0.),
+            max: (size, size, size),
+        }
+    }
+
+    /// Returns the side lengths of the box.
+    pub fn size(&self) -> (f64, f64, f64) {
+        sub(&self.max, &self.min)
+    }
+
+    /// Returns whether or not `position` is inside of the box, including its walls.
+    pub fn contains(&self, position: (f64, f64, f64)) -> bool {
+        self.min.0 <= position.0
+            && position.0 <= self.max.0
+            && self.min.1 <= position.1
+            && position.1 <= self.max.1
+            && self.min.2 <= position.2
+            && position.2 <= self.max.2
+    }
+}
+
+/// What happens to entities at the edge of the simulation.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Boundary {
+    /// Entities bounce off of the walls of the domain, elastically.
+    Reflecting(Domain),
+    /// Entities which leave the domain are removed from the simulation.
+    Absorbing(Domain),
+    /// Entities which leave through one wall of the domain come back in through the opposite one.
+    /// Forces and collisions use the nearest periodic image of every entity and node. There is
+    /// no Ewald correction for the more distant images, so this is only accurate when structures
+    /// are small compared to the domain.
+    Periodic(Domain),
+    /// Entities further than `radius` from the center of mass of the simulation which are not
+    /// bound to it are removed. Whether an entity is bound is judged as if all of the mass of the
+    /// simulation were at its center of mass, with gravitational constant `g`.
+    Escapers { radius: f64, g: f64 },
+}
+
+/// The center of mass, its velocity and the total mass of the simulation, which is what escapers
+/// are judged against.
+pub(crate) struct System {
+    center: (f64, f64, f64),
+    velocity: (f64, f64, f64),
+    mass: f64,
+}
+
+impl System {
+    pub(crate) fn new(entities: &[Entity]) -> System {
+        let (mass, center, velocity) = center_of_mass(entities);
+        System {
+            center,
+            velocity,
+            mass,
+        }
+    }
+}
+
+/// What a boundary did to an entity.
+pub(crate) enum Applied {
+    Unchanged,
+    Moved(Entity),
+    Removed,
+}
+
+impl Boundary {
+    /// Returns the side lengths of the domain, if it is periodic.
+    pub(crate) fn periodic_size(&self) -> Option<(f64, f64, f64)> {
+        match self {
+            Boundary::Periodic(domain) => Some(domain.size()),
+            _ => None,
+        }
+    }
+
+    /// Checks that the domain of a reflecting or periodic boundary has sides which are positive
+    /// and finite, since entities could not be folded back into it otherwise, and says why not if
+    /// they are not.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if let Boundary::Reflecting(domain) | Boundary::Periodic(domain) = self {
+            let (x, y, z) = domain.size();
+            if ![x, y, z].iter().all(|side| side.is_finite() && *side > 0.) {
+                return Err(format!(
+                    "the sides of a reflecting or periodic domain must be positive, not ({}, {}, {})",
+                    x, y, z
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether or not this boundary needs to judge entities against the whole system.
+    pub(crate) fn needs_system(&self) -> bool {
+        matches!(self, Boundary::Escapers { .. })
+    }
+
+    /// Applies the boundary to a single entity.
+    pub(crate) fn apply(&self, entity: &Entity, system: Option<&System>) -> Applied {
+        let position = (entity.x, entity.y, entity.z);
+        match self {
+            Boundary::Reflecting(domain) => {
+                if domain.contains(position) {
+                    return Applied::Unchanged;
+                }
+                // An entity may have crossed the box several times in one step, so its path is
+                // unfolded over a period of two box widths, in which it is travelling backwards
+                // for the second width.
+                let reflect = |value: f64, velocity: f64, min: f64, max: f64| {
+                    if min <= value && value <= max {
+                        return (value, velocity);
+                    }
+                    let size = max - min;
+                    let folded = (value - min).rem_euclid(2. * size);
+                    if folded > size {
+                        (max - (folded - size), -velocity)
+                    } else {
+                        (min + folded, velocity)
+                    }
+                };
+                let (x, vx) = reflect(entity.x, entity.vx, domain.min.0, domain.max.0);
+                let (y, vy) = reflect(entity.y, entity.vy, domain.min.1, domain.max.1);
+                let (z, vz) = reflect(entity.z, entity.vz, domain.min.2, domain.max.2);
+                Applied::Moved(Entity {
+                    x,
+                    y,
+                    z,
+                    vx,
+                    vy,
+                    vz,
+                    ..entity.clone()
+                })
+            }
+            Boundary::Absorbing(domain) => {
+                if domain.contains(position) {
+                    Applied::Unchanged
+                } else {
+                    Applied::Removed
+                }
+            }
+            Boundary::Periodic(domain) => {
+                if domain.contains(position) {
+                    return Applied::Unchanged;
+                }
+                let (size_x, size_y, size_z) = domain.size();
+                Applied::Moved(Entity {
+                    x: domain.min.0 + (entity.x - domain.min.0).rem_euclid(size_x),
+                    y: domain.min.1 + (entity.y - domain.min.1).rem_euclid(size_y),
+                    z: domain.min.2 + (entity.z - domain.min.2).rem_euclid(size_z),
+                    ..entity.clone()
+                })
+            }
+            Boundary::Escapers { radius, g } => {
+                let system = system.expect("escapers are judged against the whole system");
+                let offset = sub(&position, &system.center);
+                let distance = dot(&offset, &offset).sqrt();
+                let velocity = sub(&(entity.vx, entity.vy, entity.vz), &system.velocity);
+                if distance > *radius
+                    && 0.5 * dot(&velocity, &velocity) > g * system.mass / distance
+                {
+                    Applied::Removed
+                } else {
+                    Applied::Unchanged
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! This module contains the checkpoint format used by [[GravTree::save_checkpoint]] and
+//! [[GravTree::load_checkpoint]] to stop a simulation and restart it later.
+//!
+//! A checkpoint consists of:
+//! 1. the magic bytes `BIGBANG\0`,
+//! 2. the version of the format it was written with, and the oldest version of the format a
+//!    reader must understand to read it, each as a little-endian `u32`,
+//! 3. the length of the header as a little-endian `u64`, followed by the header, which holds the
+//!    configuration of the tree, the simulation time, the step count and the random number
+//!    generator state,
+//! 4. the length of the body as a little-endian `u64`, followed by the body, which holds the
+//!    entities and the test particles.
+//!
+//! The header and the body are encoded with [bincode](https://docs.rs/bincode). Later versions of
+//! the format only add fields to the end of the header, which older readers skip, and bump the
+//! oldest compatible version when they change anything else. A checkpoint whose oldest compatible
+//! version is newer than [[CHECKPOINT_VERSION]] is refused with
+//! [[CheckpointError::UnsupportedVersion]].
+//!
+//! External fields and drag forces are trait objects, so they are not saved, and must be added to
+//! the tree again after it is loaded.
+
+use crate::boundaries::Boundary;
+use crate::collisions::Friction;
+use crate::cosmology::{Cosmology, TimeVariable};
+use crate::entity::CalculateCollisions;
+use crate::hermite::Hermite;
+use crate::interaction_matrix::InteractionMatrix;
+use crate::post_newtonian::PostNewtonian;
+use crate::regularization::Regularization;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/// The version of the checkpoint format which this version of bigbang writes.
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+/// The oldest version of the checkpoint format which can read what this version writes.
+const COMPATIBLE_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 8] = b"BIGBANG\0";
+
+/// The ways in which saving or loading a checkpoint can fail.
+#[derive(Debug)]
+pub enum CheckpointError {
+    /// The checkpoint could not be read or written.
+    Io(io::Error),
+    /// The file does not start with the checkpoint magic bytes.
+    NotACheckpoint,
+    /// The checkpoint was written with a version of the format which this version of bigbang does
+    /// not understand.
+    UnsupportedVersion {
+        version: u32,
+        compatible_version: u32,
+    },
+    /// The header or the body could not be decoded, e.g. because the checkpoint was saved with a
+    /// different entity type or force law.
+    Corrupt(String),
+    /// The checkpoint was saved by a tree which integrates its entities itself, so it must be loaded
+    /// with [[GravTree::load_integrable_checkpoint]].
+    RequiresIntegrable,
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::Io(error) => write!(f, "could not access the checkpoint: {}", error),
+            CheckpointError::NotACheckpoint => write!(f, "the file is not a bigbang checkpoint"),
+            CheckpointError::UnsupportedVersion {
+                version,
+                compatible_version,
+            } => write!(
+                f,
+                "the checkpoint has version {} and needs a reader of version {} or later, but \
+                 this reader has version {}",
+                version, compatible_version, CHECKPOINT_VERSION
+            ),
+            CheckpointError::Corrupt(reason) => write!(f, "the checkpoint is corrupt: {}", reason),
+            CheckpointError::RequiresIntegrable => write!(
+                f,
+                "the checkpoint integrates its entities itself and needs an Integrable type"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(error: io::Error) -> Self {
+        CheckpointError::Io(error)
+    }
+}
+
+impl From<bincode::Error> for CheckpointError {
+    fn from(error: bincode::Error) -> Self {
+        match *error {
+            bincode::ErrorKind::Io(error) => CheckpointError::Io(error),
+            error => CheckpointError::Corrupt(error.to_string()),
+        }
+    }
+}
+
+/// Everything about a [[GravTree]] except for its entities. New fields may only be added to the
+/// end.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Header<F> {
+    pub(crate) time_step: f64,
+    pub(crate) max_entities: i32,
+    pub(crate) theta: f64,
+    pub(crate) calculate_collisions: CalculateCollisions,
+    pub(crate) derivatives: bool,
+    pub(crate) collision_groups: u32,
+    pub(crate) interactions: Option<InteractionMatrix>,
+    pub(crate) law: F,
+    pub(crate) time: f64,
+    pub(crate) steps: u64,
+    pub(crate) rng_state: Vec<u8>,
+    pub(crate) cosmology: Option<(Cosmology, TimeVariable)>,
+    pub(crate) boundary: Option<Boundary>,
+    pub(crate) post_newtonian: Option<PostNewtonian>,
+    pub(crate) regularization: Option<Regularization>,
+    pub(crate) hermite: Option<Hermite>,
+    pub(crate) friction: Option<Friction>,
+    /// Whether or not the tree integrates entities itself, which requires [[Integrable]].
+    pub(crate) integrated: bool,
+}
+
+/// Writes a checkpoint of `header` and the entities and test particles in `body`.
+pub(crate) fn write<W: Write, F: Serialize, T: Serialize>(
+    mut writer: W,
+    header: &Header<F>,
+    body: (&[T], &[T]),
+) -> Result<(), CheckpointError> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&CHECKPOINT_VERSION.to_le_bytes())?;
+    writer.write_all(&COMPATIBLE_VERSION.to_le_bytes())?;
+    for section in &[bincode::serialize(header)?, bincode::serialize(&body)?] {
+        writer.write_all(&(section.len() as u64).to_le_bytes())?;
+        writer.write_all(section)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a checkpoint, returning its header and its entities and test particles.
+#[allow(clippy::type_complexity)]
+pub(crate) fn read<R: Read, F: DeserializeOwned, T: DeserializeOwned>(
+    mut reader: R,
+) -> Result<(Header<F>, (Vec<T>, Vec<T>)), CheckpointError> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(CheckpointError::NotACheckpoint);
+    }
+    let version = read_u32(&mut reader)?;
+    let compatible_version = read_u32(&mut reader)?;
+    if compatible_version > CHECKPOINT_VERSION {
+        return Err(CheckpointError::UnsupportedVersion {
+            version,
+            compatible_version,
+        });
+    }
+    // anything a newer writer added to the end of a section is ignored
+    let header = bincode::deserialize(&read_section(&mut reader)?)?;
+    let body = bincode::deserialize(&read_section(&mut reader)?)?;
+    Ok((header, body))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, CheckpointError> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_section<R: Read>(reader: &mut R) -> Result<Vec<u8>, CheckpointError> {
+    let mut length = [0; 8];
+    reader.read_exact(&mut length)?;
+    let length = u64::from_le_bytes(length);
+    let mut section = Vec::new();
+    reader.take(length).read_to_end(&mut section)?;
+    if (section.len() as u64) < length {
+        return Err(CheckpointError::Corrupt(format!(
+            "a section should be {} bytes long, but the checkpoint ends after {}",
+            length,
+            section.len()
+        )));
+    }
+    Ok(section)
+}
@@ -5,6 +5,28 @@
 //!  exerted from the collision
 
 use crate::as_entity::AsEntity;
+use crate::utilities::{add, cross, dot, scale, sub};
+use serde::{Deserialize, Serialize};
+
+/// The settings for the contact friction which a [[crate::GravTree]] uses to spin entities up
+/// itself. See [[crate::GravTree::set_friction]].
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Friction {
+    /// The stiffness of the contact, which should match that passed to [[soft_body]] in
+    /// [[crate::Responsive::respond]].
+    pub stiffness: f64,
+    /// The coefficient of friction between the two surfaces.
+    pub coefficient: f64,
+}
+
+impl Friction {
+    pub fn new(stiffness: f64, coefficient: f64) -> Friction {
+        Friction {
+            stiffness,
+            coefficient,
+        }
+    }
+}
 
 /// Uses [Hooke's law](https://en.wikipedia.org/wiki/Hooke%27s_law) exerting an outwards force
 /// proportional to the amount of overlap when two entities are overlapping.
@@ -40,3 +62,62 @@ fn unit_vector(vec: &(f64, f64, f64)) -> (f64, f64, f64) {
     let length = f64::sqrt((x * x) + (y * y) + (z * z));
     (x / length, y / length, z / length)
 }
+
+/// Uses [Coulomb friction](https://en.wikipedia.org/wiki/Friction#Dry_friction) to calculate the
+/// tangential force exerted at the contact point of two overlapping entities. The normal force is
+/// the same Hooke's law force as in [[soft_body]], so `stiffness` should match the value used there.
+/// The argument `coefficient` is the coefficient of friction between the two surfaces.
+///
+/// Friction acts against the relative velocity of the two surfaces at the contact point, which
+/// includes the spin of both entities. Because of this it both slows sliding entities and exerts
+/// a torque on them, spinning them up until they roll.
+///
+/// Returns a pair of vectors: the linear acceleration and the angular acceleration of `p1`. If `p1`
+/// has no mass, or no moment of inertia, the corresponding acceleration is zero.
+pub fn friction<T>(
+    p1: &T,
+    p2: &T,
+    stiffness: f64,
+    coefficient: f64,
+) -> ((f64, f64, f64), (f64, f64, f64))
+where
+    T: AsEntity,
+{
+    let p1 = p1.as_entity();
+    let p2 = p2.as_entity();
+    let distance = p1.distance(&p2);
+    let radii_sum = p1.radius + p2.radius;
+    // no overlap or entirely overlapping -- there is no contact surface to speak of
+    if distance >= radii_sum || distance == 0. {
+        return ((0., 0., 0.), (0., 0., 0.));
+    }
+    let normal_force = stiffness * (radii_sum - distance);
+    // the normal points from the center of p1 towards the center of p2
+    let normal = unit_vector(&p1.distance_vector(&p2));
+
+    // the contact point, relative to the center of each entity
+    let r1 = scale(&normal, p1.radius);
+    let r2 = scale(&normal, -p2.radius);
+
+    // velocity of each surface at the contact point
+    let v1 = add(&(p1.vx, p1.vy, p1.vz), &cross(&(p1.wx, p1.wy, p1.wz), &r1));
+    let v2 = add(&(p2.vx, p2.vy, p2.vz), &cross(&(p2.wx, p2.wy, p2.wz), &r2));
+    let relative = sub(&v1, &v2);
+    let tangential = sub(&relative, &scale(&normal, dot(&relative, &normal)));
+    let tangential_speed = dot(&tangential, &tangential).sqrt();
+    if tangential_speed == 0. {
+        return ((0., 0., 0.), (0., 0., 0.));
+    }
+
+    let force = scale(&tangential, -coefficient * normal_force / tangential_speed);
+    let torque = cross(&r1, &force);
+    // a massless or point-like entity has nothing for the force or torque to act on
+    let per_unit = |vector: &(f64, f64, f64), amount: f64| {
+        if amount > 0. {
+            scale(vector, 1. / amount)
+        } else {
+            (0., 0., 0.)
+        }
+    };
+    (per_unit(&force, p1.mass), per_unit(&torque, p1.inertia()))
+}
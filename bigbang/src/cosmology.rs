@@ -0,0 +1,118 @@
+//! This module contains the background cosmology used to run a [[GravTree]] in comoving
+//! coordinates, i.e. in an expanding universe. See [[GravTree::set_cosmology]].
+//!
+//! In comoving mode, positions are comoving coordinates `x = r / a`, and velocities are peculiar
+//! velocities `v = a dx/dt`. Each time step, the tree kicks the canonical momentum `p = a v` by the
+//! comoving acceleration times the kick factor `∫ dt / a`, and then drifts the position by `p`
+//! times the drift factor `∫ dt / a^2`, as in GADGET.
+
+use serde::{Deserialize, Serialize};
+
+/// The number of Simpson's rule intervals used for the integrals over the scale factor.
+const INTEGRATION_STEPS: usize = 256;
+
+/// A Friedmann-Lemaître-Robertson-Walker background made of pressureless matter and a cosmological
+/// constant. Any curvature is whatever is left over, `Ωk = 1 - Ωm - ΩΛ`.
+///
+/// `h0` is the Hubble constant in the simulation's units of inverse time, e.g. `0.1` if a unit of
+/// time is a Hubble time of `10`. The universe is assumed to expand forever.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Cosmology {
+    /// The matter density parameter today, `Ωm`.
+    pub omega_m: f64,
+    /// The cosmological constant density parameter today, `ΩΛ`.
+    pub omega_lambda: f64,
+    /// The Hubble constant, `H0`.
+    pub h0: f64,
+}
+
+/// What the simulation time of a comoving [[GravTree]] measures, and so what its time step is a
+/// step of.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum TimeVariable {
+    /// The time is the scale factor `a`, which is `1` today.
+    ScaleFactor,
+    /// The time is the cosmic time `t` since the big bang.
+    CosmicTime,
+}
+
+impl Cosmology {
+    pub fn new(omega_m: f64, omega_lambda: f64, h0: f64) -> Cosmology {
+        Cosmology {
+            omega_m,
+            omega_lambda,
+            h0,
+        }
+    }
+
+    /// Returns the curvature density parameter, `Ωk = 1 - Ωm - ΩΛ`.
+    pub fn omega_k(&self) -> f64 {
+        1. - self.omega_m - self.omega_lambda
+    }
+
+    /// Returns the Hubble parameter `H(a)` at scale factor `a`.
+    pub fn hubble(&self, a: f64) -> f64 {
+        self.h0 * (self.omega_m / (a * a * a) + self.omega_k() / (a * a) + self.omega_lambda).sqrt()
+    }
+
+    /// Returns the cosmic time at which the universe had scale factor `a`.
+    pub fn cosmic_time(&self, a: f64) -> f64 {
+        // t = ∫ da / (a H). Substituting a = u^2 removes the square root singularity at a = 0.
+        simpson(|u| 2. / (u * self.hubble(u * u)), 0., a.sqrt())
+    }
+
+    /// Returns the scale factor of the universe at cosmic time `t`. This is the inverse of
+    /// [[Cosmology::cosmic_time]].
+    pub fn scale_factor(&self, t: f64) -> f64 {
+        let mut upper = 1.;
+        while self.cosmic_time(upper) < t {
+            upper *= 2.;
+        }
+        let mut lower = 0.;
+        for _ in 0..64 {
+            let middle = 0.5 * (lower + upper);
+            if self.cosmic_time(middle) < t {
+                lower = middle;
+            } else {
+                upper = middle;
+            }
+        }
+        0.5 * (lower + upper)
+    }
+
+    /// Returns the drift factor `∫ dt / a^2` between scale factors `a0` and `a1`. Multiplying
+    /// the canonical momentum by this gives the change in comoving position.
+    pub fn drift_factor(&self, a0: f64, a1: f64) -> f64 {
+        simpson(|a| 1. / (a * a * a * self.hubble(a)), a0, a1)
+    }
+
+    /// Returns the kick factor `∫ dt / a` between scale factors `a0` and `a1`. Multiplying the
+    /// comoving acceleration by this gives the change in canonical momentum.
+    pub fn kick_factor(&self, a0: f64, a1: f64) -> f64 {
+        simpson(|a| 1. / (a * a * self.hubble(a)), a0, a1)
+    }
+
+    /// Returns the scale factor at simulation time `time`.
+    pub(crate) fn scale_factor_at(&self, time: f64, time_variable: TimeVariable) -> f64 {
+        match time_variable {
+            TimeVariable::ScaleFactor => time,
+            TimeVariable::CosmicTime => self.scale_factor(time),
+        }
+    }
+}
+
+/// Integrates `f` from `lower` to `upper` with Simpson's rule.
+fn simpson<F: Fn(f64) -> f64>(f: F, lower: f64, upper: f64) -> f64 {
+    if lower == upper {
+        return 0.;
+    }
+    let h = (upper - lower) / INTEGRATION_STEPS as f64;
+    // the integrands are finite at zero, but may not be computable there
+    let at = |x: f64| if x == 0. { 0. } else { f(x) };
+    let mut sum = at(lower) + at(upper);
+    for i in 1..INTEGRATION_STEPS {
+        let weight = if i % 2 == 1 { 4. } else { 2. };
+        sum += weight * at(lower + h * i as f64);
+    }
+    sum * h / 3.
+}
@@ -0,0 +1,288 @@
+//! This module estimates the density around each entity the way smoothed particle hydrodynamics
+//! does, by summing the masses of its neighbours weighted by a smoothing kernel. See
+//! [[DensityEstimator]].
+//!
+//! Each entity has its own smoothing length `h`, the radius within which its neighbours count.
+//! It is first guessed as the distance to the entity's `k`th nearest neighbour, and then adjusted
+//! until the kernel-weighted number of neighbours, `4 pi h^3 / 3` times the number density, is
+//! the target neighbour count. Neighbours are found through the tree, so the density of every
+//! entity is estimated in `O(n log n)` time.
+//!
+//! Smoothed particle hydrodynamics counts each entity as one of its own neighbours. Where
+//! entities are placed at random, as they are in an N-body simulation, that overestimates the
+//! density by about `4 pi h^3 W(0) / 3` divided by the neighbour count, which is `14 / 64` for the
+//! Wendland C2 kernel with 64 neighbours, so by default each entity is left out of its own
+//! density. See [[DensityEstimator::include_self]].
+
+use crate::entity::Entity;
+use crate::force_laws::{ForceLaw, Newtonian};
+use crate::utilities::xyz_distances;
+use crate::{AsEntity, GravTree, Node, Responsive};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// The number of entities in each leaf of the tree used to find neighbours.
+const MAX_ENTITIES: i32 = 8;
+
+/// The most times the radius of the nearest neighbour search is doubled before every entity is
+/// searched instead.
+const MAX_DOUBLINGS: usize = 64;
+
+/// A smoothing kernel, normalised in three dimensions. Every kernel is zero beyond the smoothing
+/// length `h`, which is the radius of its support rather than its width.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Kernel {
+    /// The [M4 cubic spline](https://ui.adsabs.harvard.edu/abs/1985A%26A...149..135M), which is
+    /// cheap but lets neighbours clump together once there are more than about 50 of them.
+    CubicSpline,
+    /// The [Wendland](https://ui.adsabs.harvard.edu/abs/2012MNRAS.425.1068D) C2 kernel, which
+    /// is stable for any number of neighbours. About 100 is typical.
+    WendlandC2,
+    /// The Wendland C4 kernel, which is smoother than C2 and suits about 200 neighbours.
+    WendlandC4,
+}
+
+impl Kernel {
+    /// Returns the kernel at a distance `r` from its center with a smoothing length of `h`.
+    pub fn value(&self, r: f64, h: f64) -> f64 {
+        let q = r / h;
+        if q >= 1. {
+            return 0.;
+        }
+        let shape = match self {
+            Kernel::CubicSpline if q < 0.5 => 8. / PI * (1. - 6. * q * q + 6. * q * q * q),
+            Kernel::CubicSpline => 16. / PI * (1. - q).powi(3),
+            Kernel::WendlandC2 => 21. / (2. * PI) * (1. - q).powi(4) * (1. + 4. * q),
+            Kernel::WendlandC4 => {
+                495. / (32. * PI) * (1. - q).powi(6) * (1. + 6. * q + 35. / 3. * q * q)
+            }
+        };
+        shape / (h * h * h)
+    }
+}
+
+/// The density around an entity, found by [[DensityEstimator]].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Density {
+    /// The mass density.
+    pub density: f64,
+    /// The smoothing length the density was found with.
+    pub smoothing_length: f64,
+    /// The kernel-weighted number of neighbours within the smoothing length. This is the target
+    /// neighbour count unless the smoothing length did not converge.
+    pub neighbours: f64,
+}
+
+/// The settings for estimating the density around each entity.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DensityEstimator {
+    /// The kernel each neighbour's mass is weighted by. See [[Kernel]] for how many neighbours
+    /// suit each one.
+    pub kernel: Kernel,
+    /// The target number of neighbours within the smoothing length.
+    pub neighbours: usize,
+    /// Whether each entity counts as one of its own neighbours, and adds its own mass to its
+    /// density, as in smoothed particle hydrodynamics. Entities at the same position as it are
+    /// left out with it otherwise. Defaults to `false`.
+    pub include_self: bool,
+    /// Smoothing lengths are adjusted until the neighbour count is within this fraction of the
+    /// target. Defaults to `1e-4`.
+    pub tolerance: f64,
+    /// The most times the smoothing length of an entity is adjusted. Defaults to `50`.
+    pub max_iterations: usize,
+}
+
+impl DensityEstimator {
+    /// Estimates densities with `kernel` and the target number of `neighbours`.
+    pub fn new(kernel: Kernel, neighbours: usize) -> DensityEstimator {
+        DensityEstimator {
+            kernel,
+            neighbours,
+            include_self: false,
+            tolerance: 1e-4,
+            max_iterations: 50,
+        }
+    }
+
+    /// Returns the density around each of `entities`, in the same order.
+    pub fn estimate(&self, entities: &[Entity]) -> Vec<Density> {
+        let root = Node::new_phantom_root(entities, MAX_ENTITIES, &Newtonian::default());
+        self.estimate_with(&root, entities)
+    }
+
+    /// Returns the entities of `tree` with the density around each of them, found through the
+    /// tree itself. Test particles are left out.
+    pub fn estimate_tree<T, F>(&self, tree: &GravTree<T, F>) -> Vec<(T, Density)>
+    where
+        T: AsEntity + Responsive + Clone + Send + Sync,
+        F: ForceLaw,
+    {
+        let bodies = tree.as_vec();
+        let entities = bodies.iter().map(|b| b.as_entity()).collect::<Vec<_>>();
+        let densities = self.estimate_with(&tree.root, &entities);
+        bodies.into_iter().zip(densities).collect()
+    }
+
+    /// Returns the density around each of `entities`, whose neighbours are found under `root`.
+    fn estimate_with<T: AsEntity + Clone + Send + Sync>(
+        &self,
+        root: &Node<T>,
+        entities: &[Entity],
+    ) -> Vec<Density> {
+        if entities.is_empty() {
+            return Vec::new();
+        }
+        // the first radius of the nearest neighbour search, which doubles until it holds enough
+        // entities. Entities are usually much closer together than if they were spread evenly
+        // through their bounding box, so it starts well inside of that.
+        let (x, y, z) = xyz_distances(entities);
+        let extent = x.max(y).max(z);
+        let guess = extent * (self.neighbours as f64 / entities.len() as f64).cbrt() / 16.;
+        let guess = if guess > 0. { guess } else { 1. };
+        let target = if self.include_self {
+            self.neighbours.clamp(1, entities.len())
+        } else {
+            self.neighbours.min(entities.len() - 1)
+        };
+        entities
+            .par_iter()
+            .map(|entity| self.density(root, entity, target, guess))
+            .collect()
+    }
+
+    /// Returns the density around `entity`, iterating its smoothing length until `target`
+    /// neighbours are within it.
+    fn density<T: AsEntity + Clone>(
+        &self,
+        root: &Node<T>,
+        entity: &Entity,
+        target: usize,
+        guess: f64,
+    ) -> Density {
+        if target == 0 {
+            // a lone entity which is not its own neighbour
+            return Density {
+                density: 0.,
+                smoothing_length: guess,
+                neighbours: 0.,
+            };
+        }
+        let point = (entity.x, entity.y, entity.z);
+        let k = if self.include_self {
+            target
+        } else {
+            target + 1
+        };
+        // one buffer for the neighbours of every search around this entity
+        let mut neighbours = Vec::new();
+        let mut h = nearest(root, point, k, guess, &mut neighbours);
+        let target = target as f64;
+        // the smoothing lengths known to give too few and too many neighbours
+        let (mut lower, mut upper) = (0., f64::INFINITY);
+        let mut estimate = self.sum(root, point, h, &mut neighbours);
+        for iteration in 0..self.max_iterations {
+            if (estimate.neighbours - target).abs() <= self.tolerance * target {
+                break;
+            }
+            if estimate.neighbours < target {
+                lower = h;
+            } else {
+                upper = h;
+            }
+            // the neighbour count grows as h^3 at a constant density, but that can creep up on
+            // the target slowly where the density is far from constant, so every other step once
+            // the target is bracketed halves the bracket instead
+            let next = h * (target / estimate.neighbours.max(f64::MIN_POSITIVE)).cbrt();
+            h = if upper.is_finite() && (iteration % 2 == 1 || next <= lower || next >= upper) {
+                0.5 * (lower + upper)
+            } else {
+                next
+            };
+            estimate = self.sum(root, point, h, &mut neighbours);
+        }
+        estimate
+    }
+
+    /// Returns the density at `point` with the smoothing length `h`, finding the neighbours in
+    /// `neighbours`.
+    fn sum<'a, T: AsEntity + Clone>(
+        &self,
+        root: &'a Node<T>,
+        point: (f64, f64, f64),
+        h: f64,
+        neighbours: &mut Vec<&'a T>,
+    ) -> Density {
+        let (mut density, mut number) = (0., 0.);
+        neighbours.clear();
+        root.entities_within(point, h, neighbours);
+        for other in neighbours.iter() {
+            let other = other.as_entity();
+            let (x, y, z) = (other.x - point.0, other.y - point.1, other.z - point.2);
+            let r = (x * x + y * y + z * z).sqrt();
+            if r == 0. && !self.include_self {
+                continue;
+            }
+            let w = self.kernel.value(r, h);
+            density += other.mass * w;
+            number += w;
+        }
+        Density {
+            density,
+            smoothing_length: h,
+            neighbours: 4. / 3. * PI * h * h * h * number,
+        }
+    }
+}
+
+/// Returns the distance from `point` to its `k`th nearest entity under `root`, counting any entity
+/// at `point` itself, starting the search within `radius` and finding entities in `neighbours`. If
+/// there are fewer than `k` entities, this is the distance to the furthest one.
+fn nearest<'a, T: AsEntity + Clone>(
+    root: &'a Node<T>,
+    point: (f64, f64, f64),
+    k: usize,
+    radius: f64,
+    neighbours: &mut Vec<&'a T>,
+) -> f64 {
+    let distance = |other: &T| {
+        let other = other.as_entity();
+        let (x, y, z) = (other.x - point.0, other.y - point.1, other.z - point.2);
+        (x * x + y * y + z * z).sqrt()
+    };
+    let kth = |mut distances: Vec<f64>, radius: f64| {
+        distances.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let kth = distances[k.min(distances.len()) - 1];
+        // entities all at one point still need a smoothing length
+        if kth > 0. {
+            kth
+        } else {
+            radius
+        }
+    };
+    let mut radius = radius;
+    for _ in 0..MAX_DOUBLINGS {
+        neighbours.clear();
+        root.entities_within(point, radius, neighbours);
+        let distances = neighbours
+            .iter()
+            .map(|other| distance(other))
+            .collect::<Vec<_>>();
+        if distances.len() >= k {
+            return kth(distances, radius);
+        }
+        radius *= 2.;
+    }
+    // the search has long since covered every entity which is not infinitely far away, or the
+    // point is not finite, so every entity is looked at
+    let distances = root
+        .traverse_tree_helper()
+        .iter()
+        .map(distance)
+        .collect::<Vec<_>>();
+    if distances.is_empty() {
+        radius
+    } else {
+        kth(distances, radius)
+    }
+}
@@ -0,0 +1,164 @@
+//! This module contains velocity-dependent, dissipative forces such as gas drag and numerical
+//! damping. Drag forces are added to a tree with [[GravTree::add_drag]] (for every entity) or
+//! [[GravTree::add_species_drag]] (for one species), after which their acceleration is summed into
+//! every affected entity's `gravitational_acceleration`, alongside gravity.
+//!
+//! Drag is evaluated from each entity's velocity at the start of the step, so a drag whose
+//! stopping time is shorter than the time step will overshoot. Keep the time step well below the
+//! shortest stopping time.
+
+use crate::entity::Entity;
+use crate::utilities::{dot, scale, sub};
+use std::f64::consts::PI;
+
+/// A velocity-dependent force. Implement this for your own dissipative forces.
+pub trait Drag: Send + Sync {
+    /// Returns the acceleration the force exerts on `entity` at simulation time `time`.
+    fn acceleration(&self, entity: &Entity, time: f64) -> (f64, f64, f64);
+}
+
+/// A velocity as a function of position and simulation time.
+pub type VelocityField = Box<dyn Fn((f64, f64, f64), f64) -> (f64, f64, f64) + Send + Sync>;
+
+/// The velocity of the medium an entity is moving through, which drag acts relative to.
+pub enum Background {
+    /// The medium is at rest.
+    Still,
+    /// The medium moves with the same velocity everywhere.
+    Uniform((f64, f64, f64)),
+    /// The medium's velocity is a function of position and simulation time, e.g. a sub-Keplerian
+    /// gas disk.
+    Field(VelocityField),
+}
+
+impl Background {
+    /// Returns the velocity of the medium at `position` at simulation time `time`.
+    pub fn velocity(&self, position: (f64, f64, f64), time: f64) -> (f64, f64, f64) {
+        match self {
+            Background::Still => (0., 0., 0.),
+            Background::Uniform(velocity) => *velocity,
+            Background::Field(field) => field(position, time),
+        }
+    }
+
+    /// Returns the velocity of `entity` relative to the medium.
+    fn relative_velocity(&self, entity: &Entity, time: f64) -> (f64, f64, f64) {
+        sub(
+            &(entity.vx, entity.vy, entity.vz),
+            &self.velocity((entity.x, entity.y, entity.z), time),
+        )
+    }
+}
+
+/// Returns the bulk density of an entity, assuming it is a uniform sphere.
+fn material_density(entity: &Entity) -> f64 {
+    entity.mass / (4. / 3. * PI * entity.radius * entity.radius * entity.radius)
+}
+
+/// Drag proportional to the relative velocity, `a = -rate * (v - v_background)`. `rate` is the
+/// inverse of the stopping time.
+pub struct LinearDrag {
+    pub rate: f64,
+    pub background: Background,
+}
+
+impl Drag for LinearDrag {
+    fn acceleration(&self, entity: &Entity, time: f64) -> (f64, f64, f64) {
+        scale(&self.background.relative_velocity(entity, time), -self.rate)
+    }
+}
+
+/// Drag proportional to the square of the relative velocity, as felt by large bodies at high
+/// Reynolds number: `F = 1/2 * density * drag_coefficient * A * |v|^2`, where `A` is the entity's
+/// cross-section.
+pub struct QuadraticDrag {
+    /// The density of the medium.
+    pub density: f64,
+    /// The dimensionless drag coefficient. This is about `0.47` for a sphere.
+    pub drag_coefficient: f64,
+    pub background: Background,
+}
+
+impl Drag for QuadraticDrag {
+    fn acceleration(&self, entity: &Entity, time: f64) -> (f64, f64, f64) {
+        if entity.mass == 0. {
+            return (0., 0., 0.);
+        }
+        let relative = self.background.relative_velocity(entity, time);
+        let speed = dot(&relative, &relative).sqrt();
+        let area = PI * entity.radius * entity.radius;
+        scale(
+            &relative,
+            -0.5 * self.density * self.drag_coefficient * area * speed / entity.mass,
+        )
+    }
+}
+
+/// [Epstein drag](https://en.wikipedia.org/wiki/Epstein_drag) on a grain smaller than the mean
+/// free path of the gas. The stopping time is `rho_grain * s / (rho_gas * v_thermal)`, where the
+/// grain's bulk density and size `s` come from the entity's mass and radius. Entities without a
+/// radius or a mass feel no drag.
+pub struct Epstein {
+    pub gas_density: f64,
+    /// The mean thermal speed of the gas molecules.
+    pub thermal_velocity: f64,
+    pub background: Background,
+}
+
+impl Drag for Epstein {
+    fn acceleration(&self, entity: &Entity, time: f64) -> (f64, f64, f64) {
+        // a point or a massless grain would be stopped instantly, so it is left alone
+        if entity.radius == 0. || entity.mass == 0. {
+            return (0., 0., 0.);
+        }
+        let stopping_time =
+            material_density(entity) * entity.radius / (self.gas_density * self.thermal_velocity);
+        scale(
+            &self.background.relative_velocity(entity, time),
+            -1. / stopping_time,
+        )
+    }
+}
+
+/// [Stokes drag](https://en.wikipedia.org/wiki/Stokes%27_law) on a grain larger than the mean free
+/// path of the gas, at low Reynolds number. The stopping time is
+/// `2 * rho_grain * s^2 / (9 * viscosity * rho_gas)`, where `viscosity` is the kinematic viscosity.
+/// Entities without a radius or a mass feel no drag.
+pub struct Stokes {
+    pub gas_density: f64,
+    /// The kinematic viscosity of the gas.
+    pub viscosity: f64,
+    pub background: Background,
+}
+
+impl Drag for Stokes {
+    fn acceleration(&self, entity: &Entity, time: f64) -> (f64, f64, f64) {
+        // a point or a massless grain would be stopped instantly, so it is left alone
+        if entity.radius == 0. || entity.mass == 0. {
+            return (0., 0., 0.);
+        }
+        let stopping_time = 2. * material_density(entity) * entity.radius * entity.radius
+            / (9. * self.viscosity * self.gas_density);
+        scale(
+            &self.background.relative_velocity(entity, time),
+            -1. / stopping_time,
+        )
+    }
+}
+
+/// Global velocity damping, `a = -rate * v`, which drains kinetic energy from the system. This is
+/// not physical and is meant for relaxing initial conditions into equilibrium before the real
+/// simulation starts.
+pub struct Damping {
+    pub rate: f64,
+}
+
+impl Drag for Damping {
+    fn acceleration(&self, entity: &Entity, _time: f64) -> (f64, f64, f64) {
+        (
+            -self.rate * entity.vx,
+            -self.rate * entity.vy,
+            -self.rate * entity.vz,
+        )
+    }
+}
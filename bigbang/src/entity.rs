@@ -2,7 +2,10 @@ use either::{Either, Left, Right};
 
 use super::Dimension;
 use crate::as_entity::AsEntity;
+use crate::force_laws::ForceLaw;
+use crate::interaction_matrix::InteractionMatrix;
 use crate::simulation_result::SimulationResult;
+use crate::utilities::{add, dot, sub};
 use crate::Node;
 use serde::{Deserialize, Serialize};
 
@@ -12,10 +15,81 @@ pub enum CalculateCollisions {
     No,
 }
 
+/// The settings which control a single walk of the tree, taken from the [[GravTree]] being
+/// stepped.
+pub(crate) struct TreeWalk<'a, F: ForceLaw> {
+    /// The force law entities and nodes exert on each other.
+    pub(crate) law: &'a F,
+    /// See [[GravTree]]'s `theta`.
+    pub(crate) theta: f64,
+    /// The collision groups which are enabled. Only entities in one of these are checked for
+    /// collisions.
+    pub(crate) collision_groups: u32,
+    /// How each species interacts with the others. If this is `None`, every entity interacts with
+    /// every other entity normally.
+    pub(crate) interactions: Option<&'a InteractionMatrix>,
+    /// The side lengths of the periodic domain, if the simulation is periodic.
+    pub(crate) periodic: Option<(f64, f64, f64)>,
+    /// Whether or not to accumulate the jerk and tidal tensor as well as the acceleration.
+    pub(crate) derivatives: bool,
+    /// The index of the walking entity in the slice the tree was built from, if it is one of the
+    /// tree's own entities. It is left out of its own jerk and tidal tensor.
+    pub(crate) origin: Option<usize>,
+}
+
+impl<'a, F: ForceLaw> TreeWalk<'a, F> {
+    /// Returns the displacement from `entity` to `point`. In a periodic domain, this is the
+    /// displacement to the nearest periodic image of `point`.
+    fn displacement(&self, entity: &Entity, point: (f64, f64, f64)) -> (f64, f64, f64) {
+        let (x, y, z) = (point.0 - entity.x, point.1 - entity.y, point.2 - entity.z);
+        match self.periodic {
+            None => (x, y, z),
+            Some((size_x, size_y, size_z)) => (
+                x - size_x * (x / size_x).round(),
+                y - size_y * (y / size_y).round(),
+                z - size_z * (z / size_z).round(),
+            ),
+        }
+    }
+}
+
+/// The jerk and tidal tensor an entity feels, which are accumulated alongside its acceleration
+/// when the walk asks for them.
+#[derive(Default)]
+pub(crate) struct Derivatives {
+    pub(crate) jerk: (f64, f64, f64),
+    pub(crate) tidal_tensor: [[f64; 3]; 3],
+}
+
+impl Derivatives {
+    fn add(&mut self, other: &Derivatives) {
+        self.jerk = add(&self.jerk, &other.jerk);
+        for i in 0..3 {
+            for j in 0..3 {
+                self.tidal_tensor[i][j] += other.tidal_tensor[i][j];
+            }
+        }
+    }
+}
+
 /// An Entity is an object (generalized to be spherical, having only a radius dimension) which has
 /// velocity, position, radius, and mass. This gravitational tree contains many entities and it moves
 /// them around according to the gravity they exert on each other.
-#[derive(Clone, Default)]
+///
+/// Entities may optionally spin. The angular velocity and moment of inertia default to zero, which
+/// describes a body that does not rotate. Use `..Default::default()` when constructing an entity
+/// that does not need them.
+///
+/// Collisions can be filtered with `collision_group` and `collision_mask`. Two entities are only
+/// checked for collision if each one's group shares a bit with the other's mask. By default an
+/// entity is in group `1` and its mask has every bit set, so everything collides with everything.
+///
+/// Every entity also has a `species`, which defaults to `0`. Species are used to look up how
+/// entities interact in the tree's [[InteractionMatrix]], if it has one.
+///
+/// Finally, entities have a `charge`, which is only used by force laws such as
+/// [[crate::force_laws::Coulomb]]. It defaults to zero.
+#[derive(Clone)]
 #[repr(C)]
 pub struct Entity {
     pub vx: f64,
@@ -26,6 +100,46 @@ pub struct Entity {
     pub z: f64,
     pub radius: f64,
     pub mass: f64,
+    /// Angular velocity about the x axis, in radians per unit time.
+    pub wx: f64,
+    /// Angular velocity about the y axis, in radians per unit time.
+    pub wy: f64,
+    /// Angular velocity about the z axis, in radians per unit time.
+    pub wz: f64,
+    /// Moment of inertia about the center of the entity. If this is left as zero, the entity is
+    /// treated as a uniform solid sphere. See [[Entity::inertia]].
+    pub moment_of_inertia: f64,
+    /// The collision groups this entity belongs to, as a bit set.
+    pub collision_group: u32,
+    /// The collision groups this entity collides with, as a bit set.
+    pub collision_mask: u32,
+    /// The species of this entity, as an index into the [[InteractionMatrix]].
+    pub species: usize,
+    /// The electric charge of this entity. This may be negative.
+    pub charge: f64,
+}
+
+impl Default for Entity {
+    fn default() -> Self {
+        Entity {
+            vx: 0.,
+            vy: 0.,
+            vz: 0.,
+            x: 0.,
+            y: 0.,
+            z: 0.,
+            radius: 0.,
+            mass: 0.,
+            wx: 0.,
+            wy: 0.,
+            wz: 0.,
+            moment_of_inertia: 0.,
+            collision_group: 1,
+            collision_mask: u32::MAX,
+            species: 0,
+            charge: 0.,
+        }
+    }
 }
 
 impl AsEntity for Entity {
@@ -49,10 +163,53 @@ impl PartialEq for Entity {
 }
 
 impl Entity {
+    /// Returns whether or not this entity and `other` are allowed to collide, given their collision
+    /// groups and masks and the groups which are currently enabled in the tree. This is cheap and
+    /// is checked before the overlap test.
+    pub fn can_collide_with(&self, other: &Entity, enabled_groups: u32) -> bool {
+        let (self_group, other_group) = (
+            self.collision_group & enabled_groups,
+            other.collision_group & enabled_groups,
+        );
+        self_group & other.collision_mask != 0 && other_group & self.collision_mask != 0
+    }
+
     /// Needs to be reworked to use min/max position values, but it naively checks
     /// if two things collide right now.
-    fn did_collide_into(&self, other: &Entity) -> bool {
-        self != other && self.distance(other) <= (self.radius + other.radius)
+    fn did_collide_into<F: ForceLaw>(&self, other: &Entity, walk: &TreeWalk<F>) -> bool {
+        if self == other {
+            return false;
+        }
+        let d_vector = walk.displacement(self, (other.x, other.y, other.z));
+        let reach = self.radius + other.radius;
+        d_vector.0 * d_vector.0 + d_vector.1 * d_vector.1 + d_vector.2 * d_vector.2 <= reach * reach
+    }
+
+    /// Returns the moment of inertia used for spin calculations. This is `moment_of_inertia` if it
+    /// has been set, otherwise that of a uniform solid sphere, `2/5 * m * r^2`.
+    pub fn inertia(&self) -> f64 {
+        if self.moment_of_inertia > 0. {
+            self.moment_of_inertia
+        } else {
+            0.4 * self.mass * self.radius * self.radius
+        }
+    }
+
+    /// Returns the angular momentum of the entity about its own center.
+    pub fn angular_momentum(&self) -> (f64, f64, f64) {
+        let inertia = self.inertia();
+        (inertia * self.wx, inertia * self.wy, inertia * self.wz)
+    }
+
+    /// Returns the angular velocity after applying `angular_acceleration` for `time_step`.
+    /// This is the spin counterpart to adding linear acceleration to the velocity in
+    /// [[Responsive::respond]]. [[crate::GravTree::set_friction]] makes the tree do this itself.
+    pub fn spin(&self, angular_acceleration: (f64, f64, f64), time_step: f64) -> (f64, f64, f64) {
+        (
+            self.wx + angular_acceleration.0 * time_step,
+            self.wy + angular_acceleration.1 * time_step,
+            self.wz + angular_acceleration.2 * time_step,
+        )
     }
 
     /// Returns the entity as a string with space separated values.
@@ -96,83 +253,221 @@ impl Entity {
     }
 
     /// Returns a boolean representing whether or node the node is within the theta range
-    /// of the entity.
-    fn theta_exceeded<T: AsEntity + Clone>(&self, node: &Node<T>, theta: f64) -> bool {
+    /// of the entity, according to the force law's node approximation rule.
+    fn theta_exceeded<T: AsEntity + Clone, F: ForceLaw>(
+        &self,
+        node: &Node<T>,
+        walk: &TreeWalk<F>,
+    ) -> bool {
         // 1) distance from entity to COM of that node
         // 2) if 1) * theta > size (max diff) then
-        // This frequently makes a node with NaN positions
         let node_as_entity = node.as_entity();
-        let dist = self.distance_squared(&node_as_entity);
+        let d_vector =
+            walk.displacement(self, (node_as_entity.x, node_as_entity.y, node_as_entity.z));
+        let dist = d_vector.0 * d_vector.0 + d_vector.1 * d_vector.1 + d_vector.2 * d_vector.2;
         let max_dist = node.max_distance();
-        (dist) * (theta * theta) > (max_dist * max_dist)
+        walk.law.accept_node(dist, max_dist, walk.theta)
     }
 
     /// Given two entities, self and other, returns the acceleration that other is exerting on
-    /// self. Other can be either an entity or a node.
-    fn get_gravitational_acceleration<T: AsEntity + Clone>(
+    /// self under the walk's force law. Other can be either an entity or a node.
+    fn get_gravitational_acceleration<T: AsEntity + Clone, F: ForceLaw>(
         &self,
         oth: Either<&Entity, &Node<T>>,
+        walk: &TreeWalk<F>,
     ) -> (f64, f64, f64) {
-        // TODO get rid of this clone
-        let other = match oth {
-            Left(entity) => entity.clone(),
-            Right(node) => node.as_entity(),
+        let law = walk.law;
+        let (center, source) = match oth {
+            Left(entity) => ((entity.x, entity.y, entity.z), law.source(entity)),
+            Right(node) => (node.source.center, node.source.strength),
         };
-        let d_magnitude = self.distance(&other);
-        if d_magnitude == 0. {
-            // sort of other use of THETA here
+        if source == 0. {
             return (0., 0., 0.);
         }
-        let d_vector = self.distance_vector(&other);
-        let d_mag_cubed = d_magnitude * d_magnitude; // TODO cube this
-        let d_over_d_cubed = (
-            d_vector.0 / d_mag_cubed,
-            d_vector.1 / d_mag_cubed,
-            d_vector.2 / d_mag_cubed,
-        );
+        let d_vector = walk.displacement(self, center);
+        law.acceleration(self, d_vector, source)
+    }
+
+    /// Returns the acceleration that an entire node exerts on self. Without an interaction matrix,
+    /// this treats the node as one big entity. With one, each species within the node is treated
+    /// as its own source, scaled by how self interacts with it.
+    fn get_node_acceleration<T: AsEntity + Clone, F: ForceLaw>(
+        &self,
+        node: &Node<T>,
+        walk: &TreeWalk<F>,
+    ) -> (f64, f64, f64) {
+        match walk.interactions {
+            None => self.get_gravitational_acceleration(Right(node), walk),
+            Some(interactions) => node.species_sources().iter().fold(
+                (0., 0., 0.),
+                |acceleration, (species, source)| {
+                    let scale = interactions.gravity(self.species, *species);
+                    if scale == 0. {
+                        return acceleration;
+                    }
+                    let d_vector = walk.displacement(self, source.center);
+                    let tmp_accel = walk.law.acceleration(self, d_vector, source.strength);
+                    (
+                        acceleration.0 + tmp_accel.0 * scale,
+                        acceleration.1 + tmp_accel.1 * scale,
+                        acceleration.2 + tmp_accel.2 * scale,
+                    )
+                },
+            ),
+        }
+    }
+
+    /// Returns the jerk and tidal tensor that other exerts on self, scaled by how their species
+    /// interact, if the walk asks for them. The jerk comes from the velocity of self relative to
+    /// other, or to the mean velocity of a node, and the tidal tensor is the derivative of the
+    /// acceleration with respect to the position of self.
+    fn get_derivatives<T: AsEntity + Clone, F: ForceLaw>(
+        &self,
+        oth: Either<&Entity, &Node<T>>,
+        walk: &TreeWalk<F>,
+    ) -> Derivatives {
+        let mut derivatives = Derivatives::default();
+        if !walk.derivatives {
+            return derivatives;
+        }
+        let scale_of = |species: usize| match walk.interactions {
+            None => 1.,
+            Some(interactions) => interactions.gravity(self.species, species),
+        };
+        // (center, velocity, strength, scale) of every source in other
+        let sources = match oth {
+            Left(entity) => vec![(
+                (entity.x, entity.y, entity.z),
+                (entity.vx, entity.vy, entity.vz),
+                walk.law.source(entity),
+                scale_of(entity.species),
+            )],
+            Right(node) => match walk.interactions {
+                None => vec![(
+                    node.source.center,
+                    node.source.velocity,
+                    node.source.strength,
+                    1.,
+                )],
+                Some(_) => node
+                    .species_sources()
+                    .iter()
+                    .map(|(species, source)| {
+                        (
+                            source.center,
+                            source.velocity,
+                            source.strength,
+                            scale_of(*species),
+                        )
+                    })
+                    .collect(),
+            },
+        };
+        for (center, velocity, strength, scale) in sources {
+            if strength == 0. || scale == 0. {
+                continue;
+            }
+            let d_vector = walk.displacement(self, center);
+            let relative_velocity = sub(&velocity, &(self.vx, self.vy, self.vz));
+            let gradient = walk.law.gradient(self, d_vector, strength);
+            let jerk = gradient
+                .iter()
+                .map(|row| dot(&(row[0], row[1], row[2]), &relative_velocity) * scale)
+                .collect::<Vec<_>>();
+            derivatives.jerk = add(&derivatives.jerk, &(jerk[0], jerk[1], jerk[2]));
+            for (tidal_row, gradient_row) in
+                derivatives.tidal_tensor.iter_mut().zip(gradient.iter())
+            {
+                for (tidal, gradient) in tidal_row.iter_mut().zip(gradient_row.iter()) {
+                    // moving self moves the displacement the other way
+                    *tidal -= gradient * scale;
+                }
+            }
+        }
+        derivatives
+    }
+
+    /// Returns the acceleration that a single other entity exerts on self, scaled by how their
+    /// species interact.
+    pub(crate) fn get_entity_acceleration<F: ForceLaw>(
+        &self,
+        other: &Entity,
+        walk: &TreeWalk<F>,
+    ) -> (f64, f64, f64) {
+        let scale = match walk.interactions {
+            None => 1.,
+            Some(interactions) => interactions.gravity(self.species, other.species),
+        };
+        if scale == 0. {
+            return (0., 0., 0.);
+        }
+        let tmp_accel = self.get_gravitational_acceleration::<Entity, F>(Left(other), walk);
         (
-            d_over_d_cubed.0 * other.mass,
-            d_over_d_cubed.1 * other.mass,
-            d_over_d_cubed.2 * other.mass,
+            tmp_accel.0 * scale,
+            tmp_accel.1 * scale,
+            tmp_accel.2 * scale,
         )
     }
 
+    /// Returns whether or not self should be checked for a collision with other, given the enabled
+    /// collision groups and how their species interact.
+    fn should_check_collision<F: ForceLaw>(&self, other: &Entity, walk: &TreeWalk<F>) -> bool {
+        self.can_collide_with(other, walk.collision_groups)
+            && match walk.interactions {
+                None => true,
+                Some(interactions) => interactions.collides(self.species, other.species),
+            }
+    }
+
     /// Returns the acceleration of an entity after it has had gravity from the specified node applied to it.
     /// In this function, we approximate some entities if they exceed a certain critera specified in
     /// "exceeds_theta()". If we reach a node and it is a leaf, then we automatically get the
     /// acceleration from every entity in that node, but if we reach a node that is not a leaf and
     /// exceeds_theta() is true, then we treat the node as one giant entity and get the
     /// acceleration from it.
-    pub(crate) fn get_acceleration_and_collisions<'a, T: AsEntity + Clone>(
+    pub(crate) fn get_acceleration_and_collisions<'a, T: AsEntity + Clone, F: ForceLaw>(
         &'a self,
         node: &'a Node<T>,
-        theta: f64,
-    ) -> SimulationResult<T> {
+        walk: &TreeWalk<F>,
+    ) -> SimulationResult<'a, T> {
         let mut collisions = Vec::new();
         let mut acceleration = (0., 0., 0.);
+        let mut derivatives = Derivatives::default();
         if let Some(node) = &node.left {
             if node.points.is_some() {
                 // if this node has some points, calculate their gravitational acceleration
-                for i in node.points.as_ref().expect("unexpected null node 2") {
-                    if self.did_collide_into(&i.as_entity()) {
+                let points = node.points.as_ref().expect("unexpected null node 2");
+                for (i, index) in points.iter().zip(&node.indices) {
+                    let other = i.as_entity();
+                    if self.should_check_collision(&other, walk)
+                        && self.did_collide_into(&other, walk)
+                    {
                         collisions.push(i);
                     }
-                    let tmp_accel =
-                        self.get_gravitational_acceleration::<Entity>(Left(&(i.as_entity())));
+                    let tmp_accel = self.get_entity_acceleration(&other, walk);
+                    // an entity exerts no tides on itself, even with softening
+                    if walk.origin != Some(*index) {
+                        derivatives.add(&self.get_derivatives::<Entity, F>(Left(&other), walk));
+                    }
                     acceleration.0 += tmp_accel.0;
                     acceleration.1 += tmp_accel.1;
                     acceleration.2 += tmp_accel.2;
                 }
-            } else if self.theta_exceeded(&node, theta) {
+            } else if self.theta_exceeded(node, walk) {
                 // otherwise, if theta is exceeded, calculate the entire node as a big boi particle
-                let tmp_accel = self.get_gravitational_acceleration(Right(&node));
+                let tmp_accel = self.get_node_acceleration(node, walk);
+                derivatives.add(&self.get_derivatives(Right(node), walk));
                 acceleration.0 += tmp_accel.0;
                 acceleration.1 += tmp_accel.1;
                 acceleration.2 += tmp_accel.2;
             } else {
                 // otherwise, theta has not been exceeded and this is not a leaf. recurse
-                let mut res = self.get_acceleration_and_collisions(&node, theta);
+                let mut res = self.get_acceleration_and_collisions(&node, walk);
                 let tmp_accel = res.gravitational_acceleration;
+                derivatives.add(&Derivatives {
+                    jerk: res.jerk,
+                    tidal_tensor: res.tidal_tensor,
+                });
                 collisions.append(&mut res.collisions);
                 acceleration.0 += tmp_accel.0;
                 acceleration.1 += tmp_accel.1;
@@ -182,26 +477,38 @@ impl Entity {
         if let Some(node) = &node.right {
             if node.points.is_some() {
                 // same logic as above
-                for i in node.points.as_ref().expect("unexpected null node 2") {
-                    if self.did_collide_into(&i.as_entity()) {
+                let points = node.points.as_ref().expect("unexpected null node 2");
+                for (i, index) in points.iter().zip(&node.indices) {
+                    let other = i.as_entity();
+                    if self.should_check_collision(&other, walk)
+                        && self.did_collide_into(&other, walk)
+                    {
                         collisions.push(i);
                     }
-                    let tmp_accel =
-                        self.get_gravitational_acceleration::<Entity>(Left(&(i.as_entity())));
+                    let tmp_accel = self.get_entity_acceleration(&other, walk);
+                    // an entity exerts no tides on itself, even with softening
+                    if walk.origin != Some(*index) {
+                        derivatives.add(&self.get_derivatives::<Entity, F>(Left(&other), walk));
+                    }
                     acceleration.0 += tmp_accel.0;
                     acceleration.1 += tmp_accel.1;
                     acceleration.2 += tmp_accel.2;
                 }
-            } else if self.theta_exceeded(&node, theta) {
+            } else if self.theta_exceeded(node, walk) {
                 // otherwise, if theta is exceeded, calculate the entire node as a big boi particle
-                let tmp_accel = self.get_gravitational_acceleration(Right(&node));
+                let tmp_accel = self.get_node_acceleration(node, walk);
+                derivatives.add(&self.get_derivatives(Right(node), walk));
                 acceleration.0 += tmp_accel.0;
                 acceleration.1 += tmp_accel.1;
                 acceleration.2 += tmp_accel.2;
             } else {
                 // otherwise, theta has not been exceeded and this is not a leaf. recurse
-                let mut res = self.get_acceleration_and_collisions(&node, theta);
+                let mut res = self.get_acceleration_and_collisions(&node, walk);
                 let tmp_accel = res.gravitational_acceleration;
+                derivatives.add(&Derivatives {
+                    jerk: res.jerk,
+                    tidal_tensor: res.tidal_tensor,
+                });
                 collisions.append(&mut res.collisions);
                 acceleration.0 += tmp_accel.0;
                 acceleration.1 += tmp_accel.1;
@@ -210,39 +517,49 @@ impl Entity {
         };
         SimulationResult {
             collisions,
-            gravitational_acceleration: (
-                acceleration.0 + acceleration.0,
-                acceleration.1 + acceleration.1,
-                acceleration.2 + acceleration.2,
-            ),
+            gravitational_acceleration: acceleration,
+            external_potential: 0.,
+            jerk: derivatives.jerk,
+            tidal_tensor: derivatives.tidal_tensor,
         }
     }
-    pub(crate) fn get_acceleration_without_collisions<'a, T: AsEntity + Clone>(
+    pub(crate) fn get_acceleration_without_collisions<'a, T: AsEntity + Clone, F: ForceLaw>(
         &'a self,
         node: &'a Node<T>,
-        theta: f64,
-    ) -> SimulationResult<T> {
+        walk: &TreeWalk<F>,
+    ) -> SimulationResult<'a, T> {
         let mut acceleration = (0., 0., 0.);
+        let mut derivatives = Derivatives::default();
         if let Some(node) = &node.left {
             if node.points.is_some() {
                 // if this node has some points, calculate their gravitational acceleration
-                for i in node.points.as_ref().expect("unexpected null node 2") {
-                    let tmp_accel =
-                        self.get_gravitational_acceleration::<Entity>(Left(&(i.as_entity())));
+                let points = node.points.as_ref().expect("unexpected null node 2");
+                for (i, index) in points.iter().zip(&node.indices) {
+                    let other = i.as_entity();
+                    let tmp_accel = self.get_entity_acceleration(&other, walk);
+                    // an entity exerts no tides on itself, even with softening
+                    if walk.origin != Some(*index) {
+                        derivatives.add(&self.get_derivatives::<Entity, F>(Left(&other), walk));
+                    }
                     acceleration.0 += tmp_accel.0;
                     acceleration.1 += tmp_accel.1;
                     acceleration.2 += tmp_accel.2;
                 }
-            } else if self.theta_exceeded(&node, theta) {
+            } else if self.theta_exceeded(node, walk) {
                 // otherwise, if theta is exceeded, calculate the entire node as a big boi particle
-                let tmp_accel = self.get_gravitational_acceleration(Right(&node));
+                let tmp_accel = self.get_node_acceleration(node, walk);
+                derivatives.add(&self.get_derivatives(Right(node), walk));
                 acceleration.0 += tmp_accel.0;
                 acceleration.1 += tmp_accel.1;
                 acceleration.2 += tmp_accel.2;
             } else {
                 // otherwise, theta has not been exceeded and this is not a leaf. recurse
-                let res = self.get_acceleration_without_collisions(&node, theta);
+                let res = self.get_acceleration_without_collisions(&node, walk);
                 let tmp_accel = res.gravitational_acceleration;
+                derivatives.add(&Derivatives {
+                    jerk: res.jerk,
+                    tidal_tensor: res.tidal_tensor,
+                });
                 acceleration.0 += tmp_accel.0;
                 acceleration.1 += tmp_accel.1;
                 acceleration.2 += tmp_accel.2;
@@ -251,23 +568,33 @@ impl Entity {
         if let Some(node) = &node.right {
             if node.points.is_some() {
                 // same logic as above
-                for i in node.points.as_ref().expect("unexpected null node 2") {
-                    let tmp_accel =
-                        self.get_gravitational_acceleration::<Entity>(Left(&(i.as_entity())));
+                let points = node.points.as_ref().expect("unexpected null node 2");
+                for (i, index) in points.iter().zip(&node.indices) {
+                    let other = i.as_entity();
+                    let tmp_accel = self.get_entity_acceleration(&other, walk);
+                    // an entity exerts no tides on itself, even with softening
+                    if walk.origin != Some(*index) {
+                        derivatives.add(&self.get_derivatives::<Entity, F>(Left(&other), walk));
+                    }
                     acceleration.0 += tmp_accel.0;
                     acceleration.1 += tmp_accel.1;
                     acceleration.2 += tmp_accel.2;
                 }
-            } else if self.theta_exceeded(&node, theta) {
+            } else if self.theta_exceeded(node, walk) {
                 // otherwise, if theta is exceeded, calculate the entire node as a big boi particle
-                let tmp_accel = self.get_gravitational_acceleration(Right(&node));
+                let tmp_accel = self.get_node_acceleration(node, walk);
+                derivatives.add(&self.get_derivatives(Right(node), walk));
                 acceleration.0 += tmp_accel.0;
                 acceleration.1 += tmp_accel.1;
                 acceleration.2 += tmp_accel.2;
             } else {
                 // otherwise, theta has not been exceeded and this is not a leaf. recurse
-                let res = self.get_acceleration_without_collisions(&node, theta);
+                let res = self.get_acceleration_without_collisions(&node, walk);
                 let tmp_accel = res.gravitational_acceleration;
+                derivatives.add(&Derivatives {
+                    jerk: res.jerk,
+                    tidal_tensor: res.tidal_tensor,
+                });
                 acceleration.0 += tmp_accel.0;
                 acceleration.1 += tmp_accel.1;
                 acceleration.2 += tmp_accel.2;
@@ -275,11 +602,10 @@ impl Entity {
         };
         SimulationResult {
             collisions: vec![],
-            gravitational_acceleration: (
-                acceleration.0 + acceleration.0,
-                acceleration.1 + acceleration.1,
-                acceleration.2 + acceleration.2,
-            ),
+            gravitational_acceleration: acceleration,
+            external_potential: 0.,
+            jerk: derivatives.jerk,
+            tidal_tensor: derivatives.tidal_tensor,
         }
     }
 }
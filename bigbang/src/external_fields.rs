@@ -0,0 +1,245 @@
+//! This module contains external potential fields, which add a fixed background to the gravity
+//! of the tree, such as a central star, a galactic disk or a dark matter halo.
+//! Fields are added to a tree with [[GravTree::add_external_field]], after which their acceleration
+//! is summed into every entity's `gravitational_acceleration` and their potential into its
+//! `external_potential`.
+//!
+//! All of the built-in fields are in simulation units where the gravitational constant is `1`, and
+//! are centered on the origin unless they say otherwise. Use [[Moving]] to move a field around.
+
+use crate::utilities::{add, dot, scale, sub};
+use std::sync::Arc;
+
+/// A static or time-dependent background potential. Implement this for your own fields.
+pub trait ExternalField: Send + Sync {
+    /// Returns the acceleration the field exerts at `position` at simulation time `time`.
+    fn acceleration(&self, position: (f64, f64, f64), time: f64) -> (f64, f64, f64);
+
+    /// Returns the potential (energy per unit mass) of the field at `position` at simulation time
+    /// `time`.
+    fn potential(&self, position: (f64, f64, f64), time: f64) -> f64;
+}
+
+/// The field of a single point mass, such as a central star, optionally softened by `softening`
+/// to avoid the singularity at its center.
+pub struct PointMass {
+    pub mass: f64,
+    pub position: (f64, f64, f64),
+    pub softening: f64,
+}
+
+impl ExternalField for PointMass {
+    fn acceleration(&self, position: (f64, f64, f64), _time: f64) -> (f64, f64, f64) {
+        let d = sub(&position, &self.position);
+        let r_squared = dot(&d, &d) + self.softening * self.softening;
+        if r_squared == 0. {
+            return (0., 0., 0.);
+        }
+        scale(&d, -self.mass / (r_squared * r_squared.sqrt()))
+    }
+
+    fn potential(&self, position: (f64, f64, f64), _time: f64) -> f64 {
+        let d = sub(&position, &self.position);
+        let r_squared = dot(&d, &d) + self.softening * self.softening;
+        if r_squared == 0. {
+            return 0.;
+        }
+        -self.mass / r_squared.sqrt()
+    }
+}
+
+/// A uniform gravitational field, such as that near the surface of a planet.
+pub struct UniformField {
+    pub acceleration: (f64, f64, f64),
+}
+
+impl ExternalField for UniformField {
+    fn acceleration(&self, _position: (f64, f64, f64), _time: f64) -> (f64, f64, f64) {
+        self.acceleration
+    }
+
+    fn potential(&self, position: (f64, f64, f64), _time: f64) -> f64 {
+        -dot(&self.acceleration, &position)
+    }
+}
+
+/// The [Miyamoto-Nagai](https://en.wikipedia.org/wiki/Miyamoto%E2%80%93Nagai_potential) potential
+/// of an axisymmetric disk in the x-y plane, with radial scale length `a` and vertical scale height
+/// `b`.
+pub struct MiyamotoNagai {
+    pub mass: f64,
+    pub a: f64,
+    pub b: f64,
+}
+
+impl ExternalField for MiyamotoNagai {
+    fn acceleration(&self, (x, y, z): (f64, f64, f64), _time: f64) -> (f64, f64, f64) {
+        let zeta = (z * z + self.b * self.b).sqrt();
+        let d_squared = x * x + y * y + (self.a + zeta) * (self.a + zeta);
+        let d_cubed = d_squared * d_squared.sqrt();
+        let radial = -self.mass / d_cubed;
+        let vertical = if zeta == 0. {
+            0.
+        } else {
+            radial * (self.a + zeta) / zeta
+        };
+        (radial * x, radial * y, vertical * z)
+    }
+
+    fn potential(&self, (x, y, z): (f64, f64, f64), _time: f64) -> f64 {
+        let zeta = (z * z + self.b * self.b).sqrt();
+        -self.mass / (x * x + y * y + (self.a + zeta) * (self.a + zeta)).sqrt()
+    }
+}
+
+/// The [Hernquist](https://en.wikipedia.org/wiki/Hernquist_profile) potential of a spherical
+/// bulge or halo with total mass `mass` and scale radius `scale_radius`.
+pub struct Hernquist {
+    pub mass: f64,
+    pub scale_radius: f64,
+}
+
+impl ExternalField for Hernquist {
+    fn acceleration(&self, position: (f64, f64, f64), _time: f64) -> (f64, f64, f64) {
+        let r = dot(&position, &position).sqrt();
+        if r == 0. {
+            return (0., 0., 0.);
+        }
+        let denominator = r + self.scale_radius;
+        scale(&position, -self.mass / (denominator * denominator * r))
+    }
+
+    fn potential(&self, position: (f64, f64, f64), _time: f64) -> f64 {
+        -self.mass / (dot(&position, &position).sqrt() + self.scale_radius)
+    }
+}
+
+/// The [Navarro-Frenk-White](https://en.wikipedia.org/wiki/Navarro%E2%80%93Frenk%E2%80%93White_profile)
+/// potential of a dark matter halo. `mass` is the characteristic mass `4 pi rho_0 r_s^3` rather than
+/// a total mass, since the total mass of an NFW halo diverges.
+pub struct Nfw {
+    pub mass: f64,
+    pub scale_radius: f64,
+}
+
+impl ExternalField for Nfw {
+    fn acceleration(&self, position: (f64, f64, f64), _time: f64) -> (f64, f64, f64) {
+        let r = dot(&position, &position).sqrt();
+        if r == 0. {
+            return (0., 0., 0.);
+        }
+        let x = r / self.scale_radius;
+        let enclosed_mass = self.mass * ((1. + x).ln() - x / (1. + x));
+        scale(&position, -enclosed_mass / (r * r * r))
+    }
+
+    fn potential(&self, position: (f64, f64, f64), _time: f64) -> f64 {
+        let r = dot(&position, &position).sqrt();
+        if r == 0. {
+            return -self.mass / self.scale_radius;
+        }
+        -self.mass * (1. + r / self.scale_radius).ln() / r
+    }
+}
+
+/// A [logarithmic potential](https://en.wikipedia.org/wiki/Logarithmic_potential), which has a
+/// flat rotation curve of circular velocity `v0` outside of `core_radius`. `flattening` is the
+/// axis ratio `q` of the potential along z; use `1.` for a spherical potential.
+pub struct Logarithmic {
+    pub v0: f64,
+    pub core_radius: f64,
+    pub flattening: f64,
+}
+
+impl Logarithmic {
+    fn radius_squared(&self, (x, y, z): (f64, f64, f64)) -> f64 {
+        let q = self.flattening;
+        self.core_radius * self.core_radius + x * x + y * y + z * z / (q * q)
+    }
+}
+
+impl ExternalField for Logarithmic {
+    fn acceleration(&self, position: (f64, f64, f64), _time: f64) -> (f64, f64, f64) {
+        let (x, y, z) = position;
+        let factor = -self.v0 * self.v0 / self.radius_squared(position);
+        let q = self.flattening;
+        (factor * x, factor * y, factor * z / (q * q))
+    }
+
+    fn potential(&self, position: (f64, f64, f64), _time: f64) -> f64 {
+        0.5 * self.v0 * self.v0 * self.radius_squared(position).ln()
+    }
+}
+
+/// Several fields added together and treated as one, e.g. a galaxy made of a disk, a bulge and a
+/// halo.
+#[derive(Default)]
+pub struct CompositeField {
+    fields: Vec<Arc<dyn ExternalField>>,
+}
+
+impl CompositeField {
+    pub fn new() -> CompositeField {
+        CompositeField { fields: Vec::new() }
+    }
+
+    /// Adds a field to the composition.
+    pub fn with<F: ExternalField + 'static>(mut self, field: F) -> CompositeField {
+        self.fields.push(Arc::new(field));
+        self
+    }
+}
+
+impl ExternalField for CompositeField {
+    fn acceleration(&self, position: (f64, f64, f64), time: f64) -> (f64, f64, f64) {
+        self.fields.iter().fold((0., 0., 0.), |acc, field| {
+            add(&acc, &field.acceleration(position, time))
+        })
+    }
+
+    fn potential(&self, position: (f64, f64, f64), time: f64) -> f64 {
+        self.fields
+            .iter()
+            .map(|field| field.potential(position, time))
+            .sum()
+    }
+}
+
+/// Scales the strength of a field over time, e.g. to grow a potential adiabatically. `amplitude`
+/// is given the simulation time and returns the factor to scale the field by.
+pub struct TimeScaled<F: ExternalField> {
+    pub field: F,
+    pub amplitude: Box<dyn Fn(f64) -> f64 + Send + Sync>,
+}
+
+impl<F: ExternalField> ExternalField for TimeScaled<F> {
+    fn acceleration(&self, position: (f64, f64, f64), time: f64) -> (f64, f64, f64) {
+        scale(
+            &self.field.acceleration(position, time),
+            (self.amplitude)(time),
+        )
+    }
+
+    fn potential(&self, position: (f64, f64, f64), time: f64) -> f64 {
+        self.field.potential(position, time) * (self.amplitude)(time)
+    }
+}
+
+/// Moves the center of a field over time, e.g. a perturber on a fixed orbit. `center` is given
+/// the simulation time and returns where the field's origin is at that time.
+pub struct Moving<F: ExternalField> {
+    pub field: F,
+    pub center: Box<dyn Fn(f64) -> (f64, f64, f64) + Send + Sync>,
+}
+
+impl<F: ExternalField> ExternalField for Moving<F> {
+    fn acceleration(&self, position: (f64, f64, f64), time: f64) -> (f64, f64, f64) {
+        let relative = sub(&position, &(self.center)(time));
+        self.field.acceleration(relative, time)
+    }
+
+    fn potential(&self, position: (f64, f64, f64), time: f64) -> f64 {
+        let relative = sub(&position, &(self.center)(time));
+        self.field.potential(relative, time)
+    }
+}
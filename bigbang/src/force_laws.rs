@@ -0,0 +1,233 @@
+//! This module contains the pairwise force laws which the tree can be built around. A
+//! [[GravTree]] uses [[Newtonian]] gravity unless it is constructed with
+//! [[GravTree::with_force_law]].
+//!
+//! A force law has two parts: the pairwise kernel, which is the acceleration a point source exerts
+//! on an entity, and the rule deciding when a whole node of the tree is far enough away to be
+//! approximated as a single source. Nodes are approximated as a point source whose strength is
+//! the sum of the strengths of the entities under it, e.g. their total mass for gravity or their
+//! net charge for electrostatics.
+
+use crate::entity::Entity;
+use crate::utilities::{dot, scale, sub};
+use serde::{Deserialize, Serialize};
+
+/// A pairwise interaction between entities. Implement this to simulate your own interactions.
+pub trait ForceLaw: Clone + Send + Sync {
+    /// Returns the strength with which `entity` exerts this force, e.g. its mass for gravity. This
+    /// may be negative, in which case nodes keep track of the signed total.
+    fn source(&self, entity: &Entity) -> f64;
+
+    /// Returns the acceleration felt by `target` from a point source of strength `source`, which
+    /// is `displacement` away from it (i.e. `displacement` points from `target` to the source).
+    fn acceleration(
+        &self,
+        target: &Entity,
+        displacement: (f64, f64, f64),
+        source: f64,
+    ) -> (f64, f64, f64);
+
+    /// Returns whether a node of width `node_size`, whose center is `distance_squared` away
+    /// (squared), may be approximated as a single source. By default this is the Barnes-Hut
+    /// criterion: the node's width must be less than `theta` times its distance.
+    fn accept_node(&self, distance_squared: f64, node_size: f64, theta: f64) -> bool {
+        distance_squared * (theta * theta) > node_size * node_size
+    }
+
+    /// Returns the derivative of [[ForceLaw::acceleration]] with respect to `displacement`, where
+    /// `gradient[i][j]` is the derivative of the `i`th component of the acceleration by the `j`th
+    /// component of the displacement. This is used for the jerk and the tidal tensor. By default
+    /// it is calculated with central differences, but laws with a closed form should override it.
+    fn gradient(
+        &self,
+        target: &Entity,
+        displacement: (f64, f64, f64),
+        source: f64,
+    ) -> [[f64; 3]; 3] {
+        let step = 1e-6 * dot(&displacement, &displacement).sqrt().max(1e-12);
+        let mut gradient = [[0.; 3]; 3];
+        for j in 0..3 {
+            let offset = |sign: f64| {
+                let mut shifted = [displacement.0, displacement.1, displacement.2];
+                shifted[j] += sign * step;
+                self.acceleration(target, (shifted[0], shifted[1], shifted[2]), source)
+            };
+            let difference = sub(&offset(1.), &offset(-1.));
+            gradient[0][j] = difference.0 / (2. * step);
+            gradient[1][j] = difference.1 / (2. * step);
+            gradient[2][j] = difference.2 / (2. * step);
+        }
+        gradient
+    }
+}
+
+/// Returns `displacement / (r^2 + softening^2)^(3/2)`, or zero if the two points coincide.
+fn inverse_square(displacement: (f64, f64, f64), softening: f64) -> (f64, f64, f64) {
+    let r_squared = dot(&displacement, &displacement) + softening * softening;
+    if r_squared == 0. {
+        return (0., 0., 0.);
+    }
+    scale(&displacement, 1. / (r_squared * r_squared.sqrt()))
+}
+
+/// Newtonian gravity, `a = G m / r^2`, optionally with
+/// [Plummer softening](https://en.wikipedia.org/wiki/Softening).
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Newtonian {
+    /// The gravitational constant in simulation units.
+    pub g: f64,
+    pub softening: f64,
+}
+
+impl Default for Newtonian {
+    fn default() -> Self {
+        Newtonian {
+            g: 1.,
+            softening: 0.,
+        }
+    }
+}
+
+impl ForceLaw for Newtonian {
+    fn source(&self, entity: &Entity) -> f64 {
+        entity.mass
+    }
+
+    fn acceleration(
+        &self,
+        _target: &Entity,
+        displacement: (f64, f64, f64),
+        source: f64,
+    ) -> (f64, f64, f64) {
+        scale(
+            &inverse_square(displacement, self.softening),
+            self.g * source,
+        )
+    }
+
+    fn gradient(
+        &self,
+        _target: &Entity,
+        displacement: (f64, f64, f64),
+        source: f64,
+    ) -> [[f64; 3]; 3] {
+        // G m (I / q^(3/2) - 3 d d^T / q^(5/2)), where q = r^2 + softening^2
+        let q = dot(&displacement, &displacement) + self.softening * self.softening;
+        if q == 0. {
+            return [[0.; 3]; 3];
+        }
+        let d = [displacement.0, displacement.1, displacement.2];
+        let (inverse_q3, inverse_q5) = (1. / (q * q.sqrt()), 1. / (q * q * q.sqrt()));
+        let mut gradient = [[0.; 3]; 3];
+        for (i, row) in gradient.iter_mut().enumerate() {
+            for (j, entry) in row.iter_mut().enumerate() {
+                let identity = if i == j { inverse_q3 } else { 0. };
+                *entry = self.g * source * (identity - 3. * d[i] * d[j] * inverse_q5);
+            }
+        }
+        gradient
+    }
+}
+
+/// The electrostatic force between charged entities, `F = k q1 q2 / r^2`, using each entity's
+/// `charge`. Like charges repel and opposite charges attract. Massless entities feel no
+/// acceleration, since they would accelerate infinitely.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Coulomb {
+    /// Coulomb's constant in simulation units.
+    pub k: f64,
+    pub softening: f64,
+}
+
+impl ForceLaw for Coulomb {
+    fn source(&self, entity: &Entity) -> f64 {
+        entity.charge
+    }
+
+    fn acceleration(
+        &self,
+        target: &Entity,
+        displacement: (f64, f64, f64),
+        source: f64,
+    ) -> (f64, f64, f64) {
+        if target.mass == 0. {
+            return (0., 0., 0.);
+        }
+        scale(
+            &inverse_square(displacement, self.softening),
+            -self.k * target.charge * source / target.mass,
+        )
+    }
+}
+
+/// A [Yukawa](https://en.wikipedia.org/wiki/Yukawa_potential) or screened Coulomb (Debye-Hückel)
+/// interaction between charged entities, whose potential is `k q1 q2 exp(-r / screening_length) / r`.
+/// It behaves like [[Coulomb]] at short range and falls off exponentially beyond the screening
+/// length, so nodes more than a few screening lengths away exert almost nothing.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Yukawa {
+    pub k: f64,
+    pub screening_length: f64,
+    pub softening: f64,
+}
+
+impl ForceLaw for Yukawa {
+    fn source(&self, entity: &Entity) -> f64 {
+        entity.charge
+    }
+
+    fn acceleration(
+        &self,
+        target: &Entity,
+        displacement: (f64, f64, f64),
+        source: f64,
+    ) -> (f64, f64, f64) {
+        if target.mass == 0. {
+            return (0., 0., 0.);
+        }
+        let r = (dot(&displacement, &displacement) + self.softening * self.softening).sqrt();
+        let screening = (-r / self.screening_length).exp() * (1. + r / self.screening_length);
+        scale(
+            &inverse_square(displacement, self.softening),
+            -self.k * target.charge * source * screening / target.mass,
+        )
+    }
+}
+
+/// A [MOND](https://en.wikipedia.org/wiki/Modified_Newtonian_dynamics)-like modification of
+/// gravity. The Newtonian acceleration `a_N` is boosted by the "simple" interpolating function,
+/// `a = a_N (1/2 + sqrt(1/4 + a0 / |a_N|))`, so that it is Newtonian well above `a0` and tends to
+/// `sqrt(a0 a_N)` well below it.
+///
+/// Note that MOND is not linear, so applying it to each pair (and node) separately, as the tree
+/// does, is an approximation of the full theory.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Mond {
+    pub g: f64,
+    /// The acceleration scale below which gravity departs from Newtonian.
+    pub a0: f64,
+    pub softening: f64,
+}
+
+impl ForceLaw for Mond {
+    fn source(&self, entity: &Entity) -> f64 {
+        entity.mass
+    }
+
+    fn acceleration(
+        &self,
+        _target: &Entity,
+        displacement: (f64, f64, f64),
+        source: f64,
+    ) -> (f64, f64, f64) {
+        let newtonian = scale(
+            &inverse_square(displacement, self.softening),
+            self.g * source,
+        );
+        let magnitude = dot(&newtonian, &newtonian).sqrt();
+        if magnitude == 0. {
+            return newtonian;
+        }
+        scale(&newtonian, 0.5 + (0.25 + self.a0 / magnitude).sqrt())
+    }
+}
@@ -0,0 +1,653 @@
+//! This module reads and writes the binary snapshots of
+//! [GADGET-2](https://wwwmpa.mpa-garching.mpg.de/gadget/), in both of its formats. In format 1, a
+//! snapshot is a sequence of Fortran records: the 256 byte header, then the positions, velocities,
+//! IDs and, for the particle types which have no mass in the header's mass table, the masses. In
+//! format 2, each of these blocks is preceded by a small record holding its four character label.
+//!
+//! The byte order and whether the positions, velocities and masses are single or double precision
+//! and the IDs are 32 or 64 bit are detected when reading. Snapshots are always written in little
+//! endian, with single precision floats, and with 32 bit IDs unless an ID does not fit. Any blocks
+//! after the masses, such as the internal energy of gas particles, are ignored, as are the other
+//! files of a snapshot which is split across several files.
+//!
+//! Velocities are stored as they are in the file. Cosmological GADGET snapshots store the peculiar
+//! velocity divided by the square root of the scale factor, so [[Snapshot::from_tree]] divides the
+//! velocities of a comoving tree by it, and [[Snapshot::comoving_particles]] multiplies them back.
+
+use crate::boundaries::Boundary;
+use crate::force_laws::ForceLaw;
+use crate::{AsEntity, Entity, GravTree, Responsive};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The number of particle types in a GADGET snapshot.
+pub const PARTICLE_TYPES: usize = 6;
+
+const HEADER_SIZE: usize = 256;
+
+/// Which of GADGET's two binary formats a snapshot is in.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GadgetFormat {
+    /// Plain Fortran records, in a fixed order.
+    One,
+    /// Fortran records, each preceded by a record holding its label.
+    Two,
+}
+
+/// The ways in which reading or writing a snapshot can fail.
+#[derive(Debug)]
+pub enum GadgetError {
+    /// The snapshot could not be read or written.
+    Io(io::Error),
+    /// The snapshot is not laid out as a GADGET snapshot should be.
+    Malformed(String),
+    /// A particle has a type, or an entity a species, which is not one of GADGET's six types.
+    UnsupportedType(usize),
+    /// Something cannot be represented in a GADGET snapshot, such as a block of more than 4 GiB
+    /// or a periodic domain which is not a cube with a corner at the origin.
+    Unrepresentable(String),
+}
+
+impl fmt::Display for GadgetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GadgetError::Io(error) => write!(f, "could not access the snapshot: {}", error),
+            GadgetError::Malformed(reason) => write!(f, "the snapshot is malformed: {}", reason),
+            GadgetError::UnsupportedType(particle_type) => write!(
+                f,
+                "GADGET only has particle types 0 to {}, not {}",
+                PARTICLE_TYPES - 1,
+                particle_type
+            ),
+            GadgetError::Unrepresentable(reason) => {
+                write!(f, "GADGET cannot represent {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GadgetError {}
+
+impl From<io::Error> for GadgetError {
+    fn from(error: io::Error) -> Self {
+        GadgetError::Io(error)
+    }
+}
+
+/// The header of a snapshot, apart from the particle counts and mass table, which are worked out
+/// from the particles.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct GadgetHeader {
+    /// The time of the snapshot, which is the scale factor in cosmological simulations.
+    pub time: f64,
+    pub redshift: f64,
+    /// The side length of the periodic box, or zero if the simulation is not periodic.
+    pub box_size: f64,
+    /// The matter density parameter, `Ωm`.
+    pub omega0: f64,
+    /// The cosmological constant density parameter, `ΩΛ`.
+    pub omega_lambda: f64,
+    /// The Hubble constant in units of 100 km/s/Mpc.
+    pub hubble_param: f64,
+    /// The number of files the snapshot is split across.
+    pub num_files: i32,
+    /// The number of particles of each type in all of the files of the snapshot. This is only
+    /// written if the snapshot is split across several files; otherwise it is the number of
+    /// particles of each type.
+    pub npart_total: [u64; PARTICLE_TYPES],
+    pub flag_sfr: i32,
+    pub flag_feedback: i32,
+    pub flag_cooling: i32,
+    pub flag_stellar_age: i32,
+    pub flag_metals: i32,
+    pub flag_entropy_instead_u: i32,
+}
+
+/// A single particle of a snapshot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GadgetParticle {
+    /// The GADGET type of the particle, from 0 to 5. This becomes the [[Entity]]'s species.
+    pub particle_type: usize,
+    pub id: u64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub vx: f64,
+    pub vy: f64,
+    pub vz: f64,
+    pub mass: f64,
+}
+
+impl AsEntity for GadgetParticle {
+    fn as_entity(&self) -> Entity {
+        Entity {
+            x: self.x,
+            y: self.y,
+            z: self.z,
+            vx: self.vx,
+            vy: self.vy,
+            vz: self.vz,
+            mass: self.mass,
+            species: self.particle_type,
+            ..Default::default()
+        }
+    }
+}
+
+/// A GADGET snapshot. The particles may be in any order; they are written sorted by type.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub header: GadgetHeader,
+    pub particles: Vec<GadgetParticle>,
+}
+
+impl Snapshot {
+    /// Reads a snapshot in either format from `path`.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Snapshot, GadgetError> {
+        Snapshot::from_bytes(&fs::read(path)?)
+    }
+
+    /// Reads a snapshot in either format from the contents of a snapshot file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, GadgetError> {
+        let mut reader = Reader::new(bytes)?;
+        let mut blocks = Vec::new();
+        match reader.format {
+            GadgetFormat::One => {
+                let header = reader.record()?;
+                let (_, npart, mass_table) = parse_header(header, reader.endian)?;
+                blocks.push((*b"HEAD", header));
+                for label in &[b"POS ", b"VEL ", b"ID  "] {
+                    blocks.push((**label, reader.record()?));
+                }
+                if masses_needed(&npart, &mass_table) > 0 {
+                    blocks.push((*b"MASS", reader.record()?));
+                }
+            }
+            GadgetFormat::Two => {
+                while !reader.at_end() {
+                    let label = reader.record()?;
+                    if label.len() != 8 {
+                        return Err(GadgetError::Malformed(format!(
+                            "a block label record has {} bytes instead of 8",
+                            label.len()
+                        )));
+                    }
+                    let mut name = [0; 4];
+                    name.copy_from_slice(&label[..4]);
+                    blocks.push((name, reader.record()?));
+                }
+            }
+        }
+        let block = |name: &[u8; 4]| {
+            blocks
+                .iter()
+                .find(|(label, _)| label == name)
+                .map(|(_, data)| *data)
+                .ok_or_else(|| {
+                    GadgetError::Malformed(format!(
+                        "there is no {} block",
+                        String::from_utf8_lossy(name).trim()
+                    ))
+                })
+        };
+
+        let endian = reader.endian;
+        let (header, npart, mass_table) = parse_header(block(b"HEAD")?, endian)?;
+        let count = npart.iter().sum::<usize>();
+        let positions = floats(block(b"POS ")?, 3 * count, endian, "position")?;
+        let velocities = floats(block(b"VEL ")?, 3 * count, endian, "velocity")?;
+        let ids = integers(block(b"ID  ")?, count, endian)?;
+        let mass_count = masses_needed(&npart, &mass_table);
+        let masses = if mass_count > 0 {
+            floats(block(b"MASS")?, mass_count, endian, "mass")?
+        } else {
+            Vec::new()
+        };
+
+        let mut particles = Vec::with_capacity(count);
+        let mut masses = masses.into_iter();
+        for (particle_type, number) in npart.iter().enumerate() {
+            for _ in 0..*number {
+                let i = particles.len();
+                let mass = if mass_table[particle_type] == 0. {
+                    masses
+                        .next()
+                        .expect("the mass block has one mass per particle")
+                } else {
+                    mass_table[particle_type]
+                };
+                particles.push(GadgetParticle {
+                    particle_type,
+                    id: ids[i],
+                    x: positions[3 * i],
+                    y: positions[3 * i + 1],
+                    z: positions[3 * i + 2],
+                    vx: velocities[3 * i],
+                    vy: velocities[3 * i + 1],
+                    vz: velocities[3 * i + 2],
+                    mass,
+                });
+            }
+        }
+        Ok(Snapshot { header, particles })
+    }
+
+    /// Returns the particles of a cosmological snapshot with the peculiar velocities that a
+    /// comoving tree integrates, taking the snapshot's time as the scale factor.
+    pub fn comoving_particles(&self) -> Vec<GadgetParticle> {
+        let root_a = self.header.time.sqrt();
+        self.particles
+            .iter()
+            .map(|particle| GadgetParticle {
+                vx: particle.vx * root_a,
+                vy: particle.vy * root_a,
+                vz: particle.vz * root_a,
+                ..particle.clone()
+            })
+            .collect()
+    }
+
+    /// Writes the snapshot to `path` in `format`.
+    pub fn write<P: AsRef<Path>>(&self, path: P, format: GadgetFormat) -> Result<(), GadgetError> {
+        fs::write(path, self.to_bytes(format)?)?;
+        Ok(())
+    }
+
+    /// Returns the contents of a snapshot file in `format`.
+    pub fn to_bytes(&self, format: GadgetFormat) -> Result<Vec<u8>, GadgetError> {
+        let mut particles = self.particles.iter().collect::<Vec<_>>();
+        if let Some(particle) = particles
+            .iter()
+            .find(|particle| particle.particle_type >= PARTICLE_TYPES)
+        {
+            return Err(GadgetError::UnsupportedType(particle.particle_type));
+        }
+        particles.sort_by_key(|particle| particle.particle_type);
+
+        let mut npart = [0; PARTICLE_TYPES];
+        for particle in &particles {
+            npart[particle.particle_type] += 1;
+        }
+        // types whose particles all have the same mass go in the mass table
+        let mut mass_table = [0.; PARTICLE_TYPES];
+        for (particle_type, mass) in mass_table.iter_mut().enumerate() {
+            let mut of_type = particles
+                .iter()
+                .filter(|particle| particle.particle_type == particle_type);
+            if let Some(first) = of_type.next() {
+                if of_type.all(|particle| particle.mass == first.mass) {
+                    *mass = first.mass;
+                }
+            }
+        }
+
+        let mut out = Vec::new();
+        let mut block = |label: &[u8; 4], data: Vec<u8>| {
+            if format == GadgetFormat::Two {
+                let mut label_record = label.to_vec();
+                label_record.extend_from_slice(&(record_length(data.len() + 8)?).to_le_bytes());
+                write_record(&mut out, &label_record)?;
+            }
+            write_record(&mut out, &data)
+        };
+        block(b"HEAD", self.header_bytes(&npart, &mass_table)?)?;
+        let mut positions = Vec::with_capacity(12 * particles.len());
+        let mut velocities = Vec::with_capacity(12 * particles.len());
+        for particle in &particles {
+            for value in &[particle.x, particle.y, particle.z] {
+                positions.extend_from_slice(&(*value as f32).to_le_bytes());
+            }
+            for value in &[particle.vx, particle.vy, particle.vz] {
+                velocities.extend_from_slice(&(*value as f32).to_le_bytes());
+            }
+        }
+        block(b"POS ", positions)?;
+        block(b"VEL ", velocities)?;
+        let long_ids = particles
+            .iter()
+            .any(|particle| particle.id > u64::from(u32::MAX));
+        let mut ids = Vec::new();
+        for particle in &particles {
+            if long_ids {
+                ids.extend_from_slice(&particle.id.to_le_bytes());
+            } else {
+                ids.extend_from_slice(&(particle.id as u32).to_le_bytes());
+            }
+        }
+        block(b"ID  ", ids)?;
+        if masses_needed(&npart, &mass_table) > 0 {
+            let mut masses = Vec::new();
+            for particle in &particles {
+                if mass_table[particle.particle_type] == 0. {
+                    masses.extend_from_slice(&(particle.mass as f32).to_le_bytes());
+                }
+            }
+            block(b"MASS", masses)?;
+        }
+        Ok(out)
+    }
+
+    /// Returns the 256 byte header block for particles of each type numbering `npart`.
+    fn header_bytes(
+        &self,
+        npart: &[usize; PARTICLE_TYPES],
+        mass_table: &[f64; PARTICLE_TYPES],
+    ) -> Result<Vec<u8>, GadgetError> {
+        let header = &self.header;
+        let npart_total = if header.num_files > 1 {
+            header.npart_total
+        } else {
+            let mut npart_total = [0; PARTICLE_TYPES];
+            for (total, number) in npart_total.iter_mut().zip(npart.iter()) {
+                *total = *number as u64;
+            }
+            npart_total
+        };
+        let mut out = Vec::with_capacity(HEADER_SIZE);
+        for number in npart {
+            let number = u32::try_from(*number).map_err(|_| {
+                GadgetError::Unrepresentable(format!("{} particles of one type", number))
+            })?;
+            out.extend_from_slice(&number.to_le_bytes());
+        }
+        for mass in mass_table {
+            out.extend_from_slice(&mass.to_le_bytes());
+        }
+        out.extend_from_slice(&header.time.to_le_bytes());
+        out.extend_from_slice(&header.redshift.to_le_bytes());
+        out.extend_from_slice(&header.flag_sfr.to_le_bytes());
+        out.extend_from_slice(&header.flag_feedback.to_le_bytes());
+        for total in &npart_total {
+            out.extend_from_slice(&(*total as u32).to_le_bytes());
+        }
+        out.extend_from_slice(&header.flag_cooling.to_le_bytes());
+        out.extend_from_slice(&header.num_files.max(1).to_le_bytes());
+        out.extend_from_slice(&header.box_size.to_le_bytes());
+        out.extend_from_slice(&header.omega0.to_le_bytes());
+        out.extend_from_slice(&header.omega_lambda.to_le_bytes());
+        out.extend_from_slice(&header.hubble_param.to_le_bytes());
+        out.extend_from_slice(&header.flag_stellar_age.to_le_bytes());
+        out.extend_from_slice(&header.flag_metals.to_le_bytes());
+        for total in &npart_total {
+            out.extend_from_slice(&((*total >> 32) as u32).to_le_bytes());
+        }
+        out.extend_from_slice(&header.flag_entropy_instead_u.to_le_bytes());
+        out.resize(HEADER_SIZE, 0);
+        Ok(out)
+    }
+
+    /// Returns a snapshot of the entities in `tree`. Each entity's species becomes its particle
+    /// type, and the entities are given IDs counting up from one. Test particles are left out.
+    ///
+    /// The time is the scale factor if the tree is comoving, in which case the redshift and
+    /// density parameters are filled in too and the velocities are divided by the square root of
+    /// the scale factor, as GADGET expects. The box size is filled in if the tree has a periodic
+    /// boundary. The Hubble parameter is left at zero, since the tree's Hubble constant
+    /// is in the simulation's own units.
+    ///
+    /// GADGET's periodic box is a cube from the origin to `box_size`, so a tree which is periodic
+    /// in any other domain gives [[GadgetError::Unrepresentable]].
+    pub fn from_tree<T, F>(tree: &GravTree<T, F>) -> Result<Snapshot, GadgetError>
+    where
+        T: AsEntity + Responsive + Clone + Send + Sync,
+        F: ForceLaw,
+    {
+        let scale_factor = tree.get_scale_factor();
+        let velocity_scale = scale_factor.map(|a| 1. / a.sqrt()).unwrap_or(1.);
+        let particles = tree
+            .as_vec()
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let entity = x.as_entity();
+                if entity.species >= PARTICLE_TYPES {
+                    return Err(GadgetError::UnsupportedType(entity.species));
+                }
+                Ok(GadgetParticle {
+                    particle_type: entity.species,
+                    id: i as u64 + 1,
+                    x: entity.x,
+                    y: entity.y,
+                    z: entity.z,
+                    vx: entity.vx * velocity_scale,
+                    vy: entity.vy * velocity_scale,
+                    vz: entity.vz * velocity_scale,
+                    mass: entity.mass,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let cosmology = tree.get_cosmology();
+        let header = GadgetHeader {
+            time: scale_factor.unwrap_or_else(|| tree.get_time()),
+            redshift: scale_factor.map(|a| 1. / a - 1.).unwrap_or(0.),
+            box_size: match tree.get_boundary() {
+                Some(Boundary::Periodic(domain)) => match tree.get_periodic_box() {
+                    Some(size) if domain.min == (0., 0., 0.) => size,
+                    _ => {
+                        return Err(GadgetError::Unrepresentable(format!(
+                            "a periodic domain from {:?} to {:?}",
+                            domain.min, domain.max
+                        )))
+                    }
+                },
+                _ => 0.,
+            },
+            omega0: cosmology.map(|cosmology| cosmology.omega_m).unwrap_or(0.),
+            omega_lambda: cosmology
+                .map(|cosmology| cosmology.omega_lambda)
+                .unwrap_or(0.),
+            num_files: 1,
+            ..Default::default()
+        };
+        Ok(Snapshot { header, particles })
+    }
+}
+
+/// Returns the number of particles whose masses are in the mass block rather than the mass table.
+fn masses_needed(npart: &[usize; PARTICLE_TYPES], mass_table: &[f64; PARTICLE_TYPES]) -> usize {
+    npart
+        .iter()
+        .zip(mass_table.iter())
+        .filter(|(_, mass)| **mass == 0.)
+        .map(|(number, _)| number)
+        .sum()
+}
+
+/// Returns the length of a record holding `length` bytes, as it is written around the record.
+fn record_length(length: usize) -> Result<u32, GadgetError> {
+    u32::try_from(length)
+        .map_err(|_| GadgetError::Unrepresentable(format!("a block of {} bytes", length)))
+}
+
+fn write_record(out: &mut Vec<u8>, data: &[u8]) -> Result<(), GadgetError> {
+    let length = record_length(data.len())?.to_le_bytes();
+    out.extend_from_slice(&length);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&length);
+    Ok(())
+}
+
+/// The byte order of a snapshot.
+#[derive(Clone, Copy)]
+struct Endian {
+    big: bool,
+}
+
+impl Endian {
+    fn bytes<const N: usize>(self, data: &[u8]) -> [u8; N] {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(&data[..N]);
+        if self.big {
+            bytes.reverse();
+        }
+        bytes
+    }
+
+    fn u32(self, data: &[u8]) -> u32 {
+        u32::from_le_bytes(self.bytes(data))
+    }
+
+    fn i32(self, data: &[u8]) -> i32 {
+        i32::from_le_bytes(self.bytes(data))
+    }
+
+    fn u64(self, data: &[u8]) -> u64 {
+        u64::from_le_bytes(self.bytes(data))
+    }
+
+    fn f32(self, data: &[u8]) -> f32 {
+        f32::from_le_bytes(self.bytes(data))
+    }
+
+    fn f64(self, data: &[u8]) -> f64 {
+        f64::from_le_bytes(self.bytes(data))
+    }
+}
+
+/// Reads the Fortran records of a snapshot one at a time.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    endian: Endian,
+    format: GadgetFormat,
+}
+
+impl<'a> Reader<'a> {
+    /// Works out the byte order and format from the length of the first record, which is the
+    /// header in format 1 and a block label in format 2.
+    fn new(bytes: &'a [u8]) -> Result<Reader<'a>, GadgetError> {
+        if bytes.len() < 4 {
+            return Err(GadgetError::Malformed("the file is empty".to_string()));
+        }
+        for big in &[false, true] {
+            let endian = Endian { big: *big };
+            let format = match endian.u32(bytes) as usize {
+                HEADER_SIZE => GadgetFormat::One,
+                8 => GadgetFormat::Two,
+                _ => continue,
+            };
+            return Ok(Reader {
+                bytes,
+                position: 0,
+                endian,
+                format,
+            });
+        }
+        Err(GadgetError::Malformed(
+            "the first record is neither a header nor a block label".to_string(),
+        ))
+    }
+
+    fn at_end(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], GadgetError> {
+        let end = self.position + length;
+        if end > self.bytes.len() {
+            return Err(GadgetError::Malformed(format!(
+                "the file ends at byte {}, in the middle of a record which should end at byte {}",
+                self.bytes.len(),
+                end
+            )));
+        }
+        let data = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(data)
+    }
+
+    /// Returns the contents of the next record, checking that its markers agree.
+    fn record(&mut self) -> Result<&'a [u8], GadgetError> {
+        let start = self.position;
+        let length = self.endian.u32(self.take(4)?);
+        let data = self.take(length as usize)?;
+        let end = self.endian.u32(self.take(4)?);
+        if end != length {
+            return Err(GadgetError::Malformed(format!(
+                "the record at byte {} starts with a length of {} but ends with {}",
+                start, length, end
+            )));
+        }
+        Ok(data)
+    }
+}
+
+/// Returns the header, the number of particles of each type in this file and the mass table.
+fn parse_header(
+    data: &[u8],
+    endian: Endian,
+) -> Result<(GadgetHeader, [usize; PARTICLE_TYPES], [f64; PARTICLE_TYPES]), GadgetError> {
+    if data.len() != HEADER_SIZE {
+        return Err(GadgetError::Malformed(format!(
+            "the header has {} bytes instead of {}",
+            data.len(),
+            HEADER_SIZE
+        )));
+    }
+    let mut npart = [0; PARTICLE_TYPES];
+    let mut mass_table = [0.; PARTICLE_TYPES];
+    let mut npart_total = [0; PARTICLE_TYPES];
+    for i in 0..PARTICLE_TYPES {
+        npart[i] = endian.u32(&data[4 * i..]) as usize;
+        mass_table[i] = endian.f64(&data[24 + 8 * i..]);
+        npart_total[i] = u64::from(endian.u32(&data[96 + 4 * i..]))
+            | u64::from(endian.u32(&data[168 + 4 * i..])) << 32;
+    }
+    let header = GadgetHeader {
+        time: endian.f64(&data[72..]),
+        redshift: endian.f64(&data[80..]),
+        flag_sfr: endian.i32(&data[88..]),
+        flag_feedback: endian.i32(&data[92..]),
+        npart_total,
+        flag_cooling: endian.i32(&data[120..]),
+        num_files: endian.i32(&data[124..]),
+        box_size: endian.f64(&data[128..]),
+        omega0: endian.f64(&data[136..]),
+        omega_lambda: endian.f64(&data[144..]),
+        hubble_param: endian.f64(&data[152..]),
+        flag_stellar_age: endian.i32(&data[160..]),
+        flag_metals: endian.i32(&data[164..]),
+        flag_entropy_instead_u: endian.i32(&data[192..]),
+    };
+    Ok((header, npart, mass_table))
+}
+
+/// Decodes `count` single or double precision floats, depending on the size of the block.
+fn floats(data: &[u8], count: usize, endian: Endian, what: &str) -> Result<Vec<f64>, GadgetError> {
+    if data.len() == 4 * count {
+        Ok(data
+            .chunks(4)
+            .map(|value| f64::from(endian.f32(value)))
+            .collect())
+    } else if data.len() == 8 * count {
+        Ok(data.chunks(8).map(|value| endian.f64(value)).collect())
+    } else {
+        Err(GadgetError::Malformed(format!(
+            "the {} block has {} bytes, which is not {} single or double precision values",
+            what,
+            data.len(),
+            count
+        )))
+    }
+}
+
+/// Decodes `count` 32 or 64 bit IDs, depending on the size of the block.
+fn integers(data: &[u8], count: usize, endian: Endian) -> Result<Vec<u64>, GadgetError> {
+    if data.len() == 4 * count {
+        Ok(data
+            .chunks(4)
+            .map(|value| u64::from(endian.u32(value)))
+            .collect())
+    } else if data.len() == 8 * count {
+        Ok(data.chunks(8).map(|value| endian.u64(value)).collect())
+    } else {
+        Err(GadgetError::Malformed(format!(
+            "the ID block has {} bytes, which is not {} 32 or 64 bit IDs",
+            data.len(),
+            count
+        )))
+    }
+}
@@ -0,0 +1,9 @@
+//! This module contains readers and writers for the file formats used by other simulation and
+//! analysis codes, so that initial conditions can be brought into a [[GravTree]] and its state can
+//! be taken back out.
+
+pub mod gadget;
+pub mod npy;
+pub mod text;
+pub mod trajectory;
+pub mod vtk;
@@ -0,0 +1,659 @@
+//! This module reads and writes the state of a [[GravTree]] as NumPy
+//! [`.npy`](https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html) arrays, either
+//! as one file per array in a directory or bundled into a single `.npz` archive, so that it can be
+//! loaded with `numpy.load` and written back from Python.
+//!
+//! A [[State]] holds the time and the positions, velocities, masses, radii and accelerations of
+//! the entities of a tree. Positions, velocities and accelerations are `(n, 3)` arrays, masses and
+//! radii are `(n,)` arrays and the time is a zero dimensional array. Arrays are written as little
+//! endian `float64` in C order. Any floating point or integer array in either byte order and
+//! either memory order can be read, and is converted to `f64`.
+//!
+//! `.npz` archives are written uncompressed, as `numpy.savez` writes them. Archives written by
+//! `numpy.savez_compressed` are deflate-compressed, which is not supported.
+
+use crate::force_laws::ForceLaw;
+use crate::{AsEntity, Entity, GravTree, Responsive};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+/// The ways in which reading or writing arrays can fail.
+#[derive(Debug)]
+pub enum NpyError {
+    /// The file could not be read or written.
+    Io(io::Error),
+    /// The file is not laid out as a `.npy` file or `.npz` archive should be.
+    Malformed(String),
+    /// The file uses a feature of the format which is not supported, such as a data type which is
+    /// not a number or a compressed archive.
+    Unsupported(String),
+}
+
+impl fmt::Display for NpyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NpyError::Io(error) => write!(f, "could not access the arrays: {}", error),
+            NpyError::Malformed(reason) => write!(f, "the file is malformed: {}", reason),
+            NpyError::Unsupported(feature) => write!(f, "{} are not supported", feature),
+        }
+    }
+}
+
+impl std::error::Error for NpyError {}
+
+impl From<io::Error> for NpyError {
+    fn from(error: io::Error) -> Self {
+        NpyError::Io(error)
+    }
+}
+
+/// An array of any number of dimensions, with its elements in C order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Array {
+    pub shape: Vec<usize>,
+    pub data: Vec<f64>,
+}
+
+impl Array {
+    /// Returns an array of shape `shape` holding `data`.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in `data` is not the product of `shape`.
+    pub fn new(shape: Vec<usize>, data: Vec<f64>) -> Array {
+        assert_eq!(
+            shape.iter().product::<usize>(),
+            data.len(),
+            "an array of shape {:?} has a different number of elements",
+            shape
+        );
+        Array { shape, data }
+    }
+
+    /// Returns an `(n, 3)` array of `vectors`.
+    pub fn from_vectors(vectors: &[(f64, f64, f64)]) -> Array {
+        let data = vectors.iter().flat_map(|v| vec![v.0, v.1, v.2]).collect();
+        Array::new(vec![vectors.len(), 3], data)
+    }
+
+    /// Returns the rows of an `(n, 3)` array as vectors, or `None` if the array does not have that
+    /// shape.
+    pub fn to_vectors(&self) -> Option<Vec<(f64, f64, f64)>> {
+        if self.shape.len() != 2 || self.shape[1] != 3 {
+            return None;
+        }
+        Some(self.data.chunks(3).map(|v| (v[0], v[1], v[2])).collect())
+    }
+
+    /// Reads the `.npy` file at `path`.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Array, NpyError> {
+        Array::from_bytes(&fs::read(path)?)
+    }
+
+    /// Reads an array from the contents of a `.npy` file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Array, NpyError> {
+        if bytes.len() < 10 || &bytes[..6] != MAGIC {
+            return Err(NpyError::Malformed(
+                "the file does not start with the .npy magic bytes".to_string(),
+            ));
+        }
+        let (header_length, header_start) = match bytes[6] {
+            1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+            2 | 3 if bytes.len() >= 12 => (
+                u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize,
+                12,
+            ),
+            version => {
+                return Err(NpyError::Unsupported(format!(
+                    "files of format version {}",
+                    version
+                )))
+            }
+        };
+        let data_start = header_start + header_length;
+        if bytes.len() < data_start {
+            return Err(NpyError::Malformed(
+                "the file ends in the middle of the header".to_string(),
+            ));
+        }
+        let header = std::str::from_utf8(&bytes[header_start..data_start])
+            .map_err(|_| NpyError::Malformed("the header is not text".to_string()))?;
+        let descr = header_value(header, "descr")?;
+        let dtype = DataType::parse(descr.trim_matches(|c| c == '\'' || c == '"'))?;
+        let fortran_order = match header_value(header, "fortran_order")? {
+            "True" => true,
+            "False" => false,
+            value => {
+                return Err(NpyError::Malformed(format!(
+                    "fortran_order is `{}` rather than True or False",
+                    value
+                )))
+            }
+        };
+        let shape = parse_shape(header_value(header, "shape")?)?;
+
+        let count = shape.iter().product::<usize>();
+        let data = &bytes[data_start..];
+        if data.len() < count * dtype.size {
+            return Err(NpyError::Malformed(format!(
+                "the data has {} bytes, which is too few for {} elements of {} bytes",
+                data.len(),
+                count,
+                dtype.size
+            )));
+        }
+        let values = data
+            .chunks(dtype.size)
+            .take(count)
+            .map(|value| dtype.decode(value))
+            .collect::<Vec<_>>();
+        let data = if fortran_order && shape.len() > 1 {
+            from_fortran_order(&shape, &values)
+        } else {
+            values
+        };
+        Ok(Array { shape, data })
+    }
+
+    /// Writes the array to the `.npy` file at `path`, replacing it if it exists.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// Returns the contents of a `.npy` file holding the array.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let shape = match self.shape.len() {
+            1 => format!("({},)", self.shape[0]),
+            _ => {
+                let dimensions = self.shape.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+                format!("({})", dimensions.join(", "))
+            }
+        };
+        let mut header = format!(
+            "{{'descr': '<f8', 'fortran_order': False, 'shape': {}, }}",
+            shape
+        );
+        // the header is padded with spaces and ends with a newline, so that the data starts on a
+        // multiple of 64 bytes
+        let unpadded = MAGIC.len() + 4 + header.len() + 1;
+        header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+        header.push('\n');
+
+        let mut out = Vec::with_capacity(10 + header.len() + 8 * self.data.len());
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&[1, 0]);
+        out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        out.extend_from_slice(header.as_bytes());
+        for value in &self.data {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out
+    }
+}
+
+/// The state of the entities of a [[GravTree]] as arrays. Each array has one row per entity, in
+/// the same order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct State {
+    pub time: f64,
+    pub positions: Vec<(f64, f64, f64)>,
+    pub velocities: Vec<(f64, f64, f64)>,
+    pub masses: Vec<f64>,
+    pub radii: Vec<f64>,
+    /// The gravitational acceleration of each entity. This is empty if the state was read from
+    /// arrays which did not include accelerations.
+    pub accelerations: Vec<(f64, f64, f64)>,
+}
+
+impl State {
+    /// Returns the state of the entities of `tree`. The accelerations are those of the tree's force
+    /// law alone; external fields and drag are left out. Test particles are left out.
+    pub fn from_tree<T, F>(tree: &GravTree<T, F>) -> State
+    where
+        T: AsEntity + Responsive + Clone + Send + Sync,
+        F: ForceLaw,
+    {
+        let mut state = State {
+            time: tree.get_time(),
+            ..Default::default()
+        };
+        for (entity, acceleration) in tree.accelerations() {
+            state.positions.push((entity.x, entity.y, entity.z));
+            state.velocities.push((entity.vx, entity.vy, entity.vz));
+            state.masses.push(entity.mass);
+            state.radii.push(entity.radius);
+            state.accelerations.push(acceleration);
+        }
+        state
+    }
+
+    /// Returns an entity for each row of the arrays. Fields which are not in the arrays are left
+    /// at their defaults.
+    pub fn entities(&self) -> Vec<Entity> {
+        (0..self.masses.len())
+            .map(|i| {
+                let (x, y, z) = self.positions[i];
+                let (vx, vy, vz) = self.velocities[i];
+                Entity {
+                    x,
+                    y,
+                    z,
+                    vx,
+                    vy,
+                    vz,
+                    mass: self.masses[i],
+                    radius: self.radii[i],
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the arrays of the state with their names, which are `time`, `positions`,
+    /// `velocities`, `masses`, `radii` and, if there are any, `accelerations`.
+    pub fn arrays(&self) -> Vec<(&'static str, Array)> {
+        let mut arrays = vec![
+            ("time", Array::new(Vec::new(), vec![self.time])),
+            ("positions", Array::from_vectors(&self.positions)),
+            ("velocities", Array::from_vectors(&self.velocities)),
+            (
+                "masses",
+                Array::new(vec![self.masses.len()], self.masses.clone()),
+            ),
+            (
+                "radii",
+                Array::new(vec![self.radii.len()], self.radii.clone()),
+            ),
+        ];
+        if !self.accelerations.is_empty() {
+            arrays.push(("accelerations", Array::from_vectors(&self.accelerations)));
+        }
+        arrays
+    }
+
+    /// Builds a state from named arrays. `time` and `accelerations` may be missing, in which case
+    /// the time is zero and there are no accelerations; every other array must be present and
+    /// have one row per entity.
+    pub fn from_arrays(arrays: &[(String, Array)]) -> Result<State, NpyError> {
+        let find = |name: &str| {
+            arrays
+                .iter()
+                .find(|(array_name, _)| array_name == name)
+                .map(|(_, array)| array)
+        };
+        let require = |name: &str| {
+            find(name).ok_or_else(|| NpyError::Malformed(format!("there is no `{}` array", name)))
+        };
+        let count = require("masses")?.data.len();
+        let vectors = |array: &Array, name: &str| match array.to_vectors() {
+            Some(vectors) if vectors.len() == count => Ok(vectors),
+            _ => Err(NpyError::Malformed(format!(
+                "`{}` has shape {:?} rather than ({}, 3)",
+                name, array.shape, count
+            ))),
+        };
+        let scalars = |array: &Array, name: &str| {
+            if array.shape == [count] {
+                Ok(array.data.clone())
+            } else {
+                Err(NpyError::Malformed(format!(
+                    "`{}` has shape {:?} rather than ({},)",
+                    name, array.shape, count
+                )))
+            }
+        };
+        let time = match find("time") {
+            Some(time) if time.data.len() == 1 => time.data[0],
+            Some(time) => {
+                return Err(NpyError::Malformed(format!(
+                    "`time` has shape {:?} rather than ()",
+                    time.shape
+                )))
+            }
+            None => 0.,
+        };
+        Ok(State {
+            time,
+            positions: vectors(require("positions")?, "positions")?,
+            velocities: vectors(require("velocities")?, "velocities")?,
+            masses: scalars(require("masses")?, "masses")?,
+            radii: scalars(require("radii")?, "radii")?,
+            accelerations: match find("accelerations") {
+                Some(array) => vectors(array, "accelerations")?,
+                None => Vec::new(),
+            },
+        })
+    }
+
+    /// Writes each array to `<name>.npy` in the directory `dir`, creating it if it does not exist.
+    pub fn write_dir<P: AsRef<Path>>(&self, dir: P) -> io::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        for (name, array) in self.arrays() {
+            array.write(dir.join(format!("{}.npy", name)))?;
+        }
+        Ok(())
+    }
+
+    /// Reads the arrays written by [[State::write_dir]] from the directory `dir`.
+    pub fn read_dir<P: AsRef<Path>>(dir: P) -> Result<State, NpyError> {
+        let dir = dir.as_ref();
+        let mut arrays = Vec::new();
+        for name in &[
+            "time",
+            "positions",
+            "velocities",
+            "masses",
+            "radii",
+            "accelerations",
+        ] {
+            let path = dir.join(format!("{}.npy", name));
+            if path.exists() {
+                arrays.push((name.to_string(), Array::read(path)?));
+            }
+        }
+        State::from_arrays(&arrays)
+    }
+
+    /// Writes the arrays to the `.npz` archive at `path`, replacing it if it exists.
+    pub fn write_npz<P: AsRef<Path>>(&self, path: P) -> Result<(), NpyError> {
+        write_npz(path, &self.arrays())
+    }
+
+    /// Reads the arrays from the `.npz` archive at `path`. Other arrays in the archive are ignored.
+    pub fn read_npz<P: AsRef<Path>>(path: P) -> Result<State, NpyError> {
+        State::from_arrays(&read_npz(path)?)
+    }
+}
+
+/// Writes `arrays` to the `.npz` archive at `path` under their names, replacing it if it exists.
+pub fn write_npz<P: AsRef<Path>, S: AsRef<str>>(
+    path: P,
+    arrays: &[(S, Array)],
+) -> Result<(), NpyError> {
+    let files = arrays
+        .iter()
+        .map(|(name, array)| (format!("{}.npy", name.as_ref()), array.to_bytes()))
+        .collect::<Vec<_>>();
+    fs::write(path, zip(&files)?)?;
+    Ok(())
+}
+
+/// Reads every array in the `.npz` archive at `path`, with their names.
+pub fn read_npz<P: AsRef<Path>>(path: P) -> Result<Vec<(String, Array)>, NpyError> {
+    unzip(&fs::read(path)?)?
+        .into_iter()
+        .map(|(name, data)| {
+            let name = name.strip_suffix(".npy").unwrap_or(&name).to_string();
+            Ok((name, Array::from_bytes(data)?))
+        })
+        .collect()
+}
+
+/// A numeric data type of a `.npy` file.
+struct DataType {
+    big_endian: bool,
+    kind: char,
+    size: usize,
+}
+
+impl DataType {
+    /// Parses a NumPy type string such as `<f8`.
+    fn parse(descr: &str) -> Result<DataType, NpyError> {
+        let unsupported = || NpyError::Unsupported(format!("arrays of type `{}`", descr));
+        let mut chars = descr.chars();
+        let big_endian = match chars.next() {
+            Some('<') | Some('|') => false,
+            Some('>') => true,
+            Some('=') => cfg!(target_endian = "big"),
+            _ => return Err(unsupported()),
+        };
+        let kind = chars.next().ok_or_else(unsupported)?;
+        let size = chars.as_str().parse().map_err(|_| unsupported())?;
+        match (kind, size) {
+            ('f', 4)
+            | ('f', 8)
+            | ('i', 1)
+            | ('i', 2)
+            | ('i', 4)
+            | ('i', 8)
+            | ('u', 1)
+            | ('u', 2)
+            | ('u', 4)
+            | ('u', 8)
+            | ('b', 1) => Ok(DataType {
+                big_endian,
+                kind,
+                size,
+            }),
+            _ => Err(unsupported()),
+        }
+    }
+
+    fn decode(&self, value: &[u8]) -> f64 {
+        let mut bytes = [0; 8];
+        bytes[..self.size].copy_from_slice(value);
+        if self.big_endian {
+            bytes[..self.size].reverse();
+        }
+        // sign extend integers so that they can be read as 64 bit integers
+        if self.kind == 'i' && bytes[self.size - 1] & 0x80 != 0 {
+            for byte in &mut bytes[self.size..] {
+                *byte = 0xff;
+            }
+        }
+        match (self.kind, self.size) {
+            ('f', 4) => f64::from(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            ('f', _) => f64::from_le_bytes(bytes),
+            ('i', _) => i64::from_le_bytes(bytes) as f64,
+            _ => u64::from_le_bytes(bytes) as f64,
+        }
+    }
+}
+
+/// Returns the value of `key` in the Python dictionary literal of a `.npy` header.
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str, NpyError> {
+    let missing = || NpyError::Malformed(format!("the header has no `{}`", key));
+    let start = ["'", "\""]
+        .iter()
+        .find_map(|quote| header.find(&format!("{}{}{}", quote, key, quote)))
+        .ok_or_else(missing)?;
+    let rest = &header[start + key.len() + 2..];
+    let rest = rest.trim_start().strip_prefix(':').ok_or_else(missing)?;
+    let rest = rest.trim_start();
+    // the value ends at the first comma which is not inside a tuple
+    let end = if rest.starts_with('(') {
+        rest.find(')').map(|i| i + 1)
+    } else {
+        rest.find([',', '}'])
+    };
+    Ok(rest[..end.ok_or_else(missing)?].trim())
+}
+
+/// Parses a Python tuple of dimensions, such as `(3,)` or `(2, 3)`.
+fn parse_shape(shape: &str) -> Result<Vec<usize>, NpyError> {
+    shape
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .split(',')
+        .map(str::trim)
+        .filter(|dimension| !dimension.is_empty())
+        .map(|dimension| {
+            dimension
+                .trim_end_matches('L')
+                .parse()
+                .map_err(|_| NpyError::Malformed(format!("`{}` is not a valid shape", shape)))
+        })
+        .collect()
+}
+
+/// Reorders the elements of an array from Fortran order, where the first index varies fastest,
+/// to C order, where the last index does.
+fn from_fortran_order(shape: &[usize], values: &[f64]) -> Vec<f64> {
+    let mut index = vec![0; shape.len()];
+    let mut data = Vec::with_capacity(values.len());
+    for _ in 0..values.len() {
+        let mut offset = 0;
+        for (i, dimension) in index.iter().zip(shape.iter()).rev() {
+            offset = offset * dimension + i;
+        }
+        data.push(values[offset]);
+        // step the C order index, with the last index varying fastest
+        for (i, dimension) in index.iter_mut().zip(shape.iter()).rev() {
+            *i += 1;
+            if *i < *dimension {
+                break;
+            }
+            *i = 0;
+        }
+    }
+    data
+}
+
+/// The CRC-32 checksum used by zip archives.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Returns a zip archive holding `files` uncompressed.
+fn zip(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>, NpyError> {
+    let mut out = Vec::new();
+    let mut directory = Vec::new();
+    for (name, data) in files {
+        if out.len() > u32::MAX as usize || data.len() > u32::MAX as usize {
+            return Err(NpyError::Unsupported(
+                "archives larger than 4 GiB".to_string(),
+            ));
+        }
+        let offset = out.len() as u32;
+        // the version needed, flags, compression method and a modification time of 1980-01-01
+        let mut fields = Vec::new();
+        fields.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0, 0x21, 0]);
+        fields.extend_from_slice(&crc32(data).to_le_bytes());
+        fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&[0, 0]);
+
+        out.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        out.extend_from_slice(&fields);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(data);
+
+        directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        directory.extend_from_slice(&[20, 0]);
+        directory.extend_from_slice(&fields);
+        // the comment length, disk number, internal and external attributes
+        directory.extend_from_slice(&[0; 10]);
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+    }
+    let directory_offset = out.len() as u32;
+    out.extend_from_slice(&directory);
+    out.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    out.extend_from_slice(&directory_offset.to_le_bytes());
+    out.extend_from_slice(&[0, 0]);
+    Ok(out)
+}
+
+/// Returns the names and contents of the uncompressed files in a zip archive, read through its
+/// central directory.
+fn unzip(bytes: &[u8]) -> Result<Vec<(String, &[u8])>, NpyError> {
+    let malformed = |reason: &str| NpyError::Malformed(format!("the archive {}", reason));
+    let get = |start: usize, length: usize| {
+        bytes
+            .get(start..start + length)
+            .ok_or_else(|| malformed("ends early"))
+    };
+    let u16_at = |start: usize| get(start, 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize);
+    let u32_at = |start: usize| get(start, 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+
+    // the end of central directory record is the last thing in the archive, before a comment
+    let end = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .find(|i| bytes[*i..].starts_with(&0x0605_4b50u32.to_le_bytes()))
+        .ok_or_else(|| malformed("has no end of central directory record"))?;
+    let count = u16_at(end + 10)?;
+    let mut entry = u32_at(end + 16)? as usize;
+
+    let mut files = Vec::with_capacity(count);
+    for _ in 0..count {
+        if u32_at(entry)? != 0x0201_4b50 {
+            return Err(malformed("has a broken central directory"));
+        }
+        let method = u16_at(entry + 10)?;
+        let crc = u32_at(entry + 16)?;
+        let mut size = u64::from(u32_at(entry + 24)?);
+        let name_length = u16_at(entry + 28)?;
+        let extra_length = u16_at(entry + 30)?;
+        let comment_length = u16_at(entry + 32)?;
+        let mut offset = u64::from(u32_at(entry + 42)?);
+        let name = String::from_utf8_lossy(get(entry + 46, name_length)?).into_owned();
+
+        // sizes and offsets which do not fit in 32 bits are in the zip64 extra field instead
+        let mut extra = entry + 46 + name_length;
+        let extra_end = extra + extra_length;
+        while extra + 4 <= extra_end {
+            let (id, length) = (u16_at(extra)?, u16_at(extra + 2)?);
+            if id == 1 {
+                let mut field = extra + 4;
+                let mut next = || {
+                    let value = get(field, 8).map(|b| {
+                        let mut value = [0; 8];
+                        value.copy_from_slice(b);
+                        u64::from_le_bytes(value)
+                    });
+                    field += 8;
+                    value
+                };
+                if size == u64::from(u32::MAX) {
+                    size = next()?;
+                }
+                // the compressed size is the same as the size, since the file is stored
+                if u32_at(entry + 20)? == u32::MAX {
+                    next()?;
+                }
+                if offset == u64::from(u32::MAX) {
+                    offset = next()?;
+                }
+            }
+            extra += 4 + length;
+        }
+
+        if method != 0 {
+            return Err(NpyError::Unsupported(
+                "compressed archives, as written by numpy.savez_compressed,".to_string(),
+            ));
+        }
+        let offset = offset as usize;
+        if u32_at(offset)? != 0x0403_4b50 {
+            return Err(malformed(&format!("has no local header for {}", name)));
+        }
+        let data_start = offset + 30 + u16_at(offset + 26)? + u16_at(offset + 28)?;
+        let data = get(data_start, size as usize)?;
+        if crc32(data) != crc {
+            return Err(malformed(&format!("has a corrupt copy of {}", name)));
+        }
+        files.push((name, data));
+        entry = extra_end + comment_length;
+    }
+    Ok(files)
+}
@@ -0,0 +1,397 @@
+//! This module reads and writes [[Entity]]s as delimited text, one entity per line, such as CSV
+//! files or the whitespace separated tables many catalogues come in.
+//!
+//! A [[TextFormat]] says which [[Field]] of the entity each column holds, how columns are
+//! separated, whether there is a header line naming the columns, and which lines are comments.
+//! [[TextFormat::whitespace]] uses the same columns as [[Entity::as_string]], so it reads back what
+//! that writes. Fields which have no column are left at their defaults, and columns mapped to
+//! [[Field::Ignore]] are skipped when reading and written as zero.
+//!
+//! Values are written with Rust's shortest representation which reads back as the same number, so
+//! writing and then reading entities gives back exactly the same entities.
+
+use crate::Entity;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// A field of an [[Entity]] which a column can hold.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Field {
+    X,
+    Y,
+    Z,
+    Vx,
+    Vy,
+    Vz,
+    Radius,
+    Mass,
+    Wx,
+    Wy,
+    Wz,
+    MomentOfInertia,
+    CollisionGroup,
+    CollisionMask,
+    Species,
+    Charge,
+    /// A column which is not a field of [[Entity]], such as a name or an ID.
+    Ignore,
+}
+
+/// Every field, in the order of [[Entity]]'s fields.
+const FIELDS: [Field; 16] = [
+    Field::X,
+    Field::Y,
+    Field::Z,
+    Field::Vx,
+    Field::Vy,
+    Field::Vz,
+    Field::Radius,
+    Field::Mass,
+    Field::Wx,
+    Field::Wy,
+    Field::Wz,
+    Field::MomentOfInertia,
+    Field::CollisionGroup,
+    Field::CollisionMask,
+    Field::Species,
+    Field::Charge,
+];
+
+impl Field {
+    /// Returns the name of the field, which is the name of the [[Entity]] field it holds. This is
+    /// what is written in headers.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::X => "x",
+            Field::Y => "y",
+            Field::Z => "z",
+            Field::Vx => "vx",
+            Field::Vy => "vy",
+            Field::Vz => "vz",
+            Field::Radius => "radius",
+            Field::Mass => "mass",
+            Field::Wx => "wx",
+            Field::Wy => "wy",
+            Field::Wz => "wz",
+            Field::MomentOfInertia => "moment_of_inertia",
+            Field::CollisionGroup => "collision_group",
+            Field::CollisionMask => "collision_mask",
+            Field::Species => "species",
+            Field::Charge => "charge",
+            Field::Ignore => "ignore",
+        }
+    }
+
+    /// Returns the field with the name `name`, ignoring case, or `None` if there is no such field.
+    pub fn from_name(name: &str) -> Option<Field> {
+        FIELDS
+            .iter()
+            .find(|field| field.name().eq_ignore_ascii_case(name))
+            .copied()
+    }
+
+    /// Returns the value of this field of `entity`, as it is written. Integer fields are written
+    /// without a fractional part.
+    fn get(&self, entity: &Entity) -> String {
+        self.value(entity).to_string()
+    }
+
+    /// Parses `value` into this field of `entity`, returning a description of the problem if it
+    /// is not a valid value for the field.
+    fn set(&self, entity: &mut Entity, value: &str) -> Result<(), String> {
+        fn parse<V: std::str::FromStr>(value: &str, kind: &str) -> Result<V, String> {
+            value
+                .parse()
+                .map_err(|_| format!("`{}` is not {}", value, kind))
+        }
+        let number = "a number";
+        let integer = "a non-negative integer";
+        match self {
+            Field::X => entity.x = parse(value, number)?,
+            Field::Y => entity.y = parse(value, number)?,
+            Field::Z => entity.z = parse(value, number)?,
+            Field::Vx => entity.vx = parse(value, number)?,
+            Field::Vy => entity.vy = parse(value, number)?,
+            Field::Vz => entity.vz = parse(value, number)?,
+            Field::Radius => entity.radius = parse(value, number)?,
+            Field::Mass => entity.mass = parse(value, number)?,
+            Field::Wx => entity.wx = parse(value, number)?,
+            Field::Wy => entity.wy = parse(value, number)?,
+            Field::Wz => entity.wz = parse(value, number)?,
+            Field::MomentOfInertia => entity.moment_of_inertia = parse(value, number)?,
+            Field::CollisionGroup => entity.collision_group = parse(value, integer)?,
+            Field::CollisionMask => entity.collision_mask = parse(value, integer)?,
+            Field::Species => entity.species = parse(value, integer)?,
+            Field::Charge => entity.charge = parse(value, number)?,
+            Field::Ignore => (),
+        }
+        Ok(())
+    }
+
+    /// Returns the value of this field of `entity` as a number, which is what the
+    /// formats that store entities field by field write. Integer fields are exact, since they are
+    /// well within the range of integers an `f64` can hold.
+    pub(crate) fn value(&self, entity: &Entity) -> f64 {
+        match self {
+            Field::X => entity.x,
+            Field::Y => entity.y,
+            Field::Z => entity.z,
+            Field::Vx => entity.vx,
+            Field::Vy => entity.vy,
+            Field::Vz => entity.vz,
+            Field::Radius => entity.radius,
+            Field::Mass => entity.mass,
+            Field::Wx => entity.wx,
+            Field::Wy => entity.wy,
+            Field::Wz => entity.wz,
+            Field::MomentOfInertia => entity.moment_of_inertia,
+            Field::CollisionGroup => f64::from(entity.collision_group),
+            Field::CollisionMask => f64::from(entity.collision_mask),
+            Field::Species => entity.species as f64,
+            Field::Charge => entity.charge,
+            Field::Ignore => 0.,
+        }
+    }
+
+    /// Sets this field of `entity` to `value`, which was returned by [[Field::value]], as the
+    /// formats that store entities field by field do when reading them back.
+    pub(crate) fn set_value(&self, entity: &mut Entity, value: f64) {
+        match self {
+            Field::X => entity.x = value,
+            Field::Y => entity.y = value,
+            Field::Z => entity.z = value,
+            Field::Vx => entity.vx = value,
+            Field::Vy => entity.vy = value,
+            Field::Vz => entity.vz = value,
+            Field::Radius => entity.radius = value,
+            Field::Mass => entity.mass = value,
+            Field::Wx => entity.wx = value,
+            Field::Wy => entity.wy = value,
+            Field::Wz => entity.wz = value,
+            Field::MomentOfInertia => entity.moment_of_inertia = value,
+            Field::CollisionGroup => entity.collision_group = value as u32,
+            Field::CollisionMask => entity.collision_mask = value as u32,
+            Field::Species => entity.species = value as usize,
+            Field::Charge => entity.charge = value,
+            Field::Ignore => (),
+        }
+    }
+}
+
+/// How the columns of a line are separated.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Delimiter {
+    /// Any run of spaces and tabs. Columns are written separated by a single space.
+    Whitespace,
+    /// A single character, such as `,` for CSV. Whitespace around each value is ignored.
+    Char(char),
+}
+
+/// The ways in which reading entities from text can fail.
+#[derive(Debug)]
+pub enum TextError {
+    /// The text could not be read.
+    Io(io::Error),
+    /// A line could not be read as an entity. Lines are numbered from one.
+    Malformed { line: usize, message: String },
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextError::Io(error) => write!(f, "could not read the entities: {}", error),
+            TextError::Malformed { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for TextError {}
+
+impl From<io::Error> for TextError {
+    fn from(error: io::Error) -> Self {
+        TextError::Io(error)
+    }
+}
+
+/// The layout of a text file of entities.
+#[derive(Clone, Debug)]
+pub struct TextFormat {
+    pub delimiter: Delimiter,
+    /// The field each column holds, in order. If this is empty, the columns are taken from the
+    /// header when reading, in which case columns with names which are not fields are ignored.
+    pub columns: Vec<Field>,
+    /// Whether the first line which is not a comment names the columns.
+    pub header: bool,
+    /// Lines starting with this character, after any leading whitespace, are skipped. Blank lines
+    /// are always skipped.
+    pub comment: Option<char>,
+}
+
+impl TextFormat {
+    /// Comma separated values with a header and `#` comments, whose columns are taken from the
+    /// header when reading. Every field is written.
+    pub fn csv() -> TextFormat {
+        TextFormat {
+            delimiter: Delimiter::Char(','),
+            columns: Vec::new(),
+            header: true,
+            comment: Some('#'),
+        }
+    }
+
+    /// Whitespace separated values with no header and `#` comments, in the same columns as
+    /// [[Entity::as_string]]: `x y z vx vy vz mass radius`.
+    pub fn whitespace() -> TextFormat {
+        TextFormat {
+            delimiter: Delimiter::Whitespace,
+            columns: vec![
+                Field::X,
+                Field::Y,
+                Field::Z,
+                Field::Vx,
+                Field::Vy,
+                Field::Vz,
+                Field::Mass,
+                Field::Radius,
+            ],
+            header: false,
+            comment: Some('#'),
+        }
+    }
+
+    /// Returns this format with the columns `columns`.
+    pub fn with_columns(self, columns: &[Field]) -> TextFormat {
+        TextFormat {
+            columns: columns.to_vec(),
+            ..self
+        }
+    }
+
+    /// Reads every entity in the file at `path`.
+    pub fn read_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Entity>, TextError> {
+        self.read(BufReader::new(File::open(path)?))
+    }
+
+    /// Reads every entity in `text`.
+    pub fn read_str(&self, text: &str) -> Result<Vec<Entity>, TextError> {
+        self.read(text.as_bytes())
+    }
+
+    /// Reads every entity from `reader`.
+    pub fn read<R: BufRead>(&self, reader: R) -> Result<Vec<Entity>, TextError> {
+        let mut columns = if self.columns.is_empty() {
+            None
+        } else {
+            Some(self.columns.clone())
+        };
+        let mut header_read = !self.header;
+        let mut entities = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let trimmed = line.trim();
+            if trimmed.is_empty()
+                || self
+                    .comment
+                    .is_some_and(|comment| trimmed.starts_with(comment))
+            {
+                continue;
+            }
+            let values = self.split(trimmed);
+            let malformed = |message: String| TextError::Malformed {
+                line: i + 1,
+                message,
+            };
+            if !header_read {
+                header_read = true;
+                if columns.is_none() {
+                    columns = Some(
+                        values
+                            .iter()
+                            .map(|name| Field::from_name(name.trim_matches('"')))
+                            .map(|field| field.unwrap_or(Field::Ignore))
+                            .collect(),
+                    );
+                }
+                continue;
+            }
+            let columns = columns.as_ref().ok_or_else(|| {
+                malformed("there is no header and no columns were given".to_string())
+            })?;
+            if values.len() != columns.len() {
+                return Err(malformed(format!(
+                    "expected {} columns but found {}",
+                    columns.len(),
+                    values.len()
+                )));
+            }
+            let mut entity = Entity::default();
+            for (column, (field, value)) in columns.iter().zip(values).enumerate() {
+                field.set(&mut entity, value).map_err(|message| {
+                    malformed(format!(
+                        "column {} ({}): {}",
+                        column + 1,
+                        field.name(),
+                        message
+                    ))
+                })?;
+            }
+            entities.push(entity);
+        }
+        Ok(entities)
+    }
+
+    /// Splits a line into its values.
+    fn split<'a>(&self, line: &'a str) -> Vec<&'a str> {
+        match self.delimiter {
+            Delimiter::Whitespace => line.split_whitespace().collect(),
+            Delimiter::Char(delimiter) => line.split(delimiter).map(str::trim).collect(),
+        }
+    }
+
+    /// Returns the columns which are written, which is every field if none were given.
+    fn written_columns(&self) -> &[Field] {
+        if self.columns.is_empty() {
+            &FIELDS
+        } else {
+            &self.columns
+        }
+    }
+
+    /// Writes `entities` to the file at `path`, replacing it if it exists.
+    pub fn write_file<P: AsRef<Path>>(&self, path: P, entities: &[Entity]) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer, entities)?;
+        writer.flush()
+    }
+
+    /// Returns `entities` as text.
+    pub fn write_string(&self, entities: &[Entity]) -> String {
+        let mut out = Vec::new();
+        self.write(&mut out, entities)
+            .expect("writing to a vector does not fail");
+        String::from_utf8(out).expect("every value is written as ASCII")
+    }
+
+    /// Writes `entities` to `writer`, after the header if there is one.
+    pub fn write<W: Write>(&self, mut writer: W, entities: &[Entity]) -> io::Result<()> {
+        let separator = match self.delimiter {
+            Delimiter::Whitespace => " ".to_string(),
+            Delimiter::Char(delimiter) => delimiter.to_string(),
+        };
+        let columns = self.written_columns();
+        if self.header {
+            let names = columns.iter().map(Field::name).collect::<Vec<_>>();
+            writeln!(writer, "{}", names.join(&separator))?;
+        }
+        for entity in entities {
+            let values = columns
+                .iter()
+                .map(|field| field.get(entity))
+                .collect::<Vec<_>>();
+            writeln!(writer, "{}", values.join(&separator))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,574 @@
+//! This module records the trajectories of entities over a simulation with a
+//! [[TrajectoryWriter]], and replays them with a [[TrajectoryReader]].
+//!
+//! Each recorded step is a frame holding the step, the time, and the ID and the selected
+//! [[Field]]s of each recorded entity. Entities do not have IDs of their own, so they are given
+//! by the caller, and must stay the same for an entity from step to step. Steps can be subsampled
+//! so that only every `n`th is recorded, and entities can be restricted to a set of IDs.
+//!
+//! Frames are grouped into chunks, each of which can be decoded on its own, which gives random
+//! access by step. Within a chunk, each value is stored as the XOR of its bits with the same
+//! value of the same entity in the frame before, which is zero for anything that did not change
+//! and mostly zero for anything that changed a little. The bytes of these words are then
+//! regrouped so that the first bytes of every word come first, and so on, and the chunk is
+//! compressed with zlib. Values are stored exactly.
+//!
+//! A trajectory file consists of:
+//! 1. the magic bytes `BBTRAJ\0\0`,
+//! 2. the version of the format as a little-endian `u32`,
+//! 3. the number of fields as a little-endian `u32`, followed by the name of each field as a
+//!    length byte and the name,
+//! 4. the chunks, each of which is the number of frames as a little-endian `u32`, the step and
+//!    time of each frame as a little-endian `u64` and `f64`, the length of the compressed frames
+//!    as a little-endian `u64`, and the compressed frames.
+//!
+//! Chunks are only written once they are full or the writer is finished, so a file whose writer
+//! never finished ends with whole chunks, and can still be read up to its last chunk.
+
+use crate::force_laws::ForceLaw;
+use crate::formats::text::Field;
+use crate::{AsEntity, Entity, GravTree, Responsive};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// The version of the trajectory format which this version of bigbang writes.
+pub const TRAJECTORY_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 8] = b"BBTRAJ\0\0";
+
+/// The number of frames in a chunk, unless it is changed with
+/// [[TrajectoryWriter::set_chunk_frames]].
+const DEFAULT_CHUNK_FRAMES: usize = 64;
+
+/// The ways in which recording or replaying a trajectory can fail.
+#[derive(Debug)]
+pub enum TrajectoryError {
+    /// The trajectory could not be read or written.
+    Io(io::Error),
+    /// The file does not start with the trajectory magic bytes.
+    NotATrajectory,
+    /// The trajectory was written with a version of the format which this version of bigbang
+    /// does not understand.
+    UnsupportedVersion(u32),
+    /// The trajectory could not be decoded.
+    Corrupt(String),
+}
+
+impl fmt::Display for TrajectoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrajectoryError::Io(error) => write!(f, "could not access the trajectory: {}", error),
+            TrajectoryError::NotATrajectory => {
+                write!(f, "the file is not a bigbang trajectory")
+            }
+            TrajectoryError::UnsupportedVersion(version) => write!(
+                f,
+                "the trajectory has version {}, but this reader has version {}",
+                version, TRAJECTORY_VERSION
+            ),
+            TrajectoryError::Corrupt(reason) => {
+                write!(f, "the trajectory is corrupt: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TrajectoryError {}
+
+impl From<io::Error> for TrajectoryError {
+    fn from(error: io::Error) -> Self {
+        TrajectoryError::Io(error)
+    }
+}
+
+/// A single recorded step.
+#[derive(Clone, PartialEq)]
+pub struct Frame {
+    pub step: u64,
+    pub time: f64,
+    /// The ID of each entity, in the same order as `entities`.
+    pub ids: Vec<u64>,
+    /// The recorded entities. Fields which were not recorded are left at their defaults.
+    pub entities: Vec<Entity>,
+}
+
+/// Records frames to a trajectory file. Frames are buffered until a chunk is full, so
+/// [[TrajectoryWriter::finish]] should be called once the last frame is recorded. If it is not,
+/// the last chunk is written when the writer is dropped, and any error is ignored.
+pub struct TrajectoryWriter<W: Write> {
+    writer: Option<W>,
+    fields: Vec<Field>,
+    every: u64,
+    ids: Option<HashSet<u64>>,
+    chunk_frames: usize,
+    frames: Vec<Frame>,
+}
+
+impl TrajectoryWriter<BufWriter<File>> {
+    /// Creates the trajectory file at `path`, replacing it if it exists, to record `fields` of
+    /// each entity.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        fields: &[Field],
+    ) -> Result<TrajectoryWriter<BufWriter<File>>, TrajectoryError> {
+        TrajectoryWriter::new(BufWriter::new(File::create(path)?), fields)
+    }
+}
+
+impl<W: Write> TrajectoryWriter<W> {
+    /// Starts a trajectory in `writer`, recording `fields` of each entity. [[Field::Ignore]] is
+    /// not recorded.
+    pub fn new(mut writer: W, fields: &[Field]) -> Result<TrajectoryWriter<W>, TrajectoryError> {
+        let fields = fields
+            .iter()
+            .copied()
+            .filter(|field| *field != Field::Ignore)
+            .collect::<Vec<_>>();
+        writer.write_all(MAGIC)?;
+        writer.write_all(&TRAJECTORY_VERSION.to_le_bytes())?;
+        writer.write_all(&(fields.len() as u32).to_le_bytes())?;
+        for field in &fields {
+            writer.write_all(&[field.name().len() as u8])?;
+            writer.write_all(field.name().as_bytes())?;
+        }
+        Ok(TrajectoryWriter {
+            writer: Some(writer),
+            fields,
+            every: 1,
+            ids: None,
+            chunk_frames: DEFAULT_CHUNK_FRAMES,
+            frames: Vec::new(),
+        })
+    }
+
+    /// Only records steps which are a multiple of `every`. By default every step is recorded.
+    pub fn set_every(&mut self, every: u64) {
+        self.every = every.max(1);
+    }
+
+    /// Only records the entities with IDs in `ids`, or every entity if `ids` is `None`, which is
+    /// the default.
+    pub fn set_ids(&mut self, ids: Option<&[u64]>) {
+        self.ids = ids.map(|ids| ids.iter().copied().collect());
+    }
+
+    /// Sets the number of frames in each chunk. Larger chunks compress better, but a frame is
+    /// read by decoding its whole chunk. The default is 64.
+    pub fn set_chunk_frames(&mut self, chunk_frames: usize) {
+        self.chunk_frames = chunk_frames.max(1);
+    }
+
+    /// Returns the fields which are recorded.
+    pub fn get_fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// Records `entities`, each with its ID, as the frame for `step` at `time`. Returns whether
+    /// the step was recorded, which it is not if it is not a multiple of
+    /// [[TrajectoryWriter::set_every]].
+    pub fn record(
+        &mut self,
+        step: u64,
+        time: f64,
+        entities: &[(u64, Entity)],
+    ) -> Result<bool, TrajectoryError> {
+        if !step.is_multiple_of(self.every) {
+            return Ok(false);
+        }
+        let (ids, entities) = entities
+            .iter()
+            .filter(|(id, _)| self.ids.as_ref().is_none_or(|ids| ids.contains(id)))
+            .cloned()
+            .unzip();
+        self.frames.push(Frame {
+            step,
+            time,
+            ids,
+            entities,
+        });
+        if self.frames.len() >= self.chunk_frames {
+            self.write_chunk()?;
+        }
+        Ok(true)
+    }
+
+    /// Records the entities of `tree` at its current step and time, giving each the ID `id`
+    /// returns for it. Test particles are not recorded.
+    pub fn record_tree<T, F, I>(
+        &mut self,
+        tree: &GravTree<T, F>,
+        id: I,
+    ) -> Result<bool, TrajectoryError>
+    where
+        T: AsEntity + Responsive + Clone + Send + Sync,
+        F: ForceLaw,
+        I: Fn(&T) -> u64,
+    {
+        if !tree.get_steps().is_multiple_of(self.every) {
+            return Ok(false);
+        }
+        let entities = tree
+            .as_vec()
+            .iter()
+            .map(|x| (id(x), x.as_entity()))
+            .collect::<Vec<_>>();
+        self.record(tree.get_steps(), tree.get_time(), &entities)
+    }
+
+    /// Writes any frames which are still buffered and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, TrajectoryError> {
+        self.write_chunk()?;
+        let mut writer = self.writer.take().expect("the writer is only taken here");
+        writer.flush()?;
+        Ok(writer)
+    }
+
+    /// Encodes the buffered frames as a chunk and writes it.
+    fn write_chunk(&mut self) -> Result<(), TrajectoryError> {
+        if self.frames.is_empty() {
+            return Ok(());
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        let mut previous: HashMap<u64, usize> = HashMap::new();
+        let mut previous_frame: Option<&Frame> = None;
+        let mut words = Vec::new();
+        for frame in &self.frames {
+            encoder.write_all(&(frame.ids.len() as u64).to_le_bytes())?;
+            // IDs are XORed with the ID in the same place in the frame before
+            words.clear();
+            words.extend(frame.ids.iter().enumerate().map(|(i, id)| {
+                let before = previous_frame.and_then(|frame| frame.ids.get(i));
+                id ^ before.copied().unwrap_or(0)
+            }));
+            encoder.write_all(&shuffle(&words))?;
+            // values are XORed with the value of the same entity in the frame before
+            for field in &self.fields {
+                words.clear();
+                words.extend(frame.ids.iter().zip(&frame.entities).map(|(id, entity)| {
+                    let value = field.value(entity).to_bits();
+                    let before = previous_frame.and_then(|before| {
+                        previous
+                            .get(id)
+                            .map(|i| field.value(&before.entities[*i]).to_bits())
+                    });
+                    value ^ before.unwrap_or(0)
+                }));
+                encoder.write_all(&shuffle(&words))?;
+            }
+            previous = frame
+                .ids
+                .iter()
+                .enumerate()
+                .map(|(i, id)| (*id, i))
+                .collect();
+            previous_frame = Some(frame);
+        }
+        let body = encoder.finish()?;
+
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("the writer is only taken when finishing");
+        writer.write_all(&(self.frames.len() as u32).to_le_bytes())?;
+        for frame in &self.frames {
+            writer.write_all(&frame.step.to_le_bytes())?;
+            writer.write_all(&frame.time.to_le_bytes())?;
+        }
+        writer.write_all(&(body.len() as u64).to_le_bytes())?;
+        writer.write_all(&body)?;
+        self.frames.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for TrajectoryWriter<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.write_chunk();
+            if let Some(writer) = self.writer.as_mut() {
+                let _ = writer.flush();
+            }
+        }
+    }
+}
+
+/// Where a chunk is in a trajectory file, and the steps and times of its frames.
+struct Chunk {
+    offset: u64,
+    length: u64,
+    frames: Vec<(u64, f64)>,
+}
+
+/// Replays the frames of a trajectory file in any order.
+pub struct TrajectoryReader<R: Read + Seek> {
+    reader: R,
+    fields: Vec<Field>,
+    chunks: Vec<Chunk>,
+    /// The index of the chunk which was decoded last, and its frames.
+    decoded: Option<(usize, Vec<Frame>)>,
+}
+
+impl TrajectoryReader<BufReader<File>> {
+    /// Opens the trajectory file at `path`.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<TrajectoryReader<BufReader<File>>, TrajectoryError> {
+        TrajectoryReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> TrajectoryReader<R> {
+    /// Reads the header of the trajectory in `reader` and finds its chunks. A chunk which is cut
+    /// off by the end of the file is ignored.
+    pub fn new(mut reader: R) -> Result<TrajectoryReader<R>, TrajectoryError> {
+        let end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        let mut magic = [0; 8];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| TrajectoryError::NotATrajectory)?;
+        if &magic != MAGIC {
+            return Err(TrajectoryError::NotATrajectory);
+        }
+        let version = read_u32(&mut reader)?;
+        if version > TRAJECTORY_VERSION {
+            return Err(TrajectoryError::UnsupportedVersion(version));
+        }
+        let mut fields = Vec::new();
+        for _ in 0..read_u32(&mut reader)? {
+            let mut length = [0; 1];
+            reader.read_exact(&mut length)?;
+            let mut name = vec![0; length[0] as usize];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8_lossy(&name);
+            let field = Field::from_name(&name).ok_or_else(|| {
+                TrajectoryError::Corrupt(format!("`{}` is not a field of an entity", name))
+            })?;
+            fields.push(field);
+        }
+
+        let mut chunks = Vec::new();
+        let mut position = reader.stream_position()?;
+        // each chunk is at least its frame count and its length
+        while position + 12 <= end {
+            let count = u64::from(read_u32(&mut reader)?);
+            let frames_end = position + 4 + 16 * count + 8;
+            if frames_end > end {
+                break;
+            }
+            let mut frames = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                let step = read_u64(&mut reader)?;
+                let time = f64::from_bits(read_u64(&mut reader)?);
+                frames.push((step, time));
+            }
+            let length = read_u64(&mut reader)?;
+            if frames_end.saturating_add(length) > end {
+                break;
+            }
+            chunks.push(Chunk {
+                offset: frames_end,
+                length,
+                frames,
+            });
+            position = reader.seek(SeekFrom::Start(frames_end + length))?;
+        }
+        Ok(TrajectoryReader {
+            reader,
+            fields,
+            chunks,
+            decoded: None,
+        })
+    }
+
+    /// Returns the fields which were recorded.
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// Returns the number of frames.
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.frames.len()).sum()
+    }
+
+    /// Returns whether there are no frames.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the step and time of every frame, in the order they were recorded.
+    pub fn steps(&self) -> Vec<(u64, f64)> {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| chunk.frames.iter().copied())
+            .collect()
+    }
+
+    /// Returns the frame recorded for `step`, or `None` if that step was not recorded.
+    pub fn frame(&mut self, step: u64) -> Result<Option<Frame>, TrajectoryError> {
+        let found = self.chunks.iter().enumerate().find_map(|(i, chunk)| {
+            chunk
+                .frames
+                .iter()
+                .position(|(frame_step, _)| *frame_step == step)
+                .map(|j| (i, j))
+        });
+        match found {
+            Some((chunk, frame)) => Ok(Some(self.decode(chunk)?[frame].clone())),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the `index`th frame, counting from zero, or `None` if there are not that many
+    /// frames. Reading frames in order only decodes each chunk once.
+    pub fn frame_at(&mut self, index: usize) -> Result<Option<Frame>, TrajectoryError> {
+        let mut index = index;
+        for chunk in 0..self.chunks.len() {
+            let count = self.chunks[chunk].frames.len();
+            if index < count {
+                return Ok(Some(self.decode(chunk)?[index].clone()));
+            }
+            index -= count;
+        }
+        Ok(None)
+    }
+
+    /// Returns every frame, in the order they were recorded.
+    pub fn read_all(&mut self) -> Result<Vec<Frame>, TrajectoryError> {
+        let mut frames = Vec::with_capacity(self.len());
+        for chunk in 0..self.chunks.len() {
+            frames.extend_from_slice(self.decode(chunk)?);
+        }
+        Ok(frames)
+    }
+
+    /// Decodes the frames of a chunk, unless it was the last one decoded.
+    fn decode(&mut self, index: usize) -> Result<&[Frame], TrajectoryError> {
+        if self.decoded.as_ref().map(|(i, _)| *i) != Some(index) {
+            let chunk = &self.chunks[index];
+            self.reader.seek(SeekFrom::Start(chunk.offset))?;
+            let mut compressed = vec![0; chunk.length as usize];
+            self.reader.read_exact(&mut compressed)?;
+            let mut body = Vec::new();
+            ZlibDecoder::new(&compressed[..])
+                .read_to_end(&mut body)
+                .map_err(|error| TrajectoryError::Corrupt(error.to_string()))?;
+            let frames = decode_frames(&body, &self.fields, &chunk.frames)?;
+            self.decoded = Some((index, frames));
+        }
+        Ok(&self.decoded.as_ref().expect("the chunk was just decoded").1)
+    }
+}
+
+/// Decodes the frames of a decompressed chunk, undoing what [[TrajectoryWriter::write_chunk]]
+/// does.
+fn decode_frames(
+    body: &[u8],
+    fields: &[Field],
+    steps: &[(u64, f64)],
+) -> Result<Vec<Frame>, TrajectoryError> {
+    let mut position: usize = 0;
+    let ends_early =
+        || TrajectoryError::Corrupt("a chunk ends in the middle of a frame".to_string());
+    let mut take = |length: usize| {
+        let data = position
+            .checked_add(length)
+            .and_then(|end| body.get(position..end))
+            .ok_or_else(ends_early)?;
+        position += length;
+        Ok::<_, TrajectoryError>(data)
+    };
+    let mut frames: Vec<Frame> = Vec::with_capacity(steps.len());
+    for (step, time) in steps {
+        let mut count = [0; 8];
+        count.copy_from_slice(take(8)?);
+        // a corrupt count could overflow, so it is checked before anything is allocated for it
+        let length = usize::try_from(u64::from_le_bytes(count))
+            .ok()
+            .and_then(|count| count.checked_mul(8))
+            .ok_or_else(ends_early)?;
+        let count = length / 8;
+        let previous_frame = frames.last();
+        let ids = unshuffle(take(length)?)
+            .iter()
+            .enumerate()
+            .map(|(i, word)| {
+                let before = previous_frame.and_then(|frame| frame.ids.get(i));
+                word ^ before.copied().unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+        let previous = previous_frame.map(|frame| {
+            frame
+                .ids
+                .iter()
+                .enumerate()
+                .map(|(i, id)| (*id, i))
+                .collect::<HashMap<_, _>>()
+        });
+        let mut entities = vec![Entity::default(); count];
+        for field in fields {
+            let words = unshuffle(take(length)?);
+            for ((id, entity), word) in ids.iter().zip(entities.iter_mut()).zip(words) {
+                let before = previous_frame
+                    .zip(previous.as_ref())
+                    .and_then(|(frame, map)| {
+                        map.get(id)
+                            .map(|i| field.value(&frame.entities[*i]).to_bits())
+                    });
+                field.set_value(entity, f64::from_bits(word ^ before.unwrap_or(0)));
+            }
+        }
+        frames.push(Frame {
+            step: *step,
+            time: *time,
+            ids,
+            entities,
+        });
+    }
+    Ok(frames)
+}
+
+/// Returns the bytes of `words` grouped by their place in the word, least significant first, so
+/// that the bytes which are mostly zero end up next to each other.
+fn shuffle(words: &[u64]) -> Vec<u8> {
+    let mut bytes = vec![0; 8 * words.len()];
+    for (i, word) in words.iter().enumerate() {
+        for (place, byte) in word.to_le_bytes().iter().enumerate() {
+            bytes[place * words.len() + i] = *byte;
+        }
+    }
+    bytes
+}
+
+/// Undoes [[shuffle]].
+fn unshuffle(bytes: &[u8]) -> Vec<u64> {
+    let count = bytes.len() / 8;
+    (0..count)
+        .map(|i| {
+            let mut word = [0; 8];
+            for (place, byte) in word.iter_mut().enumerate() {
+                *byte = bytes[place * count + i];
+            }
+            u64::from_le_bytes(word)
+        })
+        .collect()
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, TrajectoryError> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, TrajectoryError> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
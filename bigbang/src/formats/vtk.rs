@@ -0,0 +1,329 @@
+//! This module writes the state of a [[GravTree]] in the formats of the
+//! [Visualization Toolkit](https://vtk.org/), so that runs can be looked at in ParaView.
+//!
+//! [[write_particles]] writes the entities of a tree as PolyData vertices, with their velocity,
+//! mass, radius and the gravitational acceleration the tree exerts on them as point data.
+//! [[write_cells]] writes the bounding box of every node of the tree as an UnstructuredGrid of
+//! hexahedra, with each node's depth and total mass as cell data. Both can be written in the
+//! legacy format or the XML format, and a time series of XML files can be tied together with a
+//! [[Collection]].
+//!
+//! Everything is written as ASCII, which is larger than binary but easy to check by eye. Numbers
+//! are written in scientific notation with as many digits as it takes to read them back exactly,
+//! so very large and very small values stay short.
+
+use crate::force_laws::ForceLaw;
+use crate::{AsEntity, GravTree, Responsive};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The VTK hexahedron cell type.
+const HEXAHEDRON: u8 = 12;
+
+/// Which of VTK's file formats to write.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VtkFormat {
+    /// The legacy `.vtk` format.
+    Legacy,
+    /// The XML formats, `.vtp` for particles and `.vtu` for cells.
+    Xml,
+}
+
+/// A named array of values with one or three components per point or cell.
+struct DataArray {
+    name: &'static str,
+    components: usize,
+    values: Vec<f64>,
+}
+
+/// Writes the entities of `tree` to `path` as PolyData vertices, with `velocity`, `mass`,
+/// `radius` and `acceleration` point data. The acceleration is that of the tree's force law
+/// alone; external fields and drag are left out. Test particles are not written.
+pub fn write_particles<T, F, P>(path: P, tree: &GravTree<T, F>, format: VtkFormat) -> io::Result<()>
+where
+    T: AsEntity + Responsive + Clone + Send + Sync,
+    F: ForceLaw,
+    P: AsRef<Path>,
+{
+    let accelerations = tree.accelerations();
+    let mut points = Vec::with_capacity(3 * accelerations.len());
+    let mut arrays = vec![
+        DataArray::new("velocity", 3),
+        DataArray::new("mass", 1),
+        DataArray::new("radius", 1),
+        DataArray::new("acceleration", 3),
+    ];
+    for (entity, acceleration) in &accelerations {
+        points.extend_from_slice(&[entity.x, entity.y, entity.z]);
+        arrays[0]
+            .values
+            .extend_from_slice(&[entity.vx, entity.vy, entity.vz]);
+        arrays[1].values.push(entity.mass);
+        arrays[2].values.push(entity.radius);
+        arrays[3]
+            .values
+            .extend_from_slice(&[acceleration.0, acceleration.1, acceleration.2]);
+    }
+    let count = accelerations.len();
+    let mut out = String::new();
+    match format {
+        VtkFormat::Legacy => {
+            legacy_header(&mut out, tree.get_time(), "POLYDATA", &points);
+            writeln!(out, "VERTICES {} {}", count, 2 * count).unwrap();
+            for i in 0..count {
+                writeln!(out, "1 {}", i).unwrap();
+            }
+            legacy_data(&mut out, "POINT_DATA", count, &arrays);
+        }
+        VtkFormat::Xml => {
+            xml_header(&mut out, "PolyData");
+            writeln!(
+                out,
+                "    <Piece NumberOfPoints=\"{}\" NumberOfVerts=\"{}\" NumberOfLines=\"0\" \
+                 NumberOfStrips=\"0\" NumberOfPolys=\"0\">",
+                count, count
+            )
+            .unwrap();
+            xml_data(&mut out, "PointData", &arrays);
+            xml_points(&mut out, &points);
+            out.push_str("      <Verts>\n");
+            xml_array(&mut out, "Int64", "connectivity", 1, 0..count);
+            xml_array(&mut out, "Int64", "offsets", 1, 1..=count);
+            out.push_str("      </Verts>\n");
+            xml_footer(&mut out, "PolyData");
+        }
+    }
+    fs::write(path, out)
+}
+
+/// Writes the bounding box of every node of `tree` to `path` as hexahedra, with the `depth` of
+/// each node below the root and the total `mass` under it as cell data.
+pub fn write_cells<T, F, P>(path: P, tree: &GravTree<T, F>, format: VtkFormat) -> io::Result<()>
+where
+    T: AsEntity + Responsive + Clone + Send + Sync,
+    F: ForceLaw,
+    P: AsRef<Path>,
+{
+    // the root of the tree is a phantom parent of the real root
+    let cells = tree
+        .root
+        .left
+        .as_ref()
+        .map(|root| root.cells(0))
+        .unwrap_or_default();
+    let mut points = Vec::with_capacity(24 * cells.len());
+    let mut arrays = vec![DataArray::new("depth", 1), DataArray::new("mass", 1)];
+    for cell in &cells {
+        let (min, max) = (cell.min, cell.max);
+        // the corners of the bottom face and then the top face, both counterclockwise
+        for z in &[min.2, max.2] {
+            for (x, y) in &[
+                (min.0, min.1),
+                (max.0, min.1),
+                (max.0, max.1),
+                (min.0, max.1),
+            ] {
+                points.extend_from_slice(&[*x, *y, *z]);
+            }
+        }
+        arrays[0].values.push(cell.depth as f64);
+        arrays[1].values.push(cell.mass);
+    }
+    let count = cells.len();
+    let mut out = String::new();
+    match format {
+        VtkFormat::Legacy => {
+            legacy_header(&mut out, tree.get_time(), "UNSTRUCTURED_GRID", &points);
+            writeln!(out, "CELLS {} {}", count, 9 * count).unwrap();
+            for i in 0..count {
+                let corners = (8 * i..8 * i + 8)
+                    .map(|corner| corner.to_string())
+                    .collect::<Vec<_>>();
+                writeln!(out, "8 {}", corners.join(" ")).unwrap();
+            }
+            writeln!(out, "CELL_TYPES {}", count).unwrap();
+            for _ in 0..count {
+                writeln!(out, "{}", HEXAHEDRON).unwrap();
+            }
+            legacy_data(&mut out, "CELL_DATA", count, &arrays);
+        }
+        VtkFormat::Xml => {
+            xml_header(&mut out, "UnstructuredGrid");
+            writeln!(
+                out,
+                "    <Piece NumberOfPoints=\"{}\" NumberOfCells=\"{}\">",
+                8 * count,
+                count
+            )
+            .unwrap();
+            xml_data(&mut out, "CellData", &arrays);
+            xml_points(&mut out, &points);
+            out.push_str("      <Cells>\n");
+            xml_array(&mut out, "Int64", "connectivity", 1, 0..8 * count);
+            xml_array(&mut out, "Int64", "offsets", 1, (1..=count).map(|i| 8 * i));
+            xml_array(
+                &mut out,
+                "UInt8",
+                "types",
+                1,
+                (0..count).map(|_| HEXAHEDRON),
+            );
+            out.push_str("      </Cells>\n");
+            xml_footer(&mut out, "UnstructuredGrid");
+        }
+    }
+    fs::write(path, out)
+}
+
+/// A ParaView data collection (`.pvd`) file, which ties a time series of XML files together.
+#[derive(Clone, Default, Debug)]
+pub struct Collection {
+    datasets: Vec<(f64, String)>,
+}
+
+impl Collection {
+    pub fn new() -> Collection {
+        Collection::default()
+    }
+
+    /// Adds the file at `file` as the dataset at time `time`. Relative paths are relative to the
+    /// collection file.
+    pub fn add<P: AsRef<Path>>(&mut self, time: f64, file: P) {
+        self.datasets
+            .push((time, file.as_ref().to_string_lossy().into_owned()));
+    }
+
+    /// Writes the collection to `path`.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = String::new();
+        xml_header(&mut out, "Collection");
+        for (time, file) in &self.datasets {
+            writeln!(
+                out,
+                "    <DataSet timestep=\"{}\" group=\"\" part=\"0\" file=\"{}\"/>",
+                float(time),
+                escape(file)
+            )
+            .unwrap();
+        }
+        xml_footer(&mut out, "Collection");
+        fs::write(path, out)
+    }
+}
+
+impl DataArray {
+    fn new(name: &'static str, components: usize) -> DataArray {
+        DataArray {
+            name,
+            components,
+            values: Vec::new(),
+        }
+    }
+}
+
+fn legacy_header(out: &mut String, time: f64, dataset: &str, points: &[f64]) {
+    out.push_str("# vtk DataFile Version 3.0\n");
+    writeln!(out, "bigbang, time {}", float(&time)).unwrap();
+    out.push_str("ASCII\n");
+    writeln!(out, "DATASET {}", dataset).unwrap();
+    writeln!(out, "POINTS {} double", points.len() / 3).unwrap();
+    for point in points.chunks(3) {
+        writeln!(
+            out,
+            "{} {} {}",
+            float(&point[0]),
+            float(&point[1]),
+            float(&point[2])
+        )
+        .unwrap();
+    }
+}
+
+fn legacy_data(out: &mut String, attribute: &str, count: usize, arrays: &[DataArray]) {
+    writeln!(out, "{} {}", attribute, count).unwrap();
+    for array in arrays {
+        if array.components == 3 {
+            writeln!(out, "VECTORS {} double", array.name).unwrap();
+        } else {
+            writeln!(out, "SCALARS {} double 1", array.name).unwrap();
+            out.push_str("LOOKUP_TABLE default\n");
+        }
+        for value in array.values.chunks(array.components) {
+            let value = value.iter().map(float).collect::<Vec<_>>();
+            writeln!(out, "{}", value.join(" ")).unwrap();
+        }
+    }
+}
+
+fn xml_header(out: &mut String, kind: &str) {
+    out.push_str("<?xml version=\"1.0\"?>\n");
+    writeln!(
+        out,
+        "<VTKFile type=\"{}\" version=\"0.1\" byte_order=\"LittleEndian\">",
+        kind
+    )
+    .unwrap();
+    writeln!(out, "  <{}>", kind).unwrap();
+}
+
+fn xml_footer(out: &mut String, kind: &str) {
+    if kind != "Collection" {
+        out.push_str("    </Piece>\n");
+    }
+    writeln!(out, "  </{}>", kind).unwrap();
+    out.push_str("</VTKFile>\n");
+}
+
+fn xml_data(out: &mut String, attribute: &str, arrays: &[DataArray]) {
+    writeln!(out, "      <{}>", attribute).unwrap();
+    for array in arrays {
+        xml_array(
+            out,
+            "Float64",
+            array.name,
+            array.components,
+            array.values.iter().map(float),
+        );
+    }
+    writeln!(out, "      </{}>", attribute).unwrap();
+}
+
+fn xml_points(out: &mut String, points: &[f64]) {
+    out.push_str("      <Points>\n");
+    xml_array(out, "Float64", "position", 3, points.iter().map(float));
+    out.push_str("      </Points>\n");
+}
+
+fn xml_array<V: ToString>(
+    out: &mut String,
+    kind: &str,
+    name: &str,
+    components: usize,
+    values: impl Iterator<Item = V>,
+) {
+    let values = values.map(|value| value.to_string()).collect::<Vec<_>>();
+    writeln!(
+        out,
+        "        <DataArray type=\"{}\" Name=\"{}\" NumberOfComponents=\"{}\" format=\"ascii\">",
+        kind, name, components
+    )
+    .unwrap();
+    writeln!(out, "          {}", values.join(" ")).unwrap();
+    out.push_str("        </DataArray>\n");
+}
+
+/// Formats `value` in scientific notation, e.g. `2.5e-1`, with the fewest digits which read back as
+/// the same number.
+fn float(value: &f64) -> String {
+    format!("{:e}", value)
+}
+
+/// Escapes the characters which may not appear in an XML attribute.
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('"', "&quot;")
+}
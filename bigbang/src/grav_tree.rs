@@ -1,13 +1,35 @@
+use crate::boundaries::{Applied, Boundary, Domain, System};
+use crate::checkpoint::{self, CheckpointError, Header};
+use crate::collisions::Friction;
+use crate::cosmology::{Cosmology, TimeVariable};
+use crate::drag::Drag;
+use crate::entity::{Entity, TreeWalk};
+use crate::external_fields::ExternalField;
+use crate::force_laws::{ForceLaw, Newtonian};
+use crate::hermite::Hermite;
+use crate::integrable::Integrable;
+use crate::interaction_matrix::InteractionMatrix;
+use crate::post_newtonian::PostNewtonian;
+use crate::regularization::Regularization;
 use crate::responsive::Responsive;
+use crate::utilities::{add, center_of_mass, scale, sub};
 use crate::Node;
 use crate::{as_entity::AsEntity, entity::CalculateCollisions};
 use rayon::prelude::*;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::Arc;
 
 /// The main struct you will interact with. This is a k-d tree containing all of your gravitational
 /// entities.
+///
+/// The tree is generic over the [[ForceLaw]] its entities exert on each other, which is
+/// [[Newtonian]] gravity unless the tree is constructed with [[GravTree::with_force_law]].
 #[derive(Serialize, Deserialize)]
-pub struct GravTree<T: AsEntity + Responsive + Clone> {
+pub struct GravTree<T: AsEntity + Responsive + Clone, F: ForceLaw = Newtonian> {
     /// A GravTree consists of a root [[Node]]. A [[Node]] is a recursive binary tree data structure.
     /// Tragically must be public for now for testing reasons. Perhaps could be replaced by various
     /// getter methods later.
@@ -31,6 +53,54 @@ pub struct GravTree<T: AsEntity + Responsive + Clone> {
     theta: f64,
     /// Whether or not to calculate collisions when time stepping
     calculate_collisions: CalculateCollisions,
+    /// Whether or not to calculate the jerk and tidal tensor of every entity when time stepping.
+    derivatives: bool,
+    /// The collision groups which are currently enabled, as a bit set. Entities which are only in
+    /// disabled groups never collide. Defaults to every group being enabled.
+    collision_groups: u32,
+    /// Massless test particles. These feel the gravity of the entities in the tree, but are not
+    /// part of it and exert no gravity of their own.
+    test_particles: Vec<T>,
+    /// How each species of entity interacts with the others. If this is `None`, every entity
+    /// interacts with every other entity normally.
+    interactions: Option<InteractionMatrix>,
+    /// Background potentials whose acceleration is added to that of the tree. These are not
+    /// serialized.
+    #[serde(skip)]
+    external_fields: Vec<Arc<dyn ExternalField>>,
+    /// Velocity-dependent forces, along with the species they act on (or `None` for every
+    /// species). These are not serialized.
+    #[serde(skip)]
+    drag: Vec<(Option<usize>, Arc<dyn Drag>)>,
+    /// The simulation time, which advances by `time_step` every time step.
+    time: f64,
+    /// The number of time steps which have been taken.
+    steps: u64,
+    /// The state of the caller's random number generator, which is saved in checkpoints so that
+    /// stochastic simulations can be restarted exactly. The tree itself never uses it.
+    rng_state: Vec<u8>,
+    /// The force law the entities exert on each other.
+    law: F,
+    /// The background cosmology and time variable, if the simulation is run in comoving
+    /// coordinates.
+    cosmology: Option<(Cosmology, TimeVariable)>,
+    /// What happens to entities at the edge of the simulation, if anything.
+    boundary: Option<Boundary>,
+    /// The entities which were removed by the boundary during the last time step.
+    #[serde(skip)]
+    escapers: Vec<T>,
+    /// Post-Newtonian corrections for close pairs, if enabled.
+    post_newtonian: Option<PostNewtonian>,
+    /// The settings for regularising tight binaries, if enabled.
+    regularization: Option<Regularization>,
+    /// The settings for the Hermite integrator, if the tree integrates entities with it.
+    hermite: Option<Hermite>,
+    /// The contact friction the tree spins entities up with, if enabled.
+    friction: Option<Friction>,
+    /// Moves an entity to a new state, for when the tree integrates entities itself instead of
+    /// letting them respond. This is only set by methods which require [[Integrable]].
+    #[serde(skip)]
+    integrate: Option<fn(&T, &Entity) -> T>,
 }
 
 impl<T: AsEntity + Responsive + Clone + Send + Sync> GravTree<T> {
@@ -44,42 +114,101 @@ impl<T: AsEntity + Responsive + Clone + Send + Sync> GravTree<T> {
     where
         T: AsEntity,
     {
-        let size_of_vec = pts.len();
-        // Handle the case where a grav tree is initialized without any points...
-        if size_of_vec == 0 {
-            return GravTree {
-                root: Node::new(),
-                number_of_entities: size_of_vec,
-                time_step,
-                max_entities,
-                theta,
-                calculate_collisions,
-            };
-        }
+        GravTree::with_force_law(
+            pts,
+            time_step,
+            max_entities,
+            theta,
+            calculate_collisions,
+            Newtonian::default(),
+        )
+    }
+}
 
-        // Because of the tree's recursive gravity calculation, there needs to be a parent node
-        // that "contains" the _real_ root node. This "phantom_parent" serves no purpose other than
-        // to hold a pointer to the real root node. Perhaps not the most ideal situation for now,
-        // and can be made more elegant in the future, if need be.
-        // The real root of the tree is therefore tree.root.left
-        let mut phantom_parent = Node::new();
-        phantom_parent.left = Some(Box::new(Node::<T>::new_root_node(&pts[..], max_entities)));
-        phantom_parent.points = Some(Vec::new());
+impl<T: AsEntity + Responsive + Clone + Send + Sync, F: ForceLaw> GravTree<T, F> {
+    /// Constructs a tree in the same way as [[GravTree::new]], whose entities exert `law` on each
+    /// other instead of Newtonian gravity.
+    pub fn with_force_law(
+        pts: &[T],
+        time_step: f64,
+        max_entities: i32,
+        theta: f64,
+        calculate_collisions: CalculateCollisions,
+        law: F,
+    ) -> GravTree<T, F> {
+        let size_of_vec = pts.len();
+        let root = Node::new_phantom_root(pts, max_entities, &law);
 
         GravTree {
-            root: phantom_parent,
+            root,
             number_of_entities: size_of_vec,
             time_step,
             max_entities,
             theta,
             calculate_collisions,
+            derivatives: false,
+            collision_groups: u32::MAX,
+            test_particles: Vec::new(),
+            interactions: None,
+            external_fields: Vec::new(),
+            drag: Vec::new(),
+            time: 0.,
+            steps: 0,
+            rng_state: Vec::new(),
+            law,
+            cosmology: None,
+            boundary: None,
+            escapers: Vec::new(),
+            post_newtonian: None,
+            regularization: None,
+            hermite: None,
+            friction: None,
+            integrate: None,
         }
     }
+    /// Returns the force law the entities exert on each other.
+    pub fn get_force_law(&self) -> &F {
+        &self.law
+    }
+
     /// Sets the `theta` value of the simulation.
     pub fn set_theta(&mut self, theta: f64) {
         self.theta = theta;
     }
 
+    /// Sets whether or not the jerk and tidal tensor of every entity are calculated when time
+    /// stepping, in which case they are passed to [[Responsive::respond]] in the
+    /// [[SimulationResult]]. This is off by default, since it makes time stepping slower.
+    pub fn set_derivatives(&mut self, derivatives: bool) {
+        self.derivatives = derivatives;
+    }
+
+    /// Returns whether or not the jerk and tidal tensor are calculated when time stepping.
+    pub fn get_derivatives(&self) -> bool {
+        self.derivatives
+    }
+
+    /// Sets which collision groups are enabled, as a bit set. See [[Entity]] for how collision
+    /// groups and masks work.
+    pub fn set_collision_groups(&mut self, groups: u32) {
+        self.collision_groups = groups;
+    }
+
+    /// Enables every collision group in `groups`, leaving the others as they were.
+    pub fn enable_collision_groups(&mut self, groups: u32) {
+        self.collision_groups |= groups;
+    }
+
+    /// Disables every collision group in `groups`, leaving the others as they were.
+    pub fn disable_collision_groups(&mut self, groups: u32) {
+        self.collision_groups &= !groups;
+    }
+
+    /// Returns the collision groups which are currently enabled, as a bit set.
+    pub fn get_collision_groups(&self) -> u32 {
+        self.collision_groups
+    }
+
     /// Traverses the tree and returns a vector of all entities in the tree.
     pub fn as_vec(&self) -> Vec<T> {
         let node = self.root.clone();
@@ -100,6 +229,116 @@ impl<T: AsEntity + Responsive + Clone + Send + Sync> GravTree<T> {
         }
         to_return
     }
+    /// Sets the [[InteractionMatrix]] which scales or disables gravity and collisions between each
+    /// pair of species. Pass `None` to have every species interact normally again.
+    pub fn set_interaction_matrix(&mut self, interactions: Option<InteractionMatrix>) {
+        self.interactions = interactions;
+    }
+
+    /// Returns the [[InteractionMatrix]] of the simulation, if it has one.
+    pub fn get_interaction_matrix(&self) -> Option<&InteractionMatrix> {
+        self.interactions.as_ref()
+    }
+
+    /// Adds a background potential to the simulation, whose acceleration is added to the
+    /// gravitational acceleration of every entity and test particle. Several fields may be added,
+    /// in which case they are summed. See [[crate::external_fields]] for the built-in fields.
+    pub fn add_external_field<E: ExternalField + 'static>(&mut self, field: E) {
+        self.external_fields.push(Arc::new(field));
+    }
+
+    /// Removes all of the external fields from the simulation.
+    pub fn clear_external_fields(&mut self) {
+        self.external_fields.clear();
+    }
+
+    /// Adds a velocity-dependent force, such as gas drag or damping, which acts on every entity and
+    /// test particle. See [[crate::drag]] for the built-in forces.
+    pub fn add_drag<D: Drag + 'static>(&mut self, drag: D) {
+        self.drag.push((None, Arc::new(drag)));
+    }
+
+    /// Adds a velocity-dependent force which only acts on entities of species `species`.
+    pub fn add_species_drag<D: Drag + 'static>(&mut self, species: usize, drag: D) {
+        self.drag.push((Some(species), Arc::new(drag)));
+    }
+
+    /// Removes all of the drag forces from the simulation.
+    pub fn clear_drag(&mut self) {
+        self.drag.clear();
+    }
+
+    /// Returns the current simulation time. This starts at zero and advances by the time step
+    /// every time step.
+    pub fn get_time(&self) -> f64 {
+        self.time
+    }
+
+    /// Sets the current simulation time, e.g. when resuming a simulation.
+    pub fn set_time(&mut self, time: f64) {
+        self.time = time;
+    }
+
+    /// Returns the number of time steps which have been taken.
+    pub fn get_steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Stores the state of your random number generator in the tree, so that it is saved in
+    /// checkpoints along with everything else. The tree itself never uses it.
+    pub fn set_rng_state(&mut self, rng_state: &[u8]) {
+        self.rng_state = rng_state.to_vec();
+    }
+
+    /// Returns the state of your random number generator, as set with
+    /// [[GravTree::set_rng_state]] or restored from a checkpoint.
+    pub fn get_rng_state(&self) -> &[u8] {
+        &self.rng_state
+    }
+
+    /// Sets the massless test particles of the simulation, replacing any existing ones. Test
+    /// particles are integrated through the gravity of the entities in the tree, but are never
+    /// inserted into it, so their mass is ignored and they do not affect the other entities or
+    /// each other. They may still collide with the entities in the tree.
+    pub fn set_test_particles(&mut self, test_particles: &[T]) {
+        self.test_particles = test_particles.to_vec();
+    }
+
+    /// Adds a single massless test particle to the simulation. See [[GravTree::set_test_particles]].
+    pub fn add_test_particle(&mut self, test_particle: T) {
+        self.test_particles.push(test_particle);
+    }
+
+    /// Adds post-Newtonian corrections to the gravity between pairs of entities which are closer
+    /// together than the corrections' threshold. Pass `None` to disable them again. In a periodic
+    /// domain, pairs are corrected at their nearest periodic image, so the threshold should be
+    /// less than half of the domain's smallest side. See [[crate::post_newtonian]].
+    ///
+    /// Panics if the tree integrates in comoving coordinates or with the Hermite integrator,
+    /// neither of which applies the corrections.
+    pub fn set_post_newtonian(&mut self, post_newtonian: Option<PostNewtonian>) {
+        if post_newtonian.is_some() {
+            let conflicts = [
+                (self.cosmology.is_some(), "comoving integration"),
+                (self.hermite.is_some(), "the Hermite integrator"),
+            ];
+            for (enabled, other) in conflicts.iter() {
+                assert_compatible("post-Newtonian corrections", *enabled, other);
+            }
+        }
+        self.post_newtonian = post_newtonian;
+    }
+
+    /// Returns the post-Newtonian corrections of the simulation, if it has them.
+    pub fn get_post_newtonian(&self) -> Option<&PostNewtonian> {
+        self.post_newtonian.as_ref()
+    }
+
+    /// Returns all of the massless test particles in the simulation.
+    pub fn test_particles(&self) -> &[T] {
+        &self.test_particles
+    }
+
     /// Gets the total number of entities contained by this tree.
     pub fn get_number_of_entities(&self) -> usize {
         self.number_of_entities
@@ -113,33 +352,738 @@ impl<T: AsEntity + Responsive + Clone + Send + Sync> GravTree<T> {
     // some memory is saved.
     // I am not sure if this will be necessary or very practical in the rust
     // implementation (I would have to implement indexing in my GravTree struct).
-    pub fn time_step(&self) -> GravTree<T> {
+    pub fn time_step(&self) -> GravTree<T, F> {
         // TODO currently there is a time when the entities are stored twice.
         // Store only accelerations perhaps?
         // First, we extract the entities out into a vector
         let post_gravity_entity_vec: Vec<T> = self.root.traverse_tree_helper();
-        // Then, we construct a new grav tree after the gravitational acceleration for each
-        // entity has been calculated.
-        GravTree::<T>::new(
-            &post_gravity_entity_vec
+        // In comoving coordinates, the kick and drift factors are the same for every entity.
+        let factors = self.cosmology.map(|(cosmology, time_variable)| {
+            let a0 = cosmology.scale_factor_at(self.time, time_variable);
+            let a1 = cosmology.scale_factor_at(self.time + self.time_step, time_variable);
+            ComovingStep {
+                a0,
+                a1,
+                kick: cosmology.kick_factor(a0, a1),
+                drift: cosmology.drift_factor(a0, a1),
+            }
+        });
+        let (entities, test_particles) = match (&self.hermite, &factors) {
+            (Some(hermite), None) => self.hermite_step(hermite, &post_gravity_entity_vec),
+            _ => self.step_all(&post_gravity_entity_vec, factors.as_ref()),
+        };
+        // Then, we construct a new grav tree out of the entities which are still inside the
+        // boundary.
+        let (entities, test_particles, escapers) = self.apply_boundary(entities, test_particles);
+        let mut tree = self.rebuild(&entities);
+        tree.test_particles = test_particles;
+        tree.escapers = escapers;
+        tree.time = self.time + self.time_step;
+        tree.steps = self.steps + 1;
+        tree
+    }
+
+    /// Moves every entity and test particle through the time step, by letting them respond to the
+    /// tree or, in comoving coordinates, with `factors`. Regularised pairs are moved on their own.
+    fn step_all(
+        &self,
+        post_gravity_entity_vec: &[T],
+        factors: Option<&ComovingStep>,
+    ) -> (Vec<T>, Vec<T>) {
+        // Bound close pairs are integrated on their own, and everything else feels each of them
+        // as a single body at its center of mass.
+        let pairs = match (&self.regularization, factors) {
+            (Some(regularization), None) => regularization.find_pairs(
+                &post_gravity_entity_vec
+                    .iter()
+                    .map(|x| x.as_entity())
+                    .collect::<Vec<_>>(),
+                self.max_entities,
+            ),
+            _ => Vec::new(),
+        };
+        // Each entity is walked through the tree with its index in the slice that tree was built
+        // from, so that it can leave itself out of its own jerk and tidal tensor.
+        let collapsed;
+        let (root, origins) = if pairs.is_empty() {
+            (&self.root, self.root.traverse_indices())
+        } else {
+            collapsed = self.collapse_pairs(post_gravity_entity_vec, &pairs);
+            (&collapsed, (0..post_gravity_entity_vec.len()).collect())
+        };
+        let mut stepped_pairs: Vec<Option<T>> = vec![None; post_gravity_entity_vec.len()];
+        let stepped = pairs
+            .par_iter()
+            .map(|(i, j)| {
+                self.step_pair(&post_gravity_entity_vec[*i], &post_gravity_entity_vec[*j])
+            })
+            .collect::<Vec<_>>();
+        for ((i, j), (a, b)) in pairs.iter().zip(stepped) {
+            stepped_pairs[*i] = Some(a);
+            stepped_pairs[*j] = Some(b);
+        }
+        let step = |x: &T, origin: Option<usize>| match factors {
+            Some(factors) => self.comoving_step_entity(x, factors),
+            None => self.step_entity(x, root, origin),
+        };
+        let entities = post_gravity_entity_vec
+            .par_iter()
+            .zip(stepped_pairs.into_par_iter())
+            .zip(origins.into_par_iter())
+            .map(|((x, stepped), origin)| stepped.unwrap_or_else(|| step(x, Some(origin))))
+            .collect();
+        // Test particles are moved by the same tree, but are not a part of it.
+        (
+            entities,
+            self.test_particles
                 .par_iter()
-                .map(|x| {
-                    let x_entity = x.as_entity();
-                    let accel = match self.calculate_collisions {
-                        CalculateCollisions::Yes => {
-                            x_entity.get_acceleration_and_collisions(&self.root, self.theta)
-                        }
-                        CalculateCollisions::No => {
-                            x_entity.get_acceleration_without_collisions(&self.root, self.theta)
-                        }
+                .map(|x| step(x, None))
+                .collect(),
+        )
+    }
+
+    /// Returns every entity in the tree along with the gravitational acceleration the tree exerts
+    /// on it. External fields and drag are left out.
+    pub(crate) fn accelerations(&self) -> Vec<(Entity, (f64, f64, f64))> {
+        self.as_vec()
+            .par_iter()
+            .map(|x| {
+                let entity = x.as_entity();
+                let acceleration = entity
+                    .get_acceleration_without_collisions(&self.root, &self.walk())
+                    .gravitational_acceleration;
+                (entity, acceleration)
+            })
+            .collect()
+    }
+
+    /// Returns the settings for walking this tree.
+    fn walk(&self) -> TreeWalk<'_, F> {
+        TreeWalk {
+            law: &self.law,
+            theta: self.theta,
+            collision_groups: self.collision_groups,
+            interactions: self.interactions.as_ref(),
+            periodic: self.boundary.as_ref().and_then(Boundary::periodic_size),
+            derivatives: self.derivatives,
+            origin: None,
+        }
+    }
+
+    /// Calculates the gravitational acceleration (and collisions, if enabled) acting on a single
+    /// entity from the tree under `root`, and returns the entity's response to it. `origin` is the
+    /// entity's index in the slice the tree was built from, if it is one of the tree's entities.
+    fn step_entity(&self, x: &T, root: &Node<T>, origin: Option<usize>) -> T {
+        let x_entity = x.as_entity();
+        let walk = TreeWalk {
+            origin,
+            ..self.walk()
+        };
+        let mut accel = match self.calculate_collisions {
+            CalculateCollisions::Yes => x_entity.get_acceleration_and_collisions(root, &walk),
+            CalculateCollisions::No => x_entity.get_acceleration_without_collisions(root, &walk),
+        };
+        let position = (x_entity.x, x_entity.y, x_entity.z);
+        if let Some(post_newtonian) = &self.post_newtonian {
+            // In a periodic domain, neighbours across the edge are found at their nearest image,
+            // by searching around each image of the entity itself. The entity's own correction is
+            // zero, as it has no separation from itself.
+            let images = match walk.periodic {
+                None => vec![(0., 0., 0.)],
+                Some((size_x, size_y, size_z)) => (0..27)
+                    .map(|i| {
+                        let offset = |index: i32, size: f64| (index % 3 - 1) as f64 * size;
+                        (
+                            offset(i, size_x),
+                            offset(i / 3, size_y),
+                            offset(i / 9, size_z),
+                        )
+                    })
+                    .collect(),
+            };
+            let mut partners = Vec::new();
+            for image in images {
+                let around = sub(&position, &image);
+                partners.clear();
+                root.entities_within(around, post_newtonian.threshold, &mut partners);
+                for other in &partners {
+                    let other = other.as_entity();
+                    let other = Entity {
+                        x: other.x + image.0,
+                        y: other.y + image.1,
+                        z: other.z + image.2,
+                        ..other
                     };
-                    x.respond(accel, self.time_step)
+                    let (ax, ay, az) = post_newtonian.correction(&x_entity, &other);
+                    accel.gravitational_acceleration.0 += ax;
+                    accel.gravitational_acceleration.1 += ay;
+                    accel.gravitational_acceleration.2 += az;
+                }
+            }
+        }
+        for field in &self.external_fields {
+            let (ax, ay, az) = field.acceleration(position, self.time);
+            accel.gravitational_acceleration.0 += ax;
+            accel.gravitational_acceleration.1 += ay;
+            accel.gravitational_acceleration.2 += az;
+            accel.external_potential += field.potential(position, self.time);
+        }
+        for (species, drag) in &self.drag {
+            if species.is_none() || *species == Some(x_entity.species) {
+                let (ax, ay, az) = drag.acceleration(&x_entity, self.time);
+                accel.gravitational_acceleration.0 += ax;
+                accel.gravitational_acceleration.1 += ay;
+                accel.gravitational_acceleration.2 += az;
+            }
+        }
+        // With friction, the tree spins the entity up itself from the torque at each contact,
+        // just as respond moves it with the acceleration.
+        let spin = self.friction.map(|friction| {
+            let (_, angular_acceleration) =
+                accel.friction(x, friction.stiffness, friction.coefficient);
+            x_entity.spin(angular_acceleration, self.time_step)
+        });
+        let responded = x.respond(accel, self.time_step);
+        match (spin, self.integrate) {
+            (Some((wx, wy, wz)), Some(integrate)) => {
+                let state = Entity {
+                    wx,
+                    wy,
+                    wz,
+                    ..responded.as_entity()
+                };
+                integrate(&responded, &state)
+            }
+            _ => responded,
+        }
+    }
+
+    /// Kicks and then drifts a single entity in comoving coordinates. Only the force of the tree
+    /// itself is used; external fields, drag and collisions are ignored.
+    fn comoving_step_entity(&self, x: &T, factors: &ComovingStep) -> T {
+        let integrate = self
+            .integrate
+            .expect("comoving integration requires an Integrable type");
+        let mut state = x.as_entity();
+        let accel = state
+            .get_acceleration_without_collisions(&self.root, &self.walk())
+            .gravitational_acceleration;
+        // the canonical momentum, p = a v
+        let momentum = add(
+            &scale(&(state.vx, state.vy, state.vz), factors.a0),
+            &scale(&accel, factors.kick),
+        );
+        let position = add(
+            &(state.x, state.y, state.z),
+            &scale(&momentum, factors.drift),
+        );
+        let velocity = scale(&momentum, 1. / factors.a1);
+        state.x = position.0;
+        state.y = position.1;
+        state.z = position.2;
+        state.vx = velocity.0;
+        state.vy = velocity.1;
+        state.vz = velocity.2;
+        integrate(x, &state)
+    }
+
+    /// Integrates every entity and test particle through the time step with the Hermite
+    /// integrator.
+    fn hermite_step(&self, hermite: &Hermite, entities: &[T]) -> (Vec<T>, Vec<T>) {
+        let integrate = self
+            .integrate
+            .expect("the Hermite integrator requires an Integrable type");
+        let walk = TreeWalk {
+            derivatives: true,
+            ..self.walk()
+        };
+        let (states, test_particle_states) = hermite.integrate(
+            entities.iter().map(|x| x.as_entity()).collect(),
+            self.test_particles.iter().map(|x| x.as_entity()).collect(),
+            &walk,
+            self.max_entities,
+            self.time_step,
+        );
+        let moved = |(x, state): (&T, &Entity)| integrate(x, state);
+        (
+            entities.iter().zip(states.iter()).map(moved).collect(),
+            self.test_particles
+                .iter()
+                .zip(test_particle_states.iter())
+                .map(moved)
+                .collect(),
+        )
+    }
+
+    /// Returns the root of a tree in which each of the regularised pairs is collapsed onto its
+    /// center of mass, so that the rest of the system feels it as a single body.
+    fn collapse_pairs(&self, entities: &[T], pairs: &[(usize, usize)]) -> Node<T> {
+        let integrate = self
+            .integrate
+            .expect("regularization requires an Integrable type");
+        let mut collapsed = entities.to_vec();
+        for (i, j) in pairs {
+            let (a, b) = (entities[*i].as_entity(), entities[*j].as_entity());
+            let (_, (x, y, z), (vx, vy, vz)) = center_of_mass(&[a.clone(), b.clone()]);
+            let at_center = |entity: &Entity| Entity {
+                x,
+                y,
+                z,
+                vx,
+                vy,
+                vz,
+                ..entity.clone()
+            };
+            collapsed[*i] = integrate(&entities[*i], &at_center(&a));
+            collapsed[*j] = integrate(&entities[*j], &at_center(&b));
+        }
+        self.rebuild(&collapsed).root
+    }
+
+    /// Moves a regularised pair through the time step. The center of mass is moved by the
+    /// acceleration the rest of the tree exerts on the pair, and the relative orbit is integrated
+    /// with Kustaanheimo-Stiefel regularisation. Only the force of the tree itself is used;
+    /// external fields, drag and collisions are ignored.
+    fn step_pair(&self, a: &T, b: &T) -> (T, T) {
+        let integrate = self
+            .integrate
+            .expect("regularization requires an Integrable type");
+        let regularization = self
+            .regularization
+            .as_ref()
+            .expect("pairs are only found when regularising");
+        let (a_entity, b_entity) = (a.as_entity(), b.as_entity());
+        let walk = self.walk();
+        // the acceleration from everything except the other member of the pair, whose pull is
+        // taken off just as the walk added it, with the interaction matrix and periodic images
+        let external = |target: &Entity, partner: &Entity| {
+            let total = target
+                .get_acceleration_without_collisions(&self.root, &walk)
+                .gravitational_acceleration;
+            sub(&total, &target.get_entity_acceleration(partner, &walk))
+        };
+        let (a_external, b_external) = (
+            external(&a_entity, &b_entity),
+            external(&b_entity, &a_entity),
+        );
+        let total_mass = a_entity.mass + b_entity.mass;
+        let (a_share, b_share) = (a_entity.mass / total_mass, b_entity.mass / total_mass);
+
+        let (_, center, center_velocity) = center_of_mass(&[a_entity.clone(), b_entity.clone()]);
+        let center_acceleration = add(&scale(&a_external, a_share), &scale(&b_external, b_share));
+        let center = add(
+            &add(&center, &scale(&center_velocity, self.time_step)),
+            &scale(&center_acceleration, 0.5 * self.time_step * self.time_step),
+        );
+        let center_velocity = add(
+            &center_velocity,
+            &scale(&center_acceleration, self.time_step),
+        );
+        let (position, velocity) = regularization.advance_relative_orbit(
+            sub(
+                &(a_entity.x, a_entity.y, a_entity.z),
+                &(b_entity.x, b_entity.y, b_entity.z),
+            ),
+            sub(
+                &(a_entity.vx, a_entity.vy, a_entity.vz),
+                &(b_entity.vx, b_entity.vy, b_entity.vz),
+            ),
+            total_mass,
+            sub(&a_external, &b_external),
+            self.time_step,
+        );
+
+        let moved = |x: &T, entity: Entity, share: f64| {
+            let (x_position, y_position, z_position) = add(&center, &scale(&position, share));
+            let (vx, vy, vz) = add(&center_velocity, &scale(&velocity, share));
+            let state = Entity {
+                x: x_position,
+                y: y_position,
+                z: z_position,
+                vx,
+                vy,
+                vz,
+                ..entity
+            };
+            integrate(x, &state)
+        };
+        (moved(a, a_entity, b_share), moved(b, b_entity, -a_share))
+    }
+
+    /// Applies the boundary, if there is one, to the entities and test particles after they have
+    /// moved. Returns the entities and test particles which are left, and those which were
+    /// removed.
+    fn apply_boundary(&self, entities: Vec<T>, test_particles: Vec<T>) -> (Vec<T>, Vec<T>, Vec<T>) {
+        let boundary = match &self.boundary {
+            Some(boundary) => boundary,
+            None => return (entities, test_particles, Vec::new()),
+        };
+        let integrate = self
+            .integrate
+            .expect("boundaries require an Integrable type");
+        // Escapers are judged against the entities, since test particles have no mass.
+        let system = if boundary.needs_system() {
+            Some(System::new(
+                &entities.iter().map(|x| x.as_entity()).collect::<Vec<_>>(),
+            ))
+        } else {
+            None
+        };
+        let mut escapers = Vec::new();
+        let mut apply = |xs: Vec<T>| -> Vec<T> {
+            xs.into_iter()
+                .filter_map(|x| match boundary.apply(&x.as_entity(), system.as_ref()) {
+                    Applied::Unchanged => Some(x),
+                    Applied::Moved(state) => Some(integrate(&x, &state)),
+                    Applied::Removed => {
+                        escapers.push(x);
+                        None
+                    }
                 })
-                .collect::<Vec<_>>(),
+                .collect()
+        };
+        let entities = apply(entities);
+        let test_particles = apply(test_particles);
+        (entities, test_particles, escapers)
+    }
+
+    /// Constructs a new tree out of `pts` which has the same configuration as this one.
+    fn rebuild(&self, pts: &[T]) -> GravTree<T, F> {
+        let mut tree = GravTree::with_force_law(
+            pts,
             self.time_step,
             self.max_entities,
             self.theta,
             self.calculate_collisions,
+            self.law.clone(),
+        );
+        tree.collision_groups = self.collision_groups;
+        tree.derivatives = self.derivatives;
+        tree.interactions = self.interactions.clone();
+        tree.external_fields = self.external_fields.clone();
+        tree.drag = self.drag.clone();
+        tree.time = self.time;
+        tree.steps = self.steps;
+        tree.rng_state = self.rng_state.clone();
+        tree.cosmology = self.cosmology;
+        tree.boundary = self.boundary.clone();
+        tree.post_newtonian = self.post_newtonian.clone();
+        tree.regularization = self.regularization.clone();
+        tree.hermite = self.hermite.clone();
+        tree.friction = self.friction;
+        tree.integrate = self.integrate;
+        tree
+    }
+}
+
+impl<T: AsEntity + Responsive + Integrable + Clone + Send + Sync, F: ForceLaw> GravTree<T, F> {
+    /// Runs the simulation in comoving coordinates in an expanding universe described by
+    /// `cosmology`. The simulation time is then the scale factor or the cosmic time, depending on
+    /// `time_variable`, and should be set to the starting value with [[GravTree::set_time]].
+    ///
+    /// In comoving mode, positions are comoving and velocities are peculiar velocities. The tree
+    /// integrates every entity itself, so [[Responsive::respond]] is not called. See
+    /// [[crate::cosmology]] for the details.
+    ///
+    /// Panics if the tree has post-Newtonian corrections, regularisation or the Hermite
+    /// integrator, none of which comoving integration supports.
+    pub fn set_cosmology(&mut self, cosmology: Cosmology, time_variable: TimeVariable) {
+        let conflicts = [
+            (self.post_newtonian.is_some(), "post-Newtonian corrections"),
+            (self.regularization.is_some(), "regularisation"),
+            (self.hermite.is_some(), "the Hermite integrator"),
+        ];
+        for (enabled, other) in conflicts.iter() {
+            assert_compatible("comoving integration", *enabled, other);
+        }
+        self.cosmology = Some((cosmology, time_variable));
+        self.integrate = Some(T::with_state);
+    }
+
+    /// Sets what happens to entities at the edge of the simulation, or removes the boundary if
+    /// `boundary` is `None`. The boundary is applied to every entity and test particle at the end
+    /// of each time step, and the entities it removes are reported by [[GravTree::get_escapers]].
+    /// See [[crate::boundaries]].
+    ///
+    /// Panics if a reflecting or periodic domain has a side which is not positive.
+    pub fn set_boundary(&mut self, boundary: Option<Boundary>) {
+        if let Some(Err(reason)) = boundary.as_ref().map(Boundary::validate) {
+            panic!("{}", reason);
+        }
+        self.boundary = boundary;
+        self.integrate = Some(T::with_state);
+    }
+
+    /// Makes the simulation periodic in a cube of side `box_size`, with one corner at the
+    /// origin, or removes the boundary if `box_size` is `None`. This is a shorthand for
+    /// [[GravTree::set_boundary]] with a [[Boundary::Periodic]] domain.
+    pub fn set_periodic_box(&mut self, box_size: Option<f64>) {
+        self.set_boundary(box_size.map(|size| Boundary::Periodic(Domain::cube(size))));
+    }
+
+    /// Regularises tight binaries, or stops doing so if `regularization` is `None`. Bound pairs
+    /// closer together than the regularisation radius are integrated on their own with
+    /// Kustaanheimo-Stiefel regularisation, so that they do not force the time step of the
+    /// whole system down, and the rest of the system feels each pair as a single body. The tree
+    /// moves the members of these pairs itself, so [[Responsive::respond]] is not called for them.
+    /// See [[crate::regularization]].
+    ///
+    /// Panics if the tree integrates in comoving coordinates or with the Hermite integrator,
+    /// neither of which regularises pairs.
+    pub fn set_regularization(&mut self, regularization: Option<Regularization>) {
+        if regularization.is_some() {
+            let conflicts = [
+                (self.cosmology.is_some(), "comoving integration"),
+                (self.hermite.is_some(), "the Hermite integrator"),
+            ];
+            for (enabled, other) in conflicts.iter() {
+                assert_compatible("regularisation", *enabled, other);
+            }
+        }
+        self.regularization = regularization;
+        self.integrate = Some(T::with_state);
+    }
+
+    /// Integrates entities with the fourth-order Hermite integrator, with a block time step for
+    /// each entity, instead of letting them respond to the tree. Pass `None` to let them respond
+    /// again. The time step of the tree is then the longest step any entity can take. Only the
+    /// force of the tree itself is used; external fields, drag and collisions are ignored. See
+    /// [[crate::hermite]].
+    ///
+    /// Panics if the tree integrates in comoving coordinates, or has post-Newtonian corrections
+    /// or regularisation, none of which the Hermite integrator supports. Also panics if
+    /// `max_level` is greater than [[crate::hermite::MAX_LEVEL]].
+    pub fn set_hermite(&mut self, hermite: Option<Hermite>) {
+        if let Some(hermite) = &hermite {
+            if let Err(reason) = hermite.validate() {
+                panic!("{}", reason);
+            }
+            let conflicts = [
+                (self.cosmology.is_some(), "comoving integration"),
+                (self.post_newtonian.is_some(), "post-Newtonian corrections"),
+                (self.regularization.is_some(), "regularisation"),
+            ];
+            for (enabled, other) in conflicts.iter() {
+                assert_compatible("the Hermite integrator", *enabled, other);
+            }
+        }
+        self.hermite = hermite;
+        self.integrate = Some(T::with_state);
+    }
+
+    /// Spins colliding entities up with the torque of `friction` at each contact, or stops doing
+    /// so if `friction` is `None`. After each entity responds to the tree, its angular velocity is
+    /// advanced by the angular acceleration of [[SimulationResult::friction]], in place of any
+    /// spin [[Responsive::respond]] gave it. Only collisions exert friction, so this has no
+    /// effect unless the tree calculates them. The linear part of friction is still left to
+    /// [[Responsive::respond]].
+    pub fn set_friction(&mut self, friction: Option<Friction>) {
+        self.friction = friction;
+        self.integrate = Some(T::with_state);
+    }
+
+    /// Loads a simulation saved with [[GravTree::save_checkpoint]], whether or not it integrated
+    /// its entities itself.
+    pub fn load_integrable_checkpoint<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<GravTree<T, F>, CheckpointError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: Serialize + DeserializeOwned,
+    {
+        let (mut tree, integrated) = GravTree::read_checkpoint(path)?;
+        if integrated {
+            tree.integrate = Some(T::with_state);
+        }
+        Ok(tree)
+    }
+}
+
+impl<T: AsEntity + Responsive + Clone, F: ForceLaw> GravTree<T, F> {
+    /// Disables comoving integration, so that entities respond to the tree as normal again.
+    pub fn disable_cosmology(&mut self) {
+        self.cosmology = None;
+    }
+
+    /// Returns the background cosmology, if the simulation is run in comoving coordinates.
+    pub fn get_cosmology(&self) -> Option<&Cosmology> {
+        self.cosmology.as_ref().map(|(cosmology, _)| cosmology)
+    }
+
+    /// Returns the current scale factor, if the simulation is run in comoving coordinates.
+    pub fn get_scale_factor(&self) -> Option<f64> {
+        self.cosmology
+            .map(|(cosmology, time_variable)| cosmology.scale_factor_at(self.time, time_variable))
+    }
+
+    /// Returns whether or not any of the settings which make the tree integrate entities itself,
+    /// and so require [[Integrable]], are in use.
+    fn needs_integrable(&self) -> bool {
+        self.cosmology.is_some()
+            || self.boundary.is_some()
+            || self.regularization.is_some()
+            || self.hermite.is_some()
+            || self.friction.is_some()
+    }
+
+    /// Returns the settings for the Hermite integrator, if entities are integrated with it.
+    pub fn get_hermite(&self) -> Option<&Hermite> {
+        self.hermite.as_ref()
+    }
+
+    /// Returns the settings for regularising tight binaries, if they are regularised.
+    pub fn get_regularization(&self) -> Option<&Regularization> {
+        self.regularization.as_ref()
+    }
+
+    /// Returns the boundary of the simulation, if it has one.
+    pub fn get_boundary(&self) -> Option<&Boundary> {
+        self.boundary.as_ref()
+    }
+
+    /// Returns the side length of the periodic box, if the simulation is periodic in a cube. This
+    /// is a shorthand for [[GravTree::get_boundary]], and is `None` for any other boundary,
+    /// including a periodic domain whose sides differ.
+    pub fn get_periodic_box(&self) -> Option<f64> {
+        self.get_boundary()
+            .and_then(Boundary::periodic_size)
+            .filter(|size| size.0 == size.1 && size.1 == size.2)
+            .map(|size| size.0)
+    }
+
+    /// Returns the entities and test particles which were removed by the boundary during the
+    /// last time step.
+    pub fn get_escapers(&self) -> &[T] {
+        &self.escapers
+    }
+}
+
+impl<T, F> GravTree<T, F>
+where
+    T: AsEntity + Responsive + Clone + Send + Sync + Serialize + DeserializeOwned,
+    F: ForceLaw + Serialize + DeserializeOwned,
+{
+    /// Saves the whole state of the simulation to `path`, so that it can be restarted with
+    /// [[GravTree::load_checkpoint]]. External fields and drag forces are not saved. See
+    /// [[crate::checkpoint]] for the format.
+    pub fn save_checkpoint<P: AsRef<Path>>(&self, path: P) -> Result<(), CheckpointError> {
+        let header = Header {
+            time_step: self.time_step,
+            max_entities: self.max_entities,
+            theta: self.theta,
+            calculate_collisions: self.calculate_collisions,
+            derivatives: self.derivatives,
+            collision_groups: self.collision_groups,
+            interactions: self.interactions.clone(),
+            law: self.law.clone(),
+            time: self.time,
+            steps: self.steps,
+            rng_state: self.rng_state.clone(),
+            cosmology: self.cosmology,
+            boundary: self.boundary.clone(),
+            post_newtonian: self.post_newtonian.clone(),
+            regularization: self.regularization.clone(),
+            hermite: self.hermite.clone(),
+            friction: self.friction,
+            integrated: self.needs_integrable(),
+        };
+        checkpoint::write(
+            BufWriter::new(File::create(path)?),
+            &header,
+            (&self.as_vec(), &self.test_particles),
         )
     }
+
+    /// Loads a simulation saved with [[GravTree::save_checkpoint]]. If the simulation integrated
+    /// its entities itself, e.g. because it had a boundary, this fails with
+    /// [[CheckpointError::RequiresIntegrable]] and [[GravTree::load_integrable_checkpoint]] must
+    /// be used instead.
+    pub fn load_checkpoint<P: AsRef<Path>>(path: P) -> Result<GravTree<T, F>, CheckpointError> {
+        let (tree, integrated) = GravTree::read_checkpoint(path)?;
+        if integrated {
+            return Err(CheckpointError::RequiresIntegrable);
+        }
+        Ok(tree)
+    }
+
+    /// Reads a checkpoint into a tree, along with whether or not the saved tree integrated its
+    /// entities itself, in which case the caller must set `integrate` before stepping.
+    fn read_checkpoint<P: AsRef<Path>>(path: P) -> Result<(GravTree<T, F>, bool), CheckpointError> {
+        let (header, (entities, test_particles)): (Header<F>, (Vec<T>, _)) =
+            checkpoint::read(BufReader::new(File::open(path)?))?;
+        let checks = [
+            header.boundary.as_ref().map(Boundary::validate),
+            header.hermite.as_ref().map(Hermite::validate),
+        ];
+        for check in checks.iter().flatten() {
+            check.clone().map_err(CheckpointError::Corrupt)?;
+        }
+        // the same combinations the setters refuse
+        let comoving = ("comoving integration", header.cosmology.is_some());
+        let hermite = ("the Hermite integrator", header.hermite.is_some());
+        let post_newtonian = (
+            "post-Newtonian corrections",
+            header.post_newtonian.is_some(),
+        );
+        let regularization = ("regularisation", header.regularization.is_some());
+        let conflicts = [
+            (comoving, post_newtonian),
+            (comoving, regularization),
+            (comoving, hermite),
+            (hermite, post_newtonian),
+            (hermite, regularization),
+        ];
+        for ((setting, enabled), (other, other_enabled)) in conflicts.iter() {
+            if *enabled {
+                compatible(setting, *other_enabled, other).map_err(CheckpointError::Corrupt)?;
+            }
+        }
+        let mut tree = GravTree::with_force_law(
+            &entities,
+            header.time_step,
+            header.max_entities,
+            header.theta,
+            header.calculate_collisions,
+            header.law,
+        );
+        tree.derivatives = header.derivatives;
+        tree.collision_groups = header.collision_groups;
+        tree.test_particles = test_particles;
+        tree.interactions = header.interactions;
+        tree.time = header.time;
+        tree.steps = header.steps;
+        tree.rng_state = header.rng_state;
+        tree.cosmology = header.cosmology;
+        tree.boundary = header.boundary;
+        tree.post_newtonian = header.post_newtonian;
+        tree.regularization = header.regularization;
+        tree.hermite = header.hermite;
+        tree.friction = header.friction;
+        Ok((tree, header.integrated))
+    }
+}
+
+/// The scale factors at the start and end of a comoving time step, and its kick and drift factors.
+struct ComovingStep {
+    a0: f64,
+    a1: f64,
+    kick: f64,
+    drift: f64,
+}
+
+/// Says why not if `setting` cannot be used while `other`, which it cannot be combined with, is
+/// `enabled`.
+fn compatible(setting: &str, enabled: bool, other: &str) -> Result<(), String> {
+    if enabled {
+        return Err(format!("{} cannot be combined with {}", setting, other));
+    }
+    Ok(())
+}
+
+/// Panics if `setting` is being enabled while `other`, which it cannot be combined with, is
+/// `enabled`.
+fn assert_compatible(setting: &str, enabled: bool, other: &str) {
+    if let Err(reason) = compatible(setting, enabled, other) {
+        panic!("{}, which this tree already uses", reason);
+    }
 }
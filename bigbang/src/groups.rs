@@ -0,0 +1,191 @@
+//! This module finds groups of entities, such as clumps and halos, with the
+//! [friends-of-friends](https://ui.adsabs.harvard.edu/abs/1985ApJ...292..371D) algorithm. See
+//! [[FriendsOfFriends]].
+//!
+//! Any two entities no further apart than the linking length are friends, and a group is every
+//! entity which can be reached from another through a chain of friends. Friends are found through
+//! a tree, and only the nodes whose bounding boxes come within the linking length of an entity are
+//! searched, so finding groups takes `O(n log n)` time.
+
+use crate::entity::Entity;
+use crate::force_laws::{ForceLaw, Newtonian};
+use crate::utilities::{center_of_mass, dot, sub, Tagged};
+use crate::{AsEntity, GravTree, Node, Responsive};
+use serde::{Deserialize, Serialize};
+
+/// The number of entities in each leaf of the tree used to find friends.
+const MAX_ENTITIES: i32 = 8;
+
+/// The settings for finding groups with the friends-of-friends algorithm.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FriendsOfFriends {
+    /// Entities no further apart than this are friends. This is often chosen as `0.2` times the
+    /// mean separation between entities.
+    pub linking_length: f64,
+    /// Groups with fewer members than this are left out.
+    pub min_members: usize,
+}
+
+/// A group of entities found by [[FriendsOfFriends]].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Group {
+    /// The indices of the entities in the group, in increasing order.
+    pub members: Vec<usize>,
+    /// The total mass of the group.
+    pub mass: f64,
+    pub center_of_mass: (f64, f64, f64),
+    /// The mass-weighted mean velocity of the group.
+    pub velocity: (f64, f64, f64),
+    /// The mass-weighted, one-dimensional velocity dispersion of the group about its mean
+    /// velocity, `sqrt(sum(m |v - v_mean|^2) / 3 M)`.
+    pub velocity_dispersion: f64,
+}
+
+/// The groups found by [[FriendsOfFriends]].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Groups {
+    /// The groups, from the most massive to the least.
+    pub groups: Vec<Group>,
+    /// For each entity, the index in `groups` of the group it belongs to, or `None` if it does
+    /// not belong to a group of at least the minimum size.
+    pub membership: Vec<Option<usize>>,
+}
+
+impl FriendsOfFriends {
+    /// Links entities no further apart than `linking_length`, keeping groups of two or more.
+    pub fn new(linking_length: f64) -> FriendsOfFriends {
+        FriendsOfFriends {
+            linking_length,
+            min_members: 2,
+        }
+    }
+
+    /// Links entities no further apart than `b` times the mean separation of `count` entities
+    /// spread evenly through `volume`, keeping groups of two or more.
+    pub fn from_mean_separation(b: f64, count: usize, volume: f64) -> FriendsOfFriends {
+        FriendsOfFriends::new(b * (volume / count as f64).cbrt())
+    }
+
+    /// Returns the groups of `entities`, whose members are indices into `entities`.
+    pub fn find(&self, entities: &[Entity]) -> Groups {
+        let mut parents = (0..entities.len()).collect::<Vec<_>>();
+        if !entities.is_empty() {
+            let tagged = entities
+                .iter()
+                .enumerate()
+                .map(|(index, entity)| Tagged {
+                    entity: entity.clone(),
+                    index,
+                })
+                .collect::<Vec<_>>();
+            let root = Node::new_root_node(&tagged, MAX_ENTITIES, &Newtonian::default());
+            let mut neighbours = Vec::new();
+            for (i, entity) in entities.iter().enumerate() {
+                let position = (entity.x, entity.y, entity.z);
+                neighbours.clear();
+                root.entities_within(position, self.linking_length, &mut neighbours);
+                for other in &neighbours {
+                    if other.index > i {
+                        union(&mut parents, i, other.index);
+                    }
+                }
+            }
+        }
+
+        let mut members = vec![Vec::new(); entities.len()];
+        for i in 0..entities.len() {
+            let root = find(&mut parents, i);
+            members[root].push(i);
+        }
+        let mut groups = members
+            .into_iter()
+            .filter(|members| !members.is_empty() && members.len() >= self.min_members)
+            .map(|members| Group::new(members, entities))
+            .collect::<Vec<_>>();
+        groups.sort_by(|a, b| {
+            b.mass
+                .partial_cmp(&a.mass)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.members[0].cmp(&b.members[0]))
+        });
+        let mut membership = vec![None; entities.len()];
+        for (index, group) in groups.iter().enumerate() {
+            for &member in &group.members {
+                membership[member] = Some(index);
+            }
+        }
+        Groups { groups, membership }
+    }
+
+    /// Returns the entities of `tree` with their groups, whose members are indices into the
+    /// returned entities. Test particles are left out.
+    pub fn find_in_tree<T, F>(&self, tree: &GravTree<T, F>) -> (Vec<T>, Groups)
+    where
+        T: AsEntity + Responsive + Clone + Send + Sync,
+        F: ForceLaw,
+    {
+        let bodies = tree.as_vec();
+        let entities = bodies.iter().map(|b| b.as_entity()).collect::<Vec<_>>();
+        let groups = self.find(&entities);
+        (bodies, groups)
+    }
+}
+
+impl Group {
+    /// Returns the group of the `members` of `entities`.
+    fn new(members: Vec<usize>, entities: &[Entity]) -> Group {
+        let member_entities = members
+            .iter()
+            .map(|&i| entities[i].clone())
+            .collect::<Vec<_>>();
+        let (mass, center_of_mass, velocity) = center_of_mass(&member_entities);
+        // groups of massless entities are weighted evenly, as their center of mass is
+        let weight = |e: &Entity| {
+            if mass > 0. {
+                e.mass / mass
+            } else {
+                1. / members.len() as f64
+            }
+        };
+        let variance = member_entities
+            .iter()
+            .map(|e| {
+                let relative = sub(&(e.vx, e.vy, e.vz), &velocity);
+                weight(e) * dot(&relative, &relative)
+            })
+            .sum::<f64>()
+            / 3.;
+        Group {
+            members,
+            mass,
+            center_of_mass,
+            velocity,
+            velocity_dispersion: variance.sqrt(),
+        }
+    }
+}
+
+/// Returns the representative of the set holding `i`, compressing the path to it on the way.
+fn find(parents: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut i = i;
+    while parents[i] != root {
+        let next = parents[i];
+        parents[i] = root;
+        i = next;
+    }
+    root
+}
+
+/// Merges the sets holding `a` and `b`, keeping the smaller representative.
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parents, a), find(parents, b));
+    if a < b {
+        parents[b] = a;
+    } else {
+        parents[a] = b;
+    }
+}
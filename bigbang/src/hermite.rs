@@ -0,0 +1,305 @@
+//! This module contains the fourth-order Hermite predictor-corrector integrator, the standard
+//! integrator for collisional systems such as star clusters. See [[GravTree::set_hermite]].
+//!
+//! Every entity has its own time step, which is the global time step divided by a power of two
+//! (a "block" time step), chosen with the criterion of
+//! [Aarseth (1985)](https://ui.adsabs.harvard.edu/abs/1985mcp..book..377A). At each block step,
+//! every entity is predicted to the current time from its acceleration and jerk, the acceleration
+//! and jerk of the entities whose steps end there are recalculated from the predicted positions,
+//! and those entities are corrected with the Hermite interpolation of
+//! [Makino & Aarseth (1992)](https://ui.adsabs.harvard.edu/abs/1992PASJ...44..141M). The block
+//! steps always add up to exactly one global time step.
+
+use crate::entity::{Entity, TreeWalk};
+use crate::force_laws::ForceLaw;
+use crate::utilities::{add, dot, scale, sub};
+use crate::Node;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// The largest [[Hermite::max_level]] allowed. The end of a block step is counted in ticks of the
+/// smallest block step, and may lie up to two global time steps ahead, which must fit in a `u64`.
+pub const MAX_LEVEL: u32 = 62;
+
+/// The settings for the Hermite integrator.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Hermite {
+    /// The accuracy parameter of the Aarseth time step criterion. Smaller is more accurate.
+    pub eta: f64,
+    /// The accuracy parameter for the first step of each global time step, which only has the
+    /// acceleration and jerk to go on: `dt = initial_eta * |a| / |j|`.
+    pub initial_eta: f64,
+    /// The most times the global time step may be halved for a single entity. This may be at most
+    /// [[MAX_LEVEL]], as the block steps are counted in ticks of the smallest one.
+    pub max_level: u32,
+}
+
+impl Default for Hermite {
+    fn default() -> Self {
+        Hermite {
+            eta: 0.02,
+            initial_eta: 0.01,
+            max_level: 20,
+        }
+    }
+}
+
+/// An entity being integrated, with its acceleration and jerk at the end of its last step.
+struct Particle {
+    state: Entity,
+    acceleration: (f64, f64, f64),
+    jerk: (f64, f64, f64),
+    /// The time of the end of the last step, in ticks of the smallest allowed block step.
+    tick: u64,
+    /// The entity's block step is the global time step divided by `2^level`.
+    level: u32,
+    /// Whether or not the entity exerts gravity, i.e. is not a test particle.
+    exerts: bool,
+}
+
+impl Hermite {
+    /// Checks that the settings can be integrated with, and says why not if they cannot.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.max_level > MAX_LEVEL {
+            return Err(format!(
+                "the max_level of the Hermite integrator must be at most {}, not {}",
+                MAX_LEVEL, self.max_level
+            ));
+        }
+        Ok(())
+    }
+
+    /// Integrates `entities` and `test_particles` through one global time step, returning their
+    /// new states. Only the force of `walk`'s force law is used.
+    pub(crate) fn integrate<F: ForceLaw>(
+        &self,
+        entities: Vec<Entity>,
+        test_particles: Vec<Entity>,
+        walk: &TreeWalk<F>,
+        max_entities: i32,
+        time_step: f64,
+    ) -> (Vec<Entity>, Vec<Entity>) {
+        let number_of_entities = entities.len();
+        let states = entities
+            .into_iter()
+            .map(|state| (state, true))
+            .chain(test_particles.into_iter().map(|state| (state, false)))
+            .collect::<Vec<_>>();
+        let all = (0..states.len()).collect::<Vec<_>>();
+        let sources = states
+            .iter()
+            .filter(|(_, exerts)| *exerts)
+            .map(|(state, _)| state.clone())
+            .collect::<Vec<_>>();
+        let initial = forces(
+            &states
+                .iter()
+                .map(|(state, _)| state.clone())
+                .collect::<Vec<_>>(),
+            &sources,
+            &all,
+            walk,
+            max_entities,
+        );
+        let mut particles = states
+            .into_iter()
+            .zip(initial)
+            .map(|((state, exerts), (acceleration, jerk))| {
+                let (a, j) = (norm(&acceleration), norm(&jerk));
+                Particle {
+                    state,
+                    acceleration,
+                    jerk,
+                    tick: 0,
+                    level: self.level_for(self.initial_eta * a / j, time_step),
+                    exerts,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let total_ticks = 1u64 << self.max_level;
+        let tick_length = time_step / total_ticks as f64;
+        let ticks_of = |level: u32| total_ticks >> level;
+        while let Some(next_tick) = particles
+            .iter()
+            .map(|particle| particle.tick + ticks_of(particle.level))
+            .min()
+        {
+            if next_tick > total_ticks {
+                break;
+            }
+            // predict everything to the current time
+            let predicted = particles
+                .iter()
+                .map(|particle| particle.predict((next_tick - particle.tick) as f64 * tick_length))
+                .collect::<Vec<_>>();
+            let sources = predicted
+                .iter()
+                .zip(particles.iter())
+                .filter(|(_, particle)| particle.exerts)
+                .map(|(state, _)| state.clone())
+                .collect::<Vec<_>>();
+            let active = particles
+                .iter()
+                .enumerate()
+                .filter(|(_, particle)| particle.tick + ticks_of(particle.level) == next_tick)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            let new_forces = forces(&predicted, &sources, &active, walk, max_entities);
+            // and correct the entities whose steps end now
+            for (i, (acceleration, jerk)) in active.into_iter().zip(new_forces) {
+                let particle = &mut particles[i];
+                let dt = ticks_of(particle.level) as f64 * tick_length;
+                let next_dt = particle.correct(&predicted[i], acceleration, jerk, dt, self.eta);
+                let desired = self.level_for(next_dt, time_step);
+                particle.tick = next_tick;
+                // steps may only grow when the larger step would line up with the blocks
+                if desired < particle.level && next_tick % (2 * ticks_of(particle.level)) == 0 {
+                    particle.level -= 1;
+                } else if desired > particle.level {
+                    particle.level = desired;
+                }
+            }
+        }
+
+        let mut states = particles
+            .into_iter()
+            .map(|particle| particle.state)
+            .collect::<Vec<_>>();
+        let test_particles = states.split_off(number_of_entities);
+        (states, test_particles)
+    }
+
+    /// Returns the level of the largest block step no larger than `desired`.
+    fn level_for(&self, desired: f64, time_step: f64) -> u32 {
+        // this also catches a desired step of NaN, e.g. for an entity which feels nothing
+        if desired.is_nan() || desired >= time_step {
+            return 0;
+        }
+        let level = (time_step / desired).log2().ceil();
+        if level >= self.max_level as f64 {
+            self.max_level
+        } else {
+            level as u32
+        }
+    }
+}
+
+impl Particle {
+    /// Returns the state of the particle after `dt`, from its acceleration and jerk.
+    fn predict(&self, dt: f64) -> Entity {
+        let position = (self.state.x, self.state.y, self.state.z);
+        let velocity = (self.state.vx, self.state.vy, self.state.vz);
+        let (x, y, z) = add(
+            &add(&position, &scale(&velocity, dt)),
+            &add(
+                &scale(&self.acceleration, dt * dt / 2.),
+                &scale(&self.jerk, dt * dt * dt / 6.),
+            ),
+        );
+        let (vx, vy, vz) = add(
+            &add(&velocity, &scale(&self.acceleration, dt)),
+            &scale(&self.jerk, dt * dt / 2.),
+        );
+        Entity {
+            x,
+            y,
+            z,
+            vx,
+            vy,
+            vz,
+            ..self.state.clone()
+        }
+    }
+
+    /// Corrects the predicted state after a step of `dt`, given the acceleration and jerk there.
+    /// Returns the next time step from the Aarseth criterion.
+    fn correct(
+        &mut self,
+        predicted: &Entity,
+        acceleration: (f64, f64, f64),
+        jerk: (f64, f64, f64),
+        dt: f64,
+        eta: f64,
+    ) -> f64 {
+        let difference = sub(&self.acceleration, &acceleration);
+        // the second and third derivatives of the acceleration at the start of the step
+        let snap = scale(
+            &add(
+                &scale(&difference, -6.),
+                &scale(&add(&scale(&self.jerk, 4.), &scale(&jerk, 2.)), -dt),
+            ),
+            1. / (dt * dt),
+        );
+        let crackle = scale(
+            &add(
+                &scale(&difference, 12.),
+                &scale(&add(&self.jerk, &jerk), 6. * dt),
+            ),
+            1. / (dt * dt * dt),
+        );
+        let (x, y, z) = add(
+            &(predicted.x, predicted.y, predicted.z),
+            &add(
+                &scale(&snap, dt.powi(4) / 24.),
+                &scale(&crackle, dt.powi(5) / 120.),
+            ),
+        );
+        let (vx, vy, vz) = add(
+            &(predicted.vx, predicted.vy, predicted.vz),
+            &add(
+                &scale(&snap, dt.powi(3) / 6.),
+                &scale(&crackle, dt.powi(4) / 24.),
+            ),
+        );
+        self.state = Entity {
+            x,
+            y,
+            z,
+            vx,
+            vy,
+            vz,
+            ..self.state.clone()
+        };
+        self.acceleration = acceleration;
+        self.jerk = jerk;
+
+        let snap = add(&snap, &scale(&crackle, dt));
+        let (a, j, s, c) = (
+            norm(&acceleration),
+            norm(&jerk),
+            norm(&snap),
+            norm(&crackle),
+        );
+        (eta * (a * s + j * j) / (j * c + s * s)).sqrt()
+    }
+}
+
+type AccelerationAndJerk = ((f64, f64, f64), (f64, f64, f64));
+
+/// Returns the acceleration and jerk of each of `states` at `indices` from a tree of `sources`.
+fn forces<F: ForceLaw>(
+    states: &[Entity],
+    sources: &[Entity],
+    indices: &[usize],
+    walk: &TreeWalk<F>,
+    max_entities: i32,
+) -> Vec<AccelerationAndJerk> {
+    let root = Node::new_phantom_root(sources, max_entities, walk.law);
+    indices
+        .par_iter()
+        .map(|i| {
+            // the sources are the states which exert forces, which come before the rest
+            let walk = TreeWalk {
+                origin: if *i < sources.len() { Some(*i) } else { None },
+                ..*walk
+            };
+            let result = states[*i].get_acceleration_without_collisions(&root, &walk);
+            (result.gravitational_acceleration, result.jerk)
+        })
+        .collect()
+}
+
+fn norm(vector: &(f64, f64, f64)) -> f64 {
+    dot(vector, vector).sqrt()
+}
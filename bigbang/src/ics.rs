@@ -0,0 +1,819 @@
+//! This module generates the initial conditions of standard stellar systems: Plummer spheres, King
+//! models, Hernquist and NFW halos, uniform spheres and cubes, and disk galaxies, which
+//! [[parabolic_merger]] can set on a collision course.
+//!
+//! Every model implements [[InitialConditions]], whose [[InitialConditions::generate]] returns
+//! `count` equal mass [[Entity]]s drawn with a seeded random number generator, so the same seed
+//! always gives the same entities. The entities are moved into their center of mass frame, and
+//! have a radius of zero.
+//!
+//! Like [[crate::external_fields]], the models are in simulation units where the gravitational
+//! constant is `1`; use a [[crate::force_laws::Newtonian]] force law with `g: 1.`. The Plummer,
+//! King and Hernquist models draw velocities from their isotropic distribution functions, so they
+//! are in equilibrium, and the NFW halo draws them from the local velocity dispersion given by the
+//! Jeans equation, so it is in virial equilibrium. The uniform models have a chosen virial ratio,
+//! which can be measured with [[virial_ratio]].
+
+use crate::utilities::to_center_of_mass_frame;
+use crate::Entity;
+use rayon::prelude::*;
+use std::f64::consts::PI;
+
+/// The virial ratio of [[UniformSphere::cold_collapse]]. It is small enough for the sphere to
+/// collapse by a large factor, but not zero, so the collapse does not focus onto a single point.
+pub const COLD_COLLAPSE_VIRIAL_RATIO: f64 = 0.05;
+
+/// The potential energy of a uniform cube of unit mass and side, in units where `G = 1`.
+const CUBE_POTENTIAL: f64 = -0.941_156_3;
+
+/// A model which entities can be drawn from.
+pub trait InitialConditions {
+    /// Returns `count` entities drawn from the model with the random seed `seed`.
+    fn generate(&self, count: usize, seed: u64) -> Vec<Entity>;
+}
+
+/// A [Plummer sphere](https://en.wikipedia.org/wiki/Plummer_model) of total mass `mass` and
+/// scale radius `scale_radius`, which holds half of its mass within `1.305 * scale_radius`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlummerSphere {
+    pub mass: f64,
+    pub scale_radius: f64,
+}
+
+impl InitialConditions for PlummerSphere {
+    fn generate(&self, count: usize, seed: u64) -> Vec<Entity> {
+        let mut rng = Rng::new(seed);
+        let (mass, a) = (self.mass, self.scale_radius);
+        let entities = (0..count)
+            .map(|_| {
+                // invert the cumulative mass, (r^3 / (r^2 + a^2)^(3/2))
+                let fraction = rng.uniform().max(f64::MIN_POSITIVE);
+                let r = a / (fraction.powf(-2. / 3.) - 1.).sqrt();
+                // Aarseth, Henon and Wielen (1974): the speed as a fraction of the escape speed
+                let q = loop {
+                    let q = rng.uniform();
+                    if 0.1 * rng.uniform() < q * q * (1. - q * q).powf(3.5) {
+                        break q;
+                    }
+                };
+                let escape = (2. * mass).sqrt() * (r * r + a * a).powf(-0.25);
+                entity(mass / count as f64, r, q * escape, &mut rng)
+            })
+            .collect();
+        to_center_of_mass_frame(entities)
+    }
+}
+
+/// A [King model](https://en.wikipedia.org/wiki/King_model) of total mass `mass`, King radius
+/// `core_radius` and dimensionless central potential `w0`, which sets how concentrated it is.
+/// Typical globular clusters have `w0` between 3 and 9. The model is truncated at its tidal
+/// radius, which [[KingModel::tidal_radius]] returns.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KingModel {
+    pub mass: f64,
+    pub core_radius: f64,
+    pub w0: f64,
+}
+
+impl KingModel {
+    /// Returns the radius at which the model ends.
+    pub fn tidal_radius(&self) -> f64 {
+        let profile = self.profile();
+        profile.last().expect("the profile is never empty").0 * self.core_radius
+    }
+
+    /// Solves Poisson's equation for the dimensionless potential `W`, in units where the King
+    /// radius, the velocity dispersion parameter and `G` are one. Returns the radius, `W` and the
+    /// mass within the radius, from the center to the tidal radius, where `W` is zero.
+    fn profile(&self) -> Vec<(f64, f64, f64)> {
+        let central_density = king_density(self.w0);
+        // W'' = -9 rho(W) / rho(W0) - 2 W' / r
+        let derivative =
+            |r: f64, w: f64, dw: f64| (dw, -9. * king_density(w) / central_density - 2. * dw / r);
+        // near the center, W = W0 - 3 r^2 / 2
+        let mut r = 1e-4;
+        let (mut w, mut dw) = (self.w0 - 1.5 * r * r, -3. * r);
+        let mut profile = vec![(0., self.w0, 0.), (r, w, -r * r * dw)];
+        while w > 0. {
+            let h = 1e-3 * (1. + r);
+            let k1 = derivative(r, w, dw);
+            let k2 = derivative(r + h / 2., w + h / 2. * k1.0, dw + h / 2. * k1.1);
+            let k3 = derivative(r + h / 2., w + h / 2. * k2.0, dw + h / 2. * k2.1);
+            let k4 = derivative(r + h, w + h * k3.0, dw + h * k3.1);
+            let next_w = w + h / 6. * (k1.0 + 2. * k2.0 + 2. * k3.0 + k4.0);
+            let next_dw = dw + h / 6. * (k1.1 + 2. * k2.1 + 2. * k3.1 + k4.1);
+            if next_w <= 0. {
+                // interpolate to the tidal radius, where W is zero
+                let t = w / (w - next_w);
+                let tidal_radius = r + t * h;
+                let tidal_dw = dw + t * (next_dw - dw);
+                profile.push((tidal_radius, 0., -tidal_radius * tidal_radius * tidal_dw));
+                break;
+            }
+            r += h;
+            w = next_w;
+            dw = next_dw;
+            profile.push((r, w, -r * r * dw));
+        }
+        profile
+    }
+}
+
+impl InitialConditions for KingModel {
+    fn generate(&self, count: usize, seed: u64) -> Vec<Entity> {
+        let mut rng = Rng::new(seed);
+        let profile = self.profile();
+        let total = profile.last().expect("the profile is never empty").2;
+        let mass_scale = self.mass / total;
+        let velocity_scale = (mass_scale / self.core_radius).sqrt();
+        let entities = (0..count)
+            .map(|_| {
+                // invert the tabulated cumulative mass
+                let enclosed = rng.uniform() * total;
+                let i = profile
+                    .partition_point(|(_, _, m)| *m < enclosed)
+                    .clamp(1, profile.len() - 1);
+                let ((r0, w0, m0), (r1, w1, m1)) = (profile[i - 1], profile[i]);
+                let t = if m1 > m0 {
+                    (enclosed - m0) / (m1 - m0)
+                } else {
+                    0.
+                };
+                let (r, w) = (r0 + t * (r1 - r0), (w0 + t * (w1 - w0)).max(0.));
+                // f(E) is proportional to exp(E) - 1, where E = W - v^2 / 2
+                let v = sample_speed(&mut rng, (2. * w).sqrt(), |v| {
+                    v * v * ((w - v * v / 2.).exp() - 1.)
+                });
+                entity(
+                    self.mass / count as f64,
+                    r * self.core_radius,
+                    v * velocity_scale,
+                    &mut rng,
+                )
+            })
+            .collect();
+        to_center_of_mass_frame(entities)
+    }
+}
+
+/// A [Hernquist](https://en.wikipedia.org/wiki/Hernquist_profile) halo or bulge of scale radius
+/// `scale_radius`, truncated at `max_radius`, with mass `mass` within `max_radius`. The
+/// velocities are drawn from the distribution function of the untruncated model, which is very
+/// close to equilibrium when `max_radius` is many scale radii.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HernquistHalo {
+    pub mass: f64,
+    pub scale_radius: f64,
+    pub max_radius: f64,
+}
+
+impl InitialConditions for HernquistHalo {
+    fn generate(&self, count: usize, seed: u64) -> Vec<Entity> {
+        let mut rng = Rng::new(seed);
+        let a = self.scale_radius;
+        let truncation = self.max_radius / (self.max_radius + a);
+        // the mass of the untruncated model, whose cumulative mass is M (r / (r + a))^2
+        let total = self.mass / (truncation * truncation);
+        let entities = (0..count)
+            .map(|_| {
+                let s = rng.uniform().sqrt() * truncation;
+                let r = a * s / (1. - s);
+                let potential = total / (r + a);
+                let v = sample_speed(&mut rng, (2. * potential).sqrt(), |v| {
+                    v * v * hernquist_distribution((potential - v * v / 2.) * a / total)
+                });
+                entity(self.mass / count as f64, r, v, &mut rng)
+            })
+            .collect();
+        to_center_of_mass_frame(entities)
+    }
+}
+
+/// A [Navarro-Frenk-White](https://en.wikipedia.org/wiki/Navarro%E2%80%93Frenk%E2%80%93White_profile)
+/// halo of scale radius `scale_radius`, truncated at the virial radius
+/// `concentration * scale_radius`, with mass `mass` within the virial radius. Note that this is
+/// not the characteristic mass which [[crate::external_fields::Nfw]] takes.
+///
+/// Velocities are drawn from an isotropic Gaussian with the velocity dispersion which solves the
+/// Jeans equation for the truncated halo, leaving out speeds above the escape speed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NfwHalo {
+    pub mass: f64,
+    pub scale_radius: f64,
+    pub concentration: f64,
+}
+
+impl NfwHalo {
+    /// Returns the velocity dispersion, in units of `mass / (scale_radius m(c))`, tabulated
+    /// against the logarithm of `r / scale_radius`, from the center out to the virial radius.
+    fn dispersion_table(&self) -> Vec<(f64, f64)> {
+        const POINTS: usize = 400;
+        let c = self.concentration;
+        let (min, max) = ((c * 1e-5).ln(), c.ln());
+        let table = (0..POINTS)
+            .map(|i| min + (max - min) * i as f64 / (POINTS - 1) as f64)
+            .map(|ln_x| {
+                let x = ln_x.exp();
+                // rho(x) m(x) / x^2, times x since this is integrated over ln x
+                (ln_x, nfw_density(x) * nfw_mass(x) / x)
+            })
+            .collect::<Vec<_>>();
+        // sigma^2 (x) = the integral of rho m / x^2 from x out to c, over rho (x)
+        let mut integral = 0.;
+        let mut dispersion = vec![(max, 0.); POINTS];
+        for i in (0..POINTS - 1).rev() {
+            let step = table[i + 1].0 - table[i].0;
+            integral += step * (table[i].1 + table[i + 1].1) / 2.;
+            let x = table[i].0.exp();
+            dispersion[i] = (table[i].0, integral / nfw_density(x));
+        }
+        dispersion
+    }
+}
+
+impl InitialConditions for NfwHalo {
+    fn generate(&self, count: usize, seed: u64) -> Vec<Entity> {
+        let mut rng = Rng::new(seed);
+        let c = self.concentration;
+        let unit = self.mass / (self.scale_radius * nfw_mass(c));
+        let table = self.dispersion_table();
+        let entities = (0..count)
+            .map(|_| {
+                // invert the cumulative mass, m(x) / m(c), by bisection
+                let enclosed = rng.uniform() * nfw_mass(c);
+                let (mut low, mut high) = (0., c);
+                for _ in 0..60 {
+                    let middle = (low + high) / 2.;
+                    if nfw_mass(middle) < enclosed {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                let x = ((low + high) / 2.).max(f64::MIN_POSITIVE);
+                let sigma = (unit * interpolate(&table, x.ln())).sqrt();
+                let escape = (2. * unit * ((1. + x).ln() / x - 1. / (1. + c))).sqrt();
+                let velocity = loop {
+                    let velocity = (
+                        sigma * rng.normal(),
+                        sigma * rng.normal(),
+                        sigma * rng.normal(),
+                    );
+                    let speed_squared =
+                        velocity.0 * velocity.0 + velocity.1 * velocity.1 + velocity.2 * velocity.2;
+                    if speed_squared < escape * escape {
+                        break velocity;
+                    }
+                };
+                let (px, py, pz) = rng.direction();
+                let r = x * self.scale_radius;
+                Entity {
+                    x: px * r,
+                    y: py * r,
+                    z: pz * r,
+                    vx: velocity.0,
+                    vy: velocity.1,
+                    vz: velocity.2,
+                    mass: self.mass / count as f64,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        to_center_of_mass_frame(entities)
+    }
+}
+
+/// A uniform sphere of mass `mass` and radius `radius`, with isotropic Gaussian velocities scaled
+/// so that the ratio of its kinetic energy to the magnitude of its potential energy is
+/// `virial_ratio`. A ratio of `0.5` is in virial equilibrium.
+///
+/// A sphere with a virial ratio of zero or a few hundredths is a cold collapse: it starts with far
+/// too little kinetic energy to support itself, and so collapses and violently relaxes. See
+/// [[UniformSphere::cold_collapse]].
+#[derive(Clone, Debug, PartialEq)]
+pub struct UniformSphere {
+    pub mass: f64,
+    pub radius: f64,
+    pub virial_ratio: f64,
+}
+
+impl UniformSphere {
+    /// Returns a cold collapse of mass `mass` and radius `radius`, with a virial ratio of
+    /// [[COLD_COLLAPSE_VIRIAL_RATIO]].
+    pub fn cold_collapse(mass: f64, radius: f64) -> UniformSphere {
+        UniformSphere {
+            mass,
+            radius,
+            virial_ratio: COLD_COLLAPSE_VIRIAL_RATIO,
+        }
+    }
+}
+
+impl InitialConditions for UniformSphere {
+    fn generate(&self, count: usize, seed: u64) -> Vec<Entity> {
+        let mut rng = Rng::new(seed);
+        let entities = (0..count)
+            .map(|_| {
+                let r = self.radius * rng.uniform().cbrt();
+                entity(self.mass / count as f64, r, 0., &mut rng)
+            })
+            .collect();
+        let potential = -0.6 * self.mass * self.mass / self.radius;
+        with_virial_ratio(entities, self.virial_ratio, potential, &mut rng)
+    }
+}
+
+/// A uniform cube of mass `mass` and side `side`, centered on the origin, with isotropic Gaussian
+/// velocities scaled so that the ratio of its kinetic energy to the magnitude of its potential
+/// energy is `virial_ratio`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UniformCube {
+    pub mass: f64,
+    pub side: f64,
+    pub virial_ratio: f64,
+}
+
+impl InitialConditions for UniformCube {
+    fn generate(&self, count: usize, seed: u64) -> Vec<Entity> {
+        let mut rng = Rng::new(seed);
+        let entities = (0..count)
+            .map(|_| Entity {
+                x: self.side * (rng.uniform() - 0.5),
+                y: self.side * (rng.uniform() - 0.5),
+                z: self.side * (rng.uniform() - 0.5),
+                mass: self.mass / count as f64,
+                ..Default::default()
+            })
+            .collect();
+        let potential = CUBE_POTENTIAL * self.mass * self.mass / self.side;
+        with_virial_ratio(entities, self.virial_ratio, potential, &mut rng)
+    }
+}
+
+/// A disk galaxy: an exponential disk, optionally with a Hernquist bulge and an NFW dark matter
+/// halo, all centered on the origin. The disk lies in the x-y plane and rotates counterclockwise
+/// seen from above, that is about the positive z axis.
+///
+/// The disk's surface density falls off as `exp(-R / disk_scale_length)`, and its vertical density
+/// as `sech^2(z / disk_scale_height)`. Each disk entity moves at the circular velocity given by
+/// the mass of every component within its radius, which is measured from the generated entities
+/// themselves. On top of that, the disk has the vertical velocity dispersion of an isothermal
+/// sheet, and a radial velocity dispersion set by the Toomre parameter `toomre_q`, treating the
+/// rotation curve as flat. The bulge and halo are drawn as their own models would draw them.
+///
+/// The entities are shared between the components in proportion to their masses, by the largest
+/// remainder method, so that every entity has close to the same mass.
+///
+/// # Panics
+/// Generating a galaxy panics if its total mass is not positive and finite, as there is then no
+/// way to share the entities out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiskGalaxy {
+    pub disk_mass: f64,
+    pub disk_scale_length: f64,
+    pub disk_scale_height: f64,
+    /// The Toomre stability parameter of the disk. Disks with `toomre_q` below one form bars and
+    /// spiral arms quickly; `1.5` is a common choice.
+    pub toomre_q: f64,
+    pub bulge: Option<HernquistHalo>,
+    pub halo: Option<NfwHalo>,
+}
+
+impl DiskGalaxy {
+    /// Returns the total mass of the galaxy.
+    pub fn mass(&self) -> f64 {
+        self.disk_mass
+            + self.bulge.as_ref().map_or(0., |bulge| bulge.mass)
+            + self.halo.as_ref().map_or(0., |halo| halo.mass)
+    }
+
+    /// Draws the positions of `count` disk entities, which are given velocities later.
+    fn disk_positions(&self, count: usize, rng: &mut Rng) -> Vec<Entity> {
+        (0..count)
+            .map(|_| {
+                // invert the cumulative mass, 1 - (1 + x) exp(-x), where x = R / scale length
+                let enclosed = rng.uniform();
+                let (mut low, mut high) = (0f64, 50.);
+                for _ in 0..60 {
+                    let middle = (low + high) / 2.;
+                    if 1. - (1. + middle) * (-middle).exp() < enclosed {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                let radius = (low + high) / 2. * self.disk_scale_length;
+                let phi = 2. * PI * rng.uniform();
+                // invert the cumulative vertical mass, (1 + tanh(z / scale height)) / 2
+                let height = (2. * rng.uniform() - 1.).atanh() * self.disk_scale_height;
+                Entity {
+                    x: radius * phi.cos(),
+                    y: radius * phi.sin(),
+                    z: height,
+                    mass: self.disk_mass / count as f64,
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+}
+
+impl InitialConditions for DiskGalaxy {
+    fn generate(&self, count: usize, seed: u64) -> Vec<Entity> {
+        let mass = self.mass();
+        assert!(
+            mass.is_finite() && mass > 0.,
+            "the mass of a disk galaxy must be positive, not {}",
+            mass
+        );
+        let mut rng = Rng::new(seed);
+        let counts = apportion(
+            count,
+            &[
+                self.disk_mass,
+                self.bulge.as_ref().map_or(0., |bulge| bulge.mass),
+                self.halo.as_ref().map_or(0., |halo| halo.mass),
+            ],
+        );
+        let (disk_count, bulge_count, halo_count) = (counts[0], counts[1], counts[2]);
+
+        let mut spheroids = Vec::with_capacity(bulge_count + halo_count);
+        if let Some(bulge) = &self.bulge {
+            spheroids.extend(bulge.generate(bulge_count, rng.next_u64()));
+        }
+        if let Some(halo) = &self.halo {
+            spheroids.extend(halo.generate(halo_count, rng.next_u64()));
+        }
+        let mut disk = to_center_of_mass_frame(self.disk_positions(disk_count, &mut rng));
+
+        // the mass within each radius, from every entity
+        let mut enclosed = disk
+            .iter()
+            .chain(&spheroids)
+            .map(|e| ((e.x * e.x + e.y * e.y + e.z * e.z).sqrt(), e.mass))
+            .collect::<Vec<_>>();
+        enclosed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        let mut mass = 0.;
+        for (_, m) in enclosed.iter_mut() {
+            mass += *m;
+            *m = mass;
+        }
+
+        let (h, z0) = (self.disk_scale_length, self.disk_scale_height);
+        for e in &mut disk {
+            let cylindrical = (e.x * e.x + e.y * e.y).sqrt();
+            let r = (cylindrical * cylindrical + e.z * e.z).sqrt();
+            let i = enclosed.partition_point(|(radius, _)| *radius < r);
+            let within = if i == 0 { 0. } else { enclosed[i - 1].1 };
+            // the radial component of the acceleration times the cylindrical radius
+            let circular = if r > 0. {
+                (within * cylindrical * cylindrical / (r * r * r)).sqrt()
+            } else {
+                0.
+            };
+            let surface_density = self.disk_mass / (2. * PI * h * h) * (-cylindrical / h).exp();
+            let vertical = (PI * surface_density * z0).sqrt();
+            // Toomre: sigma_R = Q 3.36 G Sigma / kappa, with kappa = sqrt(2) v_c / R
+            let radial = if circular > 0. {
+                self.toomre_q * 3.36 * surface_density * cylindrical
+                    / (std::f64::consts::SQRT_2 * circular)
+            } else {
+                0.
+            };
+            let tangential = radial / std::f64::consts::SQRT_2;
+            let (v_r, v_phi, v_z) = (
+                radial * rng.normal(),
+                circular + tangential * rng.normal(),
+                vertical * rng.normal(),
+            );
+            let (cos, sin) = if cylindrical > 0. {
+                (e.x / cylindrical, e.y / cylindrical)
+            } else {
+                (1., 0.)
+            };
+            e.vx = v_r * cos - v_phi * sin;
+            e.vy = v_r * sin + v_phi * cos;
+            e.vz = v_z;
+        }
+        disk.extend(spheroids);
+        to_center_of_mass_frame(disk)
+    }
+}
+
+/// Rotates `entities` about the origin, first by `inclination` about the x axis and then by
+/// `node` about the z axis, both in radians. This tilts a [[DiskGalaxy]] out of the x-y plane,
+/// with the line where it crosses the plane at the angle `node` from the x axis.
+pub fn rotate(entities: Vec<Entity>, inclination: f64, node: f64) -> Vec<Entity> {
+    let (ci, si) = (inclination.cos(), inclination.sin());
+    let (cn, sn) = (node.cos(), node.sin());
+    let turn = |(x, y, z): (f64, f64, f64)| {
+        let (y, z) = (y * ci - z * si, y * si + z * ci);
+        (x * cn - y * sn, x * sn + y * cn, z)
+    };
+    entities
+        .into_iter()
+        .map(|e| {
+            let (x, y, z) = turn((e.x, e.y, e.z));
+            let (vx, vy, vz) = turn((e.vx, e.vy, e.vz));
+            Entity {
+                x,
+                y,
+                z,
+                vx,
+                vy,
+                vz,
+                ..e
+            }
+        })
+        .collect()
+}
+
+/// Places two galaxies on a parabolic orbit about each other in the x-y plane, for a merger. The
+/// galaxies start `separation` apart, falling towards each other, and would pass within
+/// `pericenter` of each other if they were point masses. Each galaxy is first moved into its own
+/// center of mass frame, and the result is in the center of mass frame of both. Use [[rotate]] to
+/// tilt the galaxies before placing them.
+///
+/// # Panics
+/// Panics if `separation` is less than `pericenter`.
+pub fn parabolic_merger(
+    first: Vec<Entity>,
+    second: Vec<Entity>,
+    pericenter: f64,
+    separation: f64,
+) -> Vec<Entity> {
+    assert!(
+        separation >= pericenter,
+        "the galaxies must start at least as far apart as their pericenter"
+    );
+    let first = to_center_of_mass_frame(first);
+    let second = to_center_of_mass_frame(second);
+    let m1 = first.iter().map(|e| e.mass).sum::<f64>();
+    let m2 = second.iter().map(|e| e.mass).sum::<f64>();
+    let total = m1 + m2;
+
+    // the relative orbit, at the true anomaly before pericenter where the separation is reached
+    let anomaly = -(2. * pericenter / separation - 1.).acos();
+    let position = (separation * anomaly.cos(), separation * anomaly.sin(), 0.);
+    let speed = (total / (2. * pericenter)).sqrt();
+    let velocity = (-speed * anomaly.sin(), speed * (1. + anomaly.cos()), 0.);
+
+    let shift = |entities: Vec<Entity>, fraction: f64| {
+        entities.into_iter().map(move |e| Entity {
+            x: e.x + fraction * position.0,
+            y: e.y + fraction * position.1,
+            z: e.z + fraction * position.2,
+            vx: e.vx + fraction * velocity.0,
+            vy: e.vy + fraction * velocity.1,
+            vz: e.vz + fraction * velocity.2,
+            ..e
+        })
+    };
+    shift(first, -m2 / total)
+        .chain(shift(second, m1 / total))
+        .collect()
+}
+
+/// Returns the ratio of the kinetic energy of `entities` to the magnitude of their potential
+/// energy, where `G = 1`. A system in virial equilibrium has a ratio of `0.5`. The potential
+/// energy is summed over every pair of entities, so this is slow for large systems.
+pub fn virial_ratio(entities: &[Entity]) -> f64 {
+    let kinetic = entities
+        .iter()
+        .map(|e| 0.5 * e.mass * (e.vx * e.vx + e.vy * e.vy + e.vz * e.vz))
+        .sum::<f64>();
+    let potential = entities
+        .par_iter()
+        .enumerate()
+        .map(|(i, a)| {
+            entities[i + 1..]
+                .iter()
+                .map(|b| {
+                    let (dx, dy, dz) = (a.x - b.x, a.y - b.y, a.z - b.z);
+                    let r = (dx * dx + dy * dy + dz * dz).sqrt();
+                    if r == 0. {
+                        0.
+                    } else {
+                        -a.mass * b.mass / r
+                    }
+                })
+                .sum::<f64>()
+        })
+        .sum::<f64>();
+    kinetic / potential.abs()
+}
+
+/// A small, fast random number generator
+/// ([xoshiro256**](https://prng.di.unimi.it/)), which gives the same numbers for the same seed
+/// on every platform and every version of bigbang.
+pub(crate) struct Rng {
+    state: [u64; 4],
+}
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        // fill the state with SplitMix64, so that similar seeds give unrelated states
+        let mut z = seed;
+        let mut state = [0; 4];
+        for word in &mut state {
+            z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut x = z;
+            x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            *word = x ^ (x >> 31);
+        }
+        Rng { state }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        let result = self.state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.state[1] << 17;
+        self.state[2] ^= self.state[0];
+        self.state[3] ^= self.state[1];
+        self.state[1] ^= self.state[2];
+        self.state[0] ^= self.state[3];
+        self.state[2] ^= t;
+        self.state[3] = self.state[3].rotate_left(45);
+        result
+    }
+
+    /// Returns a number uniformly distributed in `[0, 1)`.
+    pub(crate) fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns a normally distributed number with a mean of zero and a standard deviation of one.
+    pub(crate) fn normal(&mut self) -> f64 {
+        let u = 1. - self.uniform();
+        (-2. * u.ln()).sqrt() * (2. * PI * self.uniform()).cos()
+    }
+
+    /// Returns a unit vector pointing in a uniformly random direction.
+    pub(crate) fn direction(&mut self) -> (f64, f64, f64) {
+        let z = 2. * self.uniform() - 1.;
+        let phi = 2. * PI * self.uniform();
+        let s = (1. - z * z).sqrt();
+        (s * phi.cos(), s * phi.sin(), z)
+    }
+}
+
+/// Returns an entity of mass `mass` at radius `r` moving at `speed`, in independent random
+/// directions.
+fn entity(mass: f64, r: f64, speed: f64, rng: &mut Rng) -> Entity {
+    let (x, y, z) = rng.direction();
+    let (vx, vy, vz) = rng.direction();
+    Entity {
+        x: x * r,
+        y: y * r,
+        z: z * r,
+        vx: vx * speed,
+        vy: vy * speed,
+        vz: vz * speed,
+        mass,
+        ..Default::default()
+    }
+}
+
+/// Shares `count` entities between components of the given masses in proportion to their masses,
+/// by the largest remainder method. The shares add up to `count` as long as the total mass is
+/// positive and finite; otherwise every quota is NaN and the shares are meaningless.
+fn apportion(count: usize, masses: &[f64]) -> Vec<usize> {
+    let total = masses.iter().sum::<f64>();
+    let quotas = masses
+        .iter()
+        .map(|mass| count as f64 * mass / total)
+        .collect::<Vec<_>>();
+    let mut shares = quotas
+        .iter()
+        .map(|quota| quota.floor() as usize)
+        .collect::<Vec<_>>();
+    let mut by_remainder = (0..masses.len()).collect::<Vec<_>>();
+    by_remainder.sort_by(|&a, &b| {
+        (quotas[b] - quotas[b].floor())
+            .partial_cmp(&(quotas[a] - quotas[a].floor()))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let left = count - shares.iter().sum::<usize>();
+    for &i in by_remainder.iter().take(left) {
+        shares[i] += 1;
+    }
+    shares
+}
+
+/// Gives `entities` isotropic Gaussian velocities whose kinetic energy is `virial_ratio` times the
+/// magnitude of `potential`, in their center of mass frame.
+fn with_virial_ratio(
+    mut entities: Vec<Entity>,
+    virial_ratio: f64,
+    potential: f64,
+    rng: &mut Rng,
+) -> Vec<Entity> {
+    for e in &mut entities {
+        e.vx = rng.normal();
+        e.vy = rng.normal();
+        e.vz = rng.normal();
+    }
+    let mut entities = to_center_of_mass_frame(entities);
+    let kinetic = entities
+        .iter()
+        .map(|e| 0.5 * e.mass * (e.vx * e.vx + e.vy * e.vy + e.vz * e.vz))
+        .sum::<f64>();
+    let factor = if kinetic > 0. {
+        (virial_ratio * potential.abs() / kinetic).sqrt()
+    } else {
+        0.
+    };
+    for e in &mut entities {
+        e.vx *= factor;
+        e.vy *= factor;
+        e.vz *= factor;
+    }
+    entities
+}
+
+/// Draws a speed between zero and `max` from the unnormalized density `density` by rejection.
+fn sample_speed<D: Fn(f64) -> f64>(rng: &mut Rng, max: f64, density: D) -> f64 {
+    if max <= 0. {
+        return 0.;
+    }
+    // find the peak of the density on a coarse grid and then a fine one around the best point
+    const POINTS: usize = 64;
+    let mut best = (0., 0.);
+    let (mut low, mut high) = (0., max);
+    for _ in 0..2 {
+        for i in 0..=POINTS {
+            let v = low + (high - low) * i as f64 / POINTS as f64;
+            let value = density(v);
+            if value > best.1 {
+                best = (v, value);
+            }
+        }
+        let spacing = (high - low) / POINTS as f64;
+        low = (best.0 - spacing).max(0.);
+        high = (best.0 + spacing).min(max);
+    }
+    let bound = 1.1 * best.1;
+    loop {
+        let v = max * rng.uniform();
+        if rng.uniform() * bound < density(v) {
+            return v;
+        }
+    }
+}
+
+/// The density of a King model at dimensionless potential `w`, up to a constant factor:
+/// `exp(w) erf(sqrt(w)) - sqrt(4 w / pi) (1 + 2 w / 3)`. This is summed as the series
+/// `2 sqrt(w / pi) sum (2 w)^n / (2 n + 1)!!` from `n = 2`, which has no cancellation.
+fn king_density(w: f64) -> f64 {
+    if w <= 0. {
+        return 0.;
+    }
+    let mut term = (2. * w) * (2. * w) / 15.;
+    let mut sum = 0.;
+    let mut n = 2.;
+    while term > 1e-17 * sum {
+        sum += term;
+        n += 1.;
+        term *= 2. * w / (2. * n + 1.);
+    }
+    2. * (w / PI).sqrt() * sum
+}
+
+/// The isotropic distribution function of a Hernquist model, up to a constant factor, in terms of
+/// the dimensionless binding energy `q^2 = E a / (G M)`, from Hernquist (1990).
+fn hernquist_distribution(q_squared: f64) -> f64 {
+    if q_squared <= 0. {
+        return 0.;
+    }
+    if q_squared >= 1. {
+        return f64::INFINITY;
+    }
+    let q = q_squared.sqrt();
+    let p = 1. - q_squared;
+    (3. * q.asin()
+        + q * p.sqrt() * (1. - 2. * q_squared) * (8. * q_squared * q_squared - 8. * q_squared - 3.))
+        / p.powf(2.5)
+}
+
+/// The density of an NFW halo at `x = r / r_s`, in units of its characteristic density.
+fn nfw_density(x: f64) -> f64 {
+    1. / (x * (1. + x) * (1. + x))
+}
+
+/// The mass of an NFW halo within `x = r / r_s`, in units of its characteristic mass.
+fn nfw_mass(x: f64) -> f64 {
+    (1. + x).ln() - x / (1. + x)
+}
+
+/// Linearly interpolates a table of `(x, y)` pairs sorted by `x`, clamping at its ends.
+fn interpolate(table: &[(f64, f64)], x: f64) -> f64 {
+    let i = table.partition_point(|(tx, _)| *tx < x);
+    if i == 0 {
+        return table[0].1;
+    }
+    if i == table.len() {
+        return table[table.len() - 1].1;
+    }
+    let ((x0, y0), (x1, y1)) = (table[i - 1], table[i]);
+    y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+}
@@ -0,0 +1,31 @@
+use crate::entity::Entity;
+
+/// Most of the time, your type decides how it moves in [[Responsive::respond]]. Some features of
+/// [[GravTree]], such as contact friction, integrate the equations of motion themselves instead,
+/// and so need to be able to move your type directly. In order to use those features, a type must
+/// implement [[Integrable]].
+///
+/// If your struct's fields are named the same way as those of [[Entity]], you can derive this
+/// trait in the same way as [[AsEntity]].
+pub trait Integrable {
+    /// Return a copy of your struct whose position, velocity and spin are taken from `state`.
+    /// Everything else about it, including its mass and radius, should be left as it is.
+    fn with_state(&self, state: &Entity) -> Self;
+}
+
+impl Integrable for Entity {
+    fn with_state(&self, state: &Entity) -> Self {
+        Entity {
+            x: state.x,
+            y: state.y,
+            z: state.z,
+            vx: state.vx,
+            vy: state.vy,
+            vz: state.vz,
+            wx: state.wx,
+            wy: state.wy,
+            wz: state.wz,
+            ..self.clone()
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+/// Describes how each species of entity interacts with every other species. Every [[Entity]] has
+/// a `species`, which is an index into this matrix.
+///
+/// Gravity is given as a scale factor on the acceleration a species feels from another. It does
+/// not have to be symmetric: a factor of `0.` for `(target, source)` means `target` does not feel
+/// the gravity of `source` at all, even if `source` still feels `target`. Collisions are either on
+/// or off for each pair of species and are always symmetric.
+///
+/// Species which are outside of the matrix interact normally with everything, so a matrix only
+/// needs to be as large as the largest species it configures.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InteractionMatrix {
+    species: usize,
+    gravity: Vec<f64>,
+    collisions: Vec<bool>,
+}
+
+impl InteractionMatrix {
+    /// Creates a matrix for `species` species where every species feels the full gravity of and
+    /// collides with every other one, i.e. the default behavior of the tree.
+    pub fn new(species: usize) -> InteractionMatrix {
+        InteractionMatrix {
+            species,
+            gravity: vec![1.; species * species],
+            collisions: vec![true; species * species],
+        }
+    }
+
+    /// Returns the number of species this matrix configures.
+    pub fn species(&self) -> usize {
+        self.species
+    }
+
+    /// Sets the factor by which the gravitational acceleration `target` feels from `source` is
+    /// scaled. Use `0.` to disable it entirely.
+    pub fn set_gravity(&mut self, target: usize, source: usize, scale: f64) {
+        let index = self.index(target, source);
+        self.gravity[index] = scale;
+    }
+
+    /// Sets the scale of gravity between two species in both directions.
+    /// See [[InteractionMatrix::set_gravity]].
+    pub fn set_mutual_gravity(&mut self, a: usize, b: usize, scale: f64) {
+        self.set_gravity(a, b, scale);
+        self.set_gravity(b, a, scale);
+    }
+
+    /// Sets whether or not entities of the two species collide with each other.
+    pub fn set_collisions(&mut self, a: usize, b: usize, collide: bool) {
+        let index = self.index(a, b);
+        self.collisions[index] = collide;
+        let index = self.index(b, a);
+        self.collisions[index] = collide;
+    }
+
+    /// Returns the factor by which the gravity `target` feels from `source` is scaled.
+    pub fn gravity(&self, target: usize, source: usize) -> f64 {
+        if target < self.species && source < self.species {
+            self.gravity[target * self.species + source]
+        } else {
+            1.
+        }
+    }
+
+    /// Returns whether or not entities of the two species collide with each other.
+    pub fn collides(&self, a: usize, b: usize) -> bool {
+        if a < self.species && b < self.species {
+            self.collisions[a * self.species + b]
+        } else {
+            true
+        }
+    }
+
+    fn index(&self, a: usize, b: usize) -> usize {
+        assert!(
+            a < self.species && b < self.species,
+            "species ({}, {}) out of range for an interaction matrix of {} species",
+            a,
+            b,
+            self.species
+        );
+        a * self.species + b
+    }
+}
@@ -0,0 +1,383 @@
+//! This module converts between Keplerian orbital elements and Cartesian positions and
+//! velocities, finds the orbital elements of the entities in a [[GravTree]], and builds planetary
+//! systems from tables of elements, such as the solar system returned by [[planets]].
+//!
+//! Elements describe the orbit of a body about a central mass, with `mu = G (M + m)` the
+//! gravitational parameter of the pair. Angles are in radians, and measured in the x-y plane from
+//! the x axis, so for the solar system the x-y plane is the ecliptic and the x axis points to the
+//! vernal equinox. Elliptic orbits have `0 <= eccentricity < 1` and a positive semi-major axis, and
+//! hyperbolic orbits have `eccentricity > 1` and a negative semi-major axis. Parabolic orbits have
+//! no finite semi-major axis, and are not supported.
+
+use crate::force_laws::ForceLaw;
+use crate::utilities::{cross, dot, scale, sub, to_center_of_mass_frame};
+use crate::{AsEntity, Entity, GravTree, Responsive};
+use std::f64::consts::PI;
+
+/// The gravitational constant in units of astronomical units, years and solar masses, `4 pi^2`.
+pub const G_SOLAR: f64 = 39.478_417_604_357_43;
+
+/// The radius of the Sun in astronomical units.
+pub const SUN_RADIUS: f64 = 4.650_5e-3;
+
+/// Below this, eccentricities and inclinations are treated as zero when finding elements, and the
+/// angles they would define are set to zero.
+const TOLERANCE: f64 = 1e-12;
+
+/// The Keplerian elements of an orbit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Elements {
+    /// `a`, which is negative for hyperbolic orbits.
+    pub semi_major_axis: f64,
+    /// `e`.
+    pub eccentricity: f64,
+    /// `i`, the angle between the orbit and the x-y plane, from `0` to `pi`.
+    pub inclination: f64,
+    /// `Ω`, the angle from the x axis to where the body crosses the x-y plane going up.
+    pub longitude_of_ascending_node: f64,
+    /// `ω`, the angle from the ascending node to the periapsis.
+    pub argument_of_periapsis: f64,
+    /// `M`, which grows uniformly in time, and is zero at periapsis.
+    pub mean_anomaly: f64,
+}
+
+impl Elements {
+    /// Returns the position and velocity of a body on this orbit relative to the central mass,
+    /// where `mu` is the gravitational parameter of the pair.
+    ///
+    /// # Panics
+    /// Panics if the orbit is parabolic, or the semi-major axis has the wrong sign for the
+    /// eccentricity.
+    pub fn to_state(&self, mu: f64) -> ((f64, f64, f64), (f64, f64, f64)) {
+        let (a, e) = (self.semi_major_axis, self.eccentricity);
+        assert!(
+            (e < 1. && a > 0.) || (e > 1. && a < 0.),
+            "an orbit with eccentricity {} cannot have a semi-major axis of {}",
+            e,
+            a
+        );
+        let true_anomaly = if e < 1. {
+            let eccentric = solve_kepler(self.mean_anomaly, e);
+            2. * ((1. + e).sqrt() * (eccentric / 2.).sin())
+                .atan2((1. - e).sqrt() * (eccentric / 2.).cos())
+        } else {
+            let hyperbolic = solve_hyperbolic_kepler(self.mean_anomaly, e);
+            2. * (((e + 1.) / (e - 1.)).sqrt() * (hyperbolic / 2.).tanh()).atan()
+        };
+        let semi_latus_rectum = a * (1. - e * e);
+        let r = semi_latus_rectum / (1. + e * true_anomaly.cos());
+        let (sin, cos) = true_anomaly.sin_cos();
+        // in the plane of the orbit, with x towards the periapsis
+        let position = (r * cos, r * sin, 0.);
+        let speed = (mu / semi_latus_rectum).sqrt();
+        let velocity = (-speed * sin, speed * (e + cos), 0.);
+        (
+            self.rotate_to_reference(position),
+            self.rotate_to_reference(velocity),
+        )
+    }
+
+    /// Returns the elements of the orbit of a body at `position` moving at `velocity` relative
+    /// to the central mass, where `mu` is the gravitational parameter of the pair.
+    ///
+    /// For circular orbits the argument of periapsis is zero and the mean anomaly is measured
+    /// from the ascending node, and for orbits in the x-y plane the longitude of the ascending
+    /// node is zero and angles are measured from the x axis.
+    pub fn from_state(position: (f64, f64, f64), velocity: (f64, f64, f64), mu: f64) -> Elements {
+        let r = dot(&position, &position).sqrt();
+        let v_squared = dot(&velocity, &velocity);
+        let momentum = cross(&position, &velocity);
+        let h = dot(&momentum, &momentum).sqrt();
+        let eccentricity_vector = scale(
+            &sub(
+                &scale(&position, v_squared - mu / r),
+                &scale(&velocity, dot(&position, &velocity)),
+            ),
+            1. / mu,
+        );
+        let e = dot(&eccentricity_vector, &eccentricity_vector).sqrt();
+        let semi_major_axis = 1. / (2. / r - v_squared / mu);
+        let inclination = (momentum.2 / h).clamp(-1., 1.).acos();
+
+        // the direction of the ascending node, or the x axis if the orbit is in the x-y plane
+        let node = (-momentum.1, momentum.0, 0.);
+        let node_length = dot(&node, &node).sqrt();
+        let (longitude_of_ascending_node, reference) = if node_length > TOLERANCE * h {
+            (
+                node.1.atan2(node.0).rem_euclid(2. * PI),
+                scale(&node, 1. / node_length),
+            )
+        } else {
+            (0., (1., 0., 0.))
+        };
+        // the direction 90 degrees ahead of the reference in the plane of the orbit
+        let ahead = cross(&scale(&momentum, 1. / h), &reference);
+        let angle = |vector: &(f64, f64, f64)| dot(vector, &ahead).atan2(dot(vector, &reference));
+        let argument_of_periapsis = if e > TOLERANCE {
+            angle(&eccentricity_vector).rem_euclid(2. * PI)
+        } else {
+            0.
+        };
+        let true_anomaly = angle(&position) - argument_of_periapsis;
+
+        let mean_anomaly = if e < 1. {
+            let eccentric = 2.
+                * ((1. - e).sqrt() * (true_anomaly / 2.).sin())
+                    .atan2((1. + e).sqrt() * (true_anomaly / 2.).cos());
+            (eccentric - e * eccentric.sin()).rem_euclid(2. * PI)
+        } else {
+            let hyperbolic =
+                2. * (((e - 1.) / (e + 1.)).sqrt() * (true_anomaly / 2.).tan()).atanh();
+            e * hyperbolic.sinh() - hyperbolic
+        };
+        Elements {
+            semi_major_axis,
+            eccentricity: e,
+            inclination,
+            longitude_of_ascending_node,
+            argument_of_periapsis,
+            mean_anomaly,
+        }
+    }
+
+    /// Returns the period of an elliptic orbit, where `mu` is the gravitational parameter of the
+    /// pair, or `None` for a hyperbolic orbit.
+    pub fn period(&self, mu: f64) -> Option<f64> {
+        if self.eccentricity < 1. {
+            Some(2. * PI * (self.semi_major_axis.powi(3) / mu).sqrt())
+        } else {
+            None
+        }
+    }
+
+    /// Rotates a vector from the plane of the orbit, with x towards the periapsis, to the
+    /// reference frame.
+    fn rotate_to_reference(&self, v: (f64, f64, f64)) -> (f64, f64, f64) {
+        let (sin_w, cos_w) = self.argument_of_periapsis.sin_cos();
+        let (sin_i, cos_i) = self.inclination.sin_cos();
+        let (sin_o, cos_o) = self.longitude_of_ascending_node.sin_cos();
+        // about z by the argument of periapsis, about x by the inclination, and about z by the
+        // longitude of the ascending node
+        let (x, y) = (v.0 * cos_w - v.1 * sin_w, v.0 * sin_w + v.1 * cos_w);
+        let (y, z) = (y * cos_i - v.2 * sin_i, y * sin_i + v.2 * cos_i);
+        (x * cos_o - y * sin_o, x * sin_o + y * cos_o, z)
+    }
+}
+
+/// A body to place in orbit about a central mass with [[planetary_system]].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Body {
+    pub mass: f64,
+    pub radius: f64,
+    pub elements: Elements,
+}
+
+/// Returns the elements of the orbit of every entity in `tree` about its most massive entity,
+/// which is the central mass, with the entity they belong to. The central mass itself is left
+/// out. `g` is the gravitational constant of the tree's force law. Test particles are left out.
+pub fn orbital_elements<T, F>(tree: &GravTree<T, F>, g: f64) -> Vec<(T, Elements)>
+where
+    T: AsEntity + Responsive + Clone + Send + Sync,
+    F: ForceLaw,
+{
+    let bodies = tree.as_vec();
+    let central = match bodies.iter().enumerate().max_by(|(_, a), (_, b)| {
+        a.as_entity()
+            .mass
+            .partial_cmp(&b.as_entity().mass)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    }) {
+        Some((i, _)) => i,
+        None => return Vec::new(),
+    };
+    let center = bodies[central].as_entity();
+    bodies
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| *i != central)
+        .map(|(_, body)| {
+            let e = body.as_entity();
+            let position = (e.x - center.x, e.y - center.y, e.z - center.z);
+            let velocity = (e.vx - center.vx, e.vy - center.vy, e.vz - center.vz);
+            let elements = Elements::from_state(position, velocity, g * (center.mass + e.mass));
+            (body, elements)
+        })
+        .collect()
+}
+
+/// Returns a central mass and `bodies` in orbit about it, in the frame where their center of mass
+/// is at rest at the origin. The central mass is the first entity, followed by the bodies in the
+/// same order. Each body's orbit is about the central mass alone, with the gravitational
+/// parameter `g (central_mass + mass)`, so the bodies are not perturbed by each other.
+pub fn planetary_system(
+    central_mass: f64,
+    central_radius: f64,
+    bodies: &[Body],
+    g: f64,
+) -> Vec<Entity> {
+    let mut entities = vec![Entity {
+        mass: central_mass,
+        radius: central_radius,
+        ..Default::default()
+    }];
+    for body in bodies {
+        let (position, velocity) = body.elements.to_state(g * (central_mass + body.mass));
+        entities.push(Entity {
+            x: position.0,
+            y: position.1,
+            z: position.2,
+            vx: velocity.0,
+            vy: velocity.1,
+            vz: velocity.2,
+            mass: body.mass,
+            radius: body.radius,
+            ..Default::default()
+        });
+    }
+    to_center_of_mass_frame(entities)
+}
+
+/// Returns the eight planets of the solar system, from Mercury to Neptune, in astronomical units,
+/// years and solar masses, with their mean elements at the J2000 epoch relative to the ecliptic
+/// from [Standish](https://ssd.jpl.nasa.gov/planets/approx_pos.html). Earth is the barycenter of
+/// the Earth and the Moon, with their combined mass.
+pub fn planets() -> Vec<Body> {
+    // a, e, I, L, longitude of perihelion, longitude of the ascending node (degrees), mass and
+    // radius
+    let table = [
+        (
+            0.387_099_27,
+            0.205_635_93,
+            7.004_979_02,
+            252.250_323_5,
+            77.457_796_28,
+            48.330_765_93,
+            1.660_1e-7,
+            1.630_8e-5,
+        ),
+        (
+            0.723_335_66,
+            0.006_776_72,
+            3.394_676_05,
+            181.979_099_5,
+            131.602_467_18,
+            76.679_842_55,
+            2.447_838_3e-6,
+            4.045_4e-5,
+        ),
+        (
+            1.000_002_61,
+            0.016_711_23,
+            -0.000_015_31,
+            100.464_571_66,
+            102.937_681_93,
+            0.,
+            3.040_43e-6,
+            4.258_8e-5,
+        ),
+        (
+            1.523_710_34,
+            0.093_394_1,
+            1.849_691_42,
+            -4.553_432_05,
+            -23.943_629_59,
+            49.559_538_91,
+            3.227_151e-7,
+            2.265_7e-5,
+        ),
+        (
+            5.202_887,
+            0.048_386_24,
+            1.304_396_95,
+            34.396_440_51,
+            14.728_479_83,
+            100.473_909_09,
+            9.547_919e-4,
+            4.673_3e-4,
+        ),
+        (
+            9.536_675_94,
+            0.053_861_79,
+            2.485_991_87,
+            49.954_244_23,
+            92.598_878_31,
+            113.662_424_48,
+            2.858_86e-4,
+            3.892_6e-4,
+        ),
+        (
+            19.189_164_64,
+            0.047_257_44,
+            0.772_637_83,
+            313.238_104_51,
+            170.954_276_3,
+            74.016_925_03,
+            4.366_24e-5,
+            1.695_3e-4,
+        ),
+        (
+            30.069_922_76,
+            0.008_590_48,
+            1.770_043_47,
+            -55.120_029_69,
+            44.964_762_27,
+            131.784_225_74,
+            5.151_389e-5,
+            1.645_9e-4,
+        ),
+    ];
+    table
+        .iter()
+        .map(|&(a, e, i, l, perihelion, node, mass, radius)| {
+            // a negative inclination is the same orbit flipped over, with the node opposite
+            let (i, node): (f64, f64) = if i < 0. { (-i, node + 180.) } else { (i, node) };
+            Body {
+                mass,
+                radius,
+                elements: Elements {
+                    semi_major_axis: a,
+                    eccentricity: e,
+                    inclination: i.to_radians(),
+                    longitude_of_ascending_node: node.to_radians().rem_euclid(2. * PI),
+                    argument_of_periapsis: (perihelion - node).to_radians().rem_euclid(2. * PI),
+                    mean_anomaly: (l - perihelion).to_radians().rem_euclid(2. * PI),
+                },
+            }
+        })
+        .collect()
+}
+
+/// Returns the Sun and the eight planets of [[planets]], in the Sun's barycentric frame, in
+/// astronomical units, years and solar masses, where the gravitational constant is
+/// [[G_SOLAR]].
+pub fn solar_system() -> Vec<Entity> {
+    planetary_system(1., SUN_RADIUS, &planets(), G_SOLAR)
+}
+
+/// Solves Kepler's equation, `M = E - e sin E`, for the eccentric anomaly `E`.
+fn solve_kepler(mean_anomaly: f64, e: f64) -> f64 {
+    let mean_anomaly = mean_anomaly.rem_euclid(2. * PI);
+    let mut eccentric = if e < 0.8 { mean_anomaly } else { PI };
+    for _ in 0..100 {
+        let step = (eccentric - e * eccentric.sin() - mean_anomaly) / (1. - e * eccentric.cos());
+        eccentric -= step;
+        if step.abs() < 1e-15 {
+            break;
+        }
+    }
+    eccentric
+}
+
+/// Solves the hyperbolic Kepler's equation, `M = e sinh H - H`, for the hyperbolic anomaly `H`.
+fn solve_hyperbolic_kepler(mean_anomaly: f64, e: f64) -> f64 {
+    let mut hyperbolic = (2. * mean_anomaly / e).asinh();
+    for _ in 0..100 {
+        let step =
+            (e * hyperbolic.sinh() - hyperbolic - mean_anomaly) / (e * hyperbolic.cosh() - 1.);
+        hyperbolic -= step;
+        if step.abs() < 1e-15 * (1. + hyperbolic.abs()) {
+            break;
+        }
+    }
+    hyperbolic
+}
@@ -5,6 +5,8 @@ mod as_entity;
 mod dimension;
 mod entity;
 mod grav_tree;
+mod integrable;
+mod interaction_matrix;
 mod node;
 mod responsive;
 mod simulation_result;
@@ -14,9 +16,35 @@ use dimension::Dimension;
 use node::Node;
 /*  public-facing entry points */
 pub use as_entity::AsEntity;
-pub use bigbang_derive::AsEntity;
+pub use bigbang_derive::{AsEntity, Integrable};
 pub use entity::{CalculateCollisions, Entity};
 pub use grav_tree::GravTree;
+pub use integrable::Integrable;
+pub use interaction_matrix::InteractionMatrix;
 pub use responsive::Responsive;
 pub use simulation_result::SimulationResult;
+pub mod boundaries;
+pub mod checkpoint;
 pub mod collisions;
+pub mod cosmology;
+pub mod density;
+pub mod drag;
+pub mod external_fields;
+pub mod force_laws;
+pub mod formats;
+pub mod groups;
+pub mod hermite;
+pub mod ics;
+pub mod kepler;
+pub mod post_newtonian;
+pub mod profiles;
+pub mod regularization;
+pub use boundaries::{Boundary, Domain};
+pub use checkpoint::CheckpointError;
+pub use cosmology::{Cosmology, TimeVariable};
+pub use drag::Drag;
+pub use external_fields::ExternalField;
+pub use force_laws::ForceLaw;
+pub use hermite::Hermite;
+pub use post_newtonian::PostNewtonian;
+pub use regularization::Regularization;
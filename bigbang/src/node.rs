@@ -1,8 +1,10 @@
 use crate::as_entity::AsEntity;
 use crate::dimension::Dimension;
 use crate::entity::Entity;
-use crate::utilities::{find_median, max_min_xyz, xyz_distances};
+use crate::force_laws::ForceLaw;
+use crate::utilities::{add, find_median, max_min_xyz, scale, xyz_distances};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 
 /// This is internal to the tree and is not exposed to the consumer.
 ///
@@ -20,10 +22,20 @@ pub(crate) struct Node<T: AsEntity + Clone> {
     pub(crate) left: Option<Box<Node<T>>>, // Left subtree.
     pub(crate) right: Option<Box<Node<T>>>, // Right subtree.
     pub(crate) points: Option<Vec<T>>,  // Vector of the points if this node is a Leaf.
+    /// The index of each of `points` in the slice the tree was built from, which identifies an
+    /// entity even when another one is equal to it. This is empty if this node is not a leaf.
+    pub(crate) indices: Vec<usize>,
     pub(crate) center_of_mass: (f64, f64, f64), /* The center of mass for this node and it's children all
                                                  * together. (x, y, z). */
     total_mass: f64, // Total mass of all entities under this node.
-    r_max: f64,      // Maximum radius that is a child of this node.
+    /// The aggregate source of the tree's force law, e.g. total mass for gravity or net charge
+    /// for electrostatics, of all entities under this node.
+    pub(crate) source: Source,
+    /// The aggregate source of each species under this node, indexed by species. This is left
+    /// empty if every entity under this node is of species `0`, in which case `source` is all
+    /// species `0`.
+    species_sources: Vec<Source>,
+    r_max: f64, // Maximum radius that is a child of this node.
     x_min: f64,
     x_max: f64,
     y_min: f64,
@@ -40,8 +52,11 @@ impl<T: AsEntity + Clone> Node<T> {
             left: None,
             right: None,
             points: None,
+            indices: Vec::new(),
             center_of_mass: (0.0, 0.0, 0.0),
             total_mass: 0.0,
+            source: Source::default(),
+            species_sources: Vec::new(),
             r_max: 0.0,
             x_min: 0.0,
             x_max: 0.0,
@@ -110,6 +125,30 @@ impl<T: AsEntity + Clone> Node<T> {
             vz: 0.0,
             mass: self.total_mass,
             radius: super_radius,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the aggregate source of each species under this node, along with the species it
+    /// belongs to. Species which exert nothing under this node are left out.
+    pub(crate) fn species_sources(&self) -> Vec<(usize, &Source)> {
+        if self.species_sources.is_empty() {
+            return vec![(0, &self.source)];
+        }
+        self.species_sources
+            .iter()
+            .enumerate()
+            .filter(|(_, source)| source.strength != 0.)
+            .collect()
+    }
+
+    /// Returns the per-species sources of this node, even if every entity under it is of
+    /// species `0`.
+    fn species_aggregates(&self) -> Vec<Source> {
+        if self.species_sources.is_empty() {
+            vec![self.source.clone()]
+        } else {
+            self.species_sources.clone()
         }
     }
 
@@ -120,6 +159,56 @@ impl<T: AsEntity + Clone> Node<T> {
         f64::max(x_distance, f64::max(y_distance, z_distance))
     }
 
+    /// Returns the squared distance from `point` to the nearest point of this node's bounding box,
+    /// which is zero if the point is inside of it.
+    fn box_distance_squared(&self, point: (f64, f64, f64)) -> f64 {
+        let outside =
+            |value: f64, min: f64, max: f64| f64::max(min - value, 0.) + f64::max(value - max, 0.);
+        let x = outside(point.0, self.x_min, self.x_max);
+        let y = outside(point.1, self.y_min, self.y_max);
+        let z = outside(point.2, self.z_min, self.z_max);
+        x * x + y * y + z * z
+    }
+
+    /// Pushes every entity under this node whose position is within `radius` of `point` onto
+    /// `found`, so that a caller searching again and again can reuse one buffer. Children whose
+    /// bounding boxes are further away than that are not searched.
+    pub(crate) fn entities_within<'a>(
+        &'a self,
+        point: (f64, f64, f64),
+        radius: f64,
+        found: &mut Vec<&'a T>,
+    ) {
+        let radius_squared = radius * radius;
+        if let Some(points) = &self.points {
+            found.extend(points.iter().filter(|pt| {
+                let pt = pt.as_entity();
+                let (x, y, z) = (pt.x - point.0, pt.y - point.1, pt.z - point.2);
+                x * x + y * y + z * z <= radius_squared
+            }));
+        }
+        for child in self.left.iter().chain(self.right.iter()) {
+            if child.box_distance_squared(point) <= radius_squared {
+                child.entities_within(point, radius, found);
+            }
+        }
+    }
+
+    /// Returns the bounding box, depth and total mass of this node and every node under it, this
+    /// node being at `depth`.
+    pub(crate) fn cells(&self, depth: usize) -> Vec<Cell> {
+        let mut to_return = vec![Cell {
+            min: (self.x_min, self.y_min, self.z_min),
+            max: (self.x_max, self.y_max, self.z_max),
+            depth,
+            mass: self.total_mass,
+        }];
+        for child in self.left.iter().chain(self.right.iter()) {
+            to_return.append(&mut child.cells(depth + 1));
+        }
+        to_return
+    }
+
     /// Traverses tree and returns first child found with points.
     pub(crate) fn traverse_tree_helper(&self) -> Vec<T> {
         let mut to_return: Vec<T> = Vec::new();
@@ -140,8 +229,57 @@ impl<T: AsEntity + Clone> Node<T> {
         to_return
     }
 
-    /// Takes in a mutable slice of entities and creates a recursive 3d tree structure.
-    pub(crate) fn new_root_node(pts: &[T], max_entities: i32) -> Node<T> {
+    /// Returns the index of every entity under this node in the slice the tree was built from, in
+    /// the same order as [[Node::traverse_tree_helper]] returns the entities.
+    pub(crate) fn traverse_indices(&self) -> Vec<usize> {
+        let mut to_return = Vec::new();
+        if let Some(node) = &self.left {
+            to_return.append(&mut node.traverse_indices());
+        }
+        if let Some(node) = &self.right {
+            to_return.append(&mut node.traverse_indices());
+        } else {
+            to_return.extend(&self.indices);
+        }
+        to_return
+    }
+
+    /// Creates the root of a whole tree of `pts`, which is a phantom parent whose left child is
+    /// the real root. See [[Node::new_root_node]].
+    pub(crate) fn new_phantom_root<F: ForceLaw>(pts: &[T], max_entities: i32, law: &F) -> Node<T> {
+        // Handle the case where a grav tree is initialized without any points...
+        if pts.is_empty() {
+            let mut empty = Node::new();
+            empty.points = Some(Vec::new());
+            empty
+        } else {
+            // Because of the tree's recursive gravity calculation, there needs to be a parent node
+            // that "contains" the _real_ root node. This "phantom_parent" serves no purpose other than
+            // to hold a pointer to the real root node. Perhaps not the most ideal situation for now,
+            // and can be made more elegant in the future, if need be.
+            // The real root of the tree is therefore tree.root.left
+            let mut phantom_parent = Node::new();
+            phantom_parent.left = Some(Box::new(Node::new_root_node(pts, max_entities, law)));
+            phantom_parent.points = Some(Vec::new());
+            phantom_parent
+        }
+    }
+
+    /// Takes in a mutable slice of entities and creates a recursive 3d tree structure. The source
+    /// aggregates of each node are calculated with `law`.
+    pub(crate) fn new_root_node<F: ForceLaw>(pts: &[T], max_entities: i32, law: &F) -> Node<T> {
+        let indices = (0..pts.len()).collect::<Vec<_>>();
+        Node::new_indexed_node(pts, &indices, max_entities, law)
+    }
+
+    /// Creates a recursive 3d tree structure of `pts`, where `indices` are their indices in the
+    /// slice the whole tree is being built from.
+    fn new_indexed_node<F: ForceLaw>(
+        pts: &[T],
+        indices: &[usize],
+        max_entities: i32,
+        law: &F,
+    ) -> Node<T> {
         // Start and end are probably 0 and pts.len(), respectively.
         let length_of_points = pts.len() as i32;
         let mut entities = pts.iter().map(|x| x.as_entity()).collect::<Vec<Entity>>();
@@ -168,16 +306,48 @@ impl<T: AsEntity + Clone> Node<T> {
                     )
                 });
 
-            let (x_max, x_min, y_max, y_min, z_max, z_min) = max_min_xyz(&entities);
-            Node {
-                center_of_mass: (
+            // A leaf of massless entities has no center of mass, so we fall back to their
+            // geometric center to keep the node's position finite.
+            let center_of_mass = if total_mass == 0. {
+                let count = entities.len() as f64;
+                let (x_sum, y_sum, z_sum) = entities.iter().fold((0., 0., 0.), |acc, pt| {
+                    (acc.0 + pt.x, acc.1 + pt.y, acc.2 + pt.z)
+                });
+                (x_sum / count, y_sum / count, z_sum / count)
+            } else {
+                (
                     x_total / total_mass as f64,
                     y_total / total_mass as f64,
                     z_total / total_mass as f64,
-                ),
+                )
+            };
+
+            let source = Source::new(entities.iter(), law, center_of_mass);
+            // Only keep track of species if there is more than the default one.
+            let number_of_species = entities.iter().map(|pt| pt.species + 1).max().unwrap_or(1);
+            let species_sources = if number_of_species == 1 {
+                Vec::new()
+            } else {
+                (0..number_of_species)
+                    .map(|species| {
+                        Source::new(
+                            entities.iter().filter(|pt| pt.species == species),
+                            law,
+                            center_of_mass,
+                        )
+                    })
+                    .collect()
+            };
+
+            let (x_max, x_min, y_max, y_min, z_max, z_min) = max_min_xyz(&entities);
+            Node {
+                center_of_mass,
                 total_mass,
+                source,
+                species_sources,
                 r_max: max_radius,
                 points: Some(pts.to_vec()),
+                indices: indices.to_vec(),
                 left: None,
                 right: None,
                 split_dimension: None,
@@ -215,13 +385,32 @@ impl<T: AsEntity + Clone> Node<T> {
                 split_index = tmp;
                 (Dimension::X, split_value)
             };
+            // The median was found on a copy of the entities, so split the entities themselves
+            // the same way, with those below the median first, so that each half lies on its own
+            // side of the split and the bounding boxes of the children do not overlap.
+            let (mut below, mut equal, mut above) = (Vec::new(), Vec::new(), Vec::new());
+            for (pt, index) in pts.iter().zip(indices) {
+                match pt
+                    .as_entity()
+                    .get_dim(&split_dimension)
+                    .partial_cmp(split_value)
+                {
+                    Some(Ordering::Less) => below.push((pt.clone(), *index)),
+                    Some(Ordering::Equal) => equal.push((pt.clone(), *index)),
+                    _ => above.push((pt.clone(), *index)),
+                }
+            }
+            below.append(&mut equal);
+            below.append(&mut above);
+            let (below, below_indices): (Vec<T>, Vec<usize>) = below.into_iter().unzip();
             root_node.split_dimension = Some(split_dimension);
             root_node.split_value = *split_value;
-            let (below_split, above_split) = pts.split_at(split_index);
+            let (below_split, above_split) = below.split_at(split_index);
+            let (below_indices, above_indices) = below_indices.split_at(split_index);
 
             // Now we construct the left and right children based on this split into lower and upper halves.
-            let left = Node::new_root_node(&below_split, max_entities);
-            let right = Node::new_root_node(&above_split, max_entities);
+            let left = Node::new_indexed_node(below_split, below_indices, max_entities, law);
+            let right = Node::new_indexed_node(above_split, above_indices, max_entities, law);
             // The center of mass is a recursive definition. This finds the average COM for
             // each node.
             let left_mass = left.total_mass;
@@ -229,13 +418,37 @@ impl<T: AsEntity + Clone> Node<T> {
             let (left_x, left_y, left_z) = left.center_of_mass;
             let (right_x, right_y, right_z) = right.center_of_mass;
             let total_mass = left_mass + right_mass;
-            assert!(total_mass != 0., "invalid mass of 0");
 
-            let (center_x, center_y, center_z) = (
-                ((left_mass * left_x) + (right_mass * right_x)) / total_mass,
-                ((left_mass * left_y) + (right_mass * right_y)) / total_mass,
-                ((left_mass * left_z) + (right_mass * right_z)) / total_mass,
-            );
+            // If both halves are massless, the node exerts no gravity and its center is only used
+            // for the theta check, so the midpoint of the two halves will do.
+            let (center_x, center_y, center_z) = if total_mass == 0. {
+                (
+                    (left_x + right_x) / 2.,
+                    (left_y + right_y) / 2.,
+                    (left_z + right_z) / 2.,
+                )
+            } else {
+                (
+                    ((left_mass * left_x) + (right_mass * right_x)) / total_mass,
+                    ((left_mass * left_y) + (right_mass * right_y)) / total_mass,
+                    ((left_mass * left_z) + (right_mass * right_z)) / total_mass,
+                )
+            };
+            root_node.source = Source::combine(&left.source, &right.source);
+            if !left.species_sources.is_empty() || !right.species_sources.is_empty() {
+                let left_species = left.species_aggregates();
+                let right_species = right.species_aggregates();
+                let number_of_species = usize::max(left_species.len(), right_species.len());
+                let empty = Source::default();
+                root_node.species_sources = (0..number_of_species)
+                    .map(|species| {
+                        Source::combine(
+                            left_species.get(species).unwrap_or(&empty),
+                            right_species.get(species).unwrap_or(&empty),
+                        )
+                    })
+                    .collect();
+            }
             root_node.left = Some(Box::new(left));
             root_node.right = Some(Box::new(right));
             root_node.center_of_mass = (center_x, center_y, center_z);
@@ -246,6 +459,95 @@ impl<T: AsEntity + Clone> Node<T> {
     }
 }
 
+/// The bounding box of a [[Node]], along with how deep in the tree it is and the total mass of
+/// the entities under it.
+pub(crate) struct Cell {
+    pub(crate) min: (f64, f64, f64),
+    pub(crate) max: (f64, f64, f64),
+    pub(crate) depth: usize,
+    pub(crate) mass: f64,
+}
+
+/// The aggregate of the force law's sources under a node, which is approximated as a single point
+/// source of strength `strength` at `center`.
+///
+/// Sources may be signed (e.g. charges), so the center is weighted by the _absolute_ strength of
+/// each entity. Weighting by the signed strength would send the center off to infinity as the
+/// net strength approaches zero.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub(crate) struct Source {
+    /// The signed total strength.
+    pub(crate) strength: f64,
+    /// The total absolute strength, which the center is weighted by.
+    weight: f64,
+    pub(crate) center: (f64, f64, f64),
+    /// The mean velocity, weighted in the same way as the center. This is used for the jerk.
+    pub(crate) velocity: (f64, f64, f64),
+}
+
+impl Source {
+    /// Aggregates the sources of some entities. If none of them exert anything, the center falls
+    /// back to `fallback_center`.
+    fn new<'a, F: ForceLaw>(
+        entities: impl Iterator<Item = &'a Entity>,
+        law: &F,
+        fallback_center: (f64, f64, f64),
+    ) -> Source {
+        let (strength, weight, position_total, velocity_total) =
+            entities.fold((0., 0., (0., 0., 0.), (0., 0., 0.)), |acc, pt| {
+                let strength = law.source(pt);
+                let weight = strength.abs();
+                (
+                    acc.0 + strength,
+                    acc.1 + weight,
+                    add(&acc.2, &scale(&(pt.x, pt.y, pt.z), weight)),
+                    add(&acc.3, &scale(&(pt.vx, pt.vy, pt.vz), weight)),
+                )
+            });
+        if weight == 0. {
+            Source {
+                strength,
+                weight,
+                center: fallback_center,
+                velocity: (0., 0., 0.),
+            }
+        } else {
+            Source {
+                strength,
+                weight,
+                center: scale(&position_total, 1. / weight),
+                velocity: scale(&velocity_total, 1. / weight),
+            }
+        }
+    }
+
+    /// Aggregates two sources into one.
+    fn combine(left: &Source, right: &Source) -> Source {
+        let weight = left.weight + right.weight;
+        if weight == 0. {
+            Source {
+                strength: left.strength + right.strength,
+                weight,
+                center: scale(&add(&left.center, &right.center), 0.5),
+                velocity: (0., 0., 0.),
+            }
+        } else {
+            let weighted = |l: &(f64, f64, f64), r: &(f64, f64, f64)| {
+                scale(
+                    &add(&scale(l, left.weight), &scale(r, right.weight)),
+                    1. / weight,
+                )
+            };
+            Source {
+                strength: left.strength + right.strength,
+                weight,
+                center: weighted(&left.center, &right.center),
+                velocity: weighted(&left.velocity, &right.velocity),
+            }
+        }
+    }
+}
+
 /// This tests the recursive node construction used to create a new gravtree. It tests some private
 /// fields so it is located within the same module as the node itself.
 #[test]
@@ -257,6 +559,12 @@ fn test() {
             let mut vy = self.vy;
             let mut vz = self.vz;
             let (mut ax, mut ay, mut az) = simulation_result.gravitational_acceleration;
+            let ((friction_ax, friction_ay, friction_az), angular_acceleration) =
+                simulation_result.friction(self, 50f64, 0.5);
+            ax += friction_ax;
+            ay += friction_ay;
+            az += friction_az;
+            let (wx, wy, wz) = self.spin(angular_acceleration, time_step);
             for other in simulation_result.collisions {
                 let (collision_ax, collision_ay, collision_az) = soft_body(self, other, 50f64);
                 ax += collision_ax;
@@ -276,6 +584,10 @@ fn test() {
                 z: self.z + (vz * time_step),
                 radius: self.radius,
                 mass: self.mass,
+                wx,
+                wy,
+                wz,
+                ..self.clone()
             }
         }
     }
@@ -290,11 +602,12 @@ fn test() {
             vz: i as f64,
             mass: i as f64,
             radius: i as f64,
+            ..Default::default()
         });
     }
 
     let check_vec = test_vec.clone();
-    let tree = crate::GravTree::new(&test_vec, 0.2, 3, 0.2);
+    let tree = crate::GravTree::new(&test_vec, 0.2, 3, 0.2, crate::CalculateCollisions::Yes);
     let root_node = tree.root.clone();
 
     let mut nodes: Vec<Node<Entity>> = Vec::new();
@@ -329,3 +642,35 @@ fn test() {
     let total_mass = check_vec.iter().fold(0., |acc, x| acc + x.mass);
     assert_eq!(total_mass, tree.root.left.unwrap().total_mass);
 }
+
+/// This tests that each child of a node lies entirely on its own side of the node's split, so
+/// that the bounding boxes of siblings do not overlap.
+#[test]
+fn children_lie_on_their_side_of_the_split() {
+    fn check(node: &Node<Entity>) {
+        if let (Some(left), Some(right)) = (&node.left, &node.right) {
+            let (left_max, right_min) = match node.split_dimension {
+                Some(Dimension::X) => (left.x_max, right.x_min),
+                Some(Dimension::Y) => (left.y_max, right.y_min),
+                Some(Dimension::Z) => (left.z_max, right.z_min),
+                None => panic!("a node with children should have a split dimension"),
+            };
+            assert!(left_max <= node.split_value);
+            assert!(right_min >= node.split_value);
+            check(left);
+            check(right);
+        }
+    }
+    // scrambled, so that the entities are not already sorted along any axis
+    let test_vec: Vec<Entity> = (0..40)
+        .map(|i| Entity {
+            x: ((i * 17) % 40) as f64,
+            y: ((i * 23) % 40) as f64 * 0.5,
+            z: ((i * 31) % 40) as f64 * 0.25,
+            mass: 1.,
+            ..Default::default()
+        })
+        .collect();
+    let root_node = Node::new_root_node(&test_vec, 3, &crate::force_laws::Newtonian::default());
+    check(&root_node);
+}
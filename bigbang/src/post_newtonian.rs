@@ -0,0 +1,87 @@
+//! This module contains post-Newtonian corrections to gravity, for simulating compact objects
+//! such as black hole and neutron star binaries. See [[GravTree::set_post_newtonian]].
+//!
+//! The corrections are those of the relative acceleration of an isolated two-body system in
+//! harmonic coordinates, from
+//! [Lincoln & Will (1990)](https://doi.org/10.1103/PhysRevD.42.1123). Each entity only feels the
+//! corrections from entities closer to it than a threshold, and each pair is treated as if it
+//! were isolated, with the relative correction shared out between the two by mass.
+
+use crate::entity::Entity;
+use crate::utilities::{add, dot, scale, sub};
+use serde::{Deserialize, Serialize};
+
+/// The settings for the post-Newtonian corrections.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PostNewtonian {
+    /// The speed of light in simulation units.
+    pub speed_of_light: f64,
+    /// The gravitational constant in simulation units. This should match that of the tree's
+    /// force law.
+    pub g: f64,
+    /// Pairs of entities closer than this get the corrections. Those further apart only feel the
+    /// tree's Newtonian gravity.
+    pub threshold: f64,
+    /// Whether to include the 2.5PN radiation reaction, i.e. the loss of orbital energy to
+    /// gravitational waves, as well as the 1PN terms.
+    pub radiation_reaction: bool,
+}
+
+impl PostNewtonian {
+    /// Creates 1PN corrections for pairs closer than `threshold`, with `G = 1`.
+    pub fn new(speed_of_light: f64, threshold: f64) -> PostNewtonian {
+        PostNewtonian {
+            speed_of_light,
+            g: 1.,
+            threshold,
+            radiation_reaction: false,
+        }
+    }
+
+    /// Also includes the 2.5PN radiation reaction.
+    pub fn with_radiation_reaction(self) -> PostNewtonian {
+        PostNewtonian {
+            radiation_reaction: true,
+            ..self
+        }
+    }
+
+    /// Returns the post-Newtonian correction to the acceleration that `source` exerts on
+    /// `target`, i.e. everything except the Newtonian part.
+    pub fn correction(&self, target: &Entity, source: &Entity) -> (f64, f64, f64) {
+        let total_mass = target.mass + source.mass;
+        let position = sub(
+            &(target.x, target.y, target.z),
+            &(source.x, source.y, source.z),
+        );
+        let r = dot(&position, &position).sqrt();
+        if total_mass == 0. || r == 0. {
+            return (0., 0., 0.);
+        }
+        let velocity = sub(
+            &(target.vx, target.vy, target.vz),
+            &(source.vx, source.vy, source.vz),
+        );
+        let n = scale(&position, 1. / r);
+        let m = self.g * total_mass;
+        let eta = target.mass * source.mass / (total_mass * total_mass);
+        let r_dot = dot(&n, &velocity);
+        let v_squared = dot(&velocity, &velocity);
+        let c_squared = self.speed_of_light * self.speed_of_light;
+
+        // a = -m / r^2 [(1 + A) n + B v]
+        let mut a = (-1.5 * eta * r_dot * r_dot + (1. + 3. * eta) * v_squared
+            - 2. * (2. + eta) * m / r)
+            / c_squared;
+        let mut b = -2. * (2. - eta) * r_dot / c_squared;
+        if self.radiation_reaction {
+            let c_fifth = c_squared * c_squared * self.speed_of_light;
+            let factor = 1.6 * eta * m / r / c_fifth;
+            a -= factor * r_dot * (18. * v_squared + 2. / 3. * m / r - 25. * r_dot * r_dot);
+            b += factor * (6. * v_squared - 2. * m / r - 15. * r_dot * r_dot);
+        }
+        let relative = scale(&add(&scale(&n, a), &scale(&velocity, b)), -m / (r * r));
+        // in the center of mass frame, the target makes up this share of the relative motion
+        scale(&relative, source.mass / total_mass)
+    }
+}
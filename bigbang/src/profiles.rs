@@ -0,0 +1,293 @@
+//! This module measures how a cluster is laid out about its center: the radii which hold given
+//! fractions of its mass, [[lagrangian_radii]], and its density, velocity dispersion and
+//! anisotropy in shells, [[radial_profile]].
+//!
+//! The center is either the center of mass, or the [density center](https://ui.adsabs.harvard.edu/abs/1985ApJ...298...80C),
+//! which is the mean position of the entities weighted by the density around each of them. The
+//! density center follows the core of a cluster even when escaping entities or a passing
+//! neighbour drag the center of mass away from it. See [[Center]].
+
+use crate::density::DensityEstimator;
+use crate::entity::Entity;
+use crate::force_laws::ForceLaw;
+use crate::utilities::{add, center_of_mass, cross, dot, scale, sub};
+use crate::{AsEntity, GravTree, Responsive};
+use std::f64::consts::PI;
+
+/// The center about which entities are measured.
+#[derive(Clone)]
+pub enum Center {
+    /// The center of mass, moving with the mean velocity of the entities.
+    CenterOfMass,
+    /// The mean position and velocity of the entities weighted by the density around each of
+    /// them, which is estimated with the given estimator.
+    DensityCenter(DensityEstimator),
+    /// A fixed position and velocity.
+    Fixed((f64, f64, f64), (f64, f64, f64)),
+}
+
+/// How entities are divided into shells by [[radial_profile]].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Binning {
+    /// `count` shells of equal thickness from `min` to `max`.
+    Linear { min: f64, max: f64, count: usize },
+    /// `count` shells whose radii grow by equal factors from `min` to `max`, which must be
+    /// greater than zero.
+    Logarithmic { min: f64, max: f64, count: usize },
+    /// Shells of `entities` entities each, from the center out. The last shell holds whatever is
+    /// left over, if it is at least half as many.
+    EqualCount { entities: usize },
+}
+
+/// A spherical shell of a [[radial_profile]]. Velocities are relative to the center, and
+/// averages are weighted by mass.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shell {
+    pub inner_radius: f64,
+    pub outer_radius: f64,
+    /// The number of entities in the shell.
+    pub count: usize,
+    /// The total mass of the entities in the shell.
+    pub mass: f64,
+    /// The mass of the shell divided by its volume.
+    pub density: f64,
+    /// The mean radial velocity, which is positive if the shell is expanding.
+    pub radial_velocity: f64,
+    /// The dispersion of radial velocities about their mean.
+    pub radial_dispersion: f64,
+    /// The dispersion of each of the two tangential components of velocity, including any
+    /// rotation.
+    pub tangential_dispersion: f64,
+    /// The one-dimensional dispersion of velocities, averaged over the radial and both tangential
+    /// components.
+    pub velocity_dispersion: f64,
+    /// The anisotropy parameter, `beta = 1 - sigma_t^2 / sigma_r^2`, which is `0` for isotropic
+    /// velocities, `1` for purely radial orbits, and negative for mostly circular ones. It is `0`
+    /// for empty shells, and negative infinity for shells of purely circular orbits, which have
+    /// no radial dispersion at all.
+    pub anisotropy: f64,
+}
+
+/// Returns the position and velocity of `center` for the entities of `tree`. Test particles are
+/// left out.
+pub fn center<T, F>(tree: &GravTree<T, F>, center: &Center) -> ((f64, f64, f64), (f64, f64, f64))
+where
+    T: AsEntity + Responsive + Clone + Send + Sync,
+    F: ForceLaw,
+{
+    match center {
+        Center::CenterOfMass => {
+            let (_, position, velocity) = center_of_mass(&entities(tree));
+            (position, velocity)
+        }
+        Center::DensityCenter(estimator) => {
+            let (entities, densities): (Vec<_>, Vec<_>) = estimator
+                .estimate_tree(tree)
+                .into_iter()
+                .map(|(body, density)| (body.as_entity(), density.density))
+                .unzip();
+            let total = densities.iter().sum::<f64>();
+            if total == 0. {
+                return ((0., 0., 0.), (0., 0., 0.));
+            }
+            entities.iter().zip(densities).fold(
+                ((0., 0., 0.), (0., 0., 0.)),
+                |(position, velocity), (e, density)| {
+                    (
+                        add(&position, &scale(&(e.x, e.y, e.z), density / total)),
+                        add(&velocity, &scale(&(e.vx, e.vy, e.vz), density / total)),
+                    )
+                },
+            )
+        }
+        Center::Fixed(position, velocity) => (*position, *velocity),
+    }
+}
+
+/// Returns the radius about `center` within which each of `fractions` of the mass of the entities
+/// of `tree` lies, interpolating between entities. The usual fractions are `0.1`, `0.5` and
+/// `0.9`. Test particles are left out.
+pub fn lagrangian_radii<T, F>(tree: &GravTree<T, F>, center: &Center, fractions: &[f64]) -> Vec<f64>
+where
+    T: AsEntity + Responsive + Clone + Send + Sync,
+    F: ForceLaw,
+{
+    let (position, _) = self::center(tree, center);
+    let mut radii = entities(tree)
+        .iter()
+        .map(|e| {
+            let offset = sub(&(e.x, e.y, e.z), &position);
+            (dot(&offset, &offset).sqrt(), e.mass)
+        })
+        .collect::<Vec<_>>();
+    radii.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    let total = radii.iter().map(|r| r.1).sum::<f64>();
+    fractions
+        .iter()
+        .map(|fraction| {
+            let target = fraction * total;
+            let (mut enclosed, mut previous) = (0., 0.);
+            for &(radius, mass) in &radii {
+                if enclosed + mass >= target {
+                    let between = if mass > 0. {
+                        (target - enclosed) / mass
+                    } else {
+                        1.
+                    };
+                    return previous + between.max(0.) * (radius - previous);
+                }
+                enclosed += mass;
+                previous = radius;
+            }
+            previous
+        })
+        .collect()
+}
+
+/// Returns the profile of the entities of `tree` about `center`, in the shells given by
+/// `binning`. Entities outside of every shell are left out, as are test particles.
+///
+/// # Panics
+/// Panics if linear or logarithmic binning has no shells, or logarithmic binning starts at a
+/// radius which is not greater than zero.
+pub fn radial_profile<T, F>(tree: &GravTree<T, F>, center: &Center, binning: &Binning) -> Vec<Shell>
+where
+    T: AsEntity + Responsive + Clone + Send + Sync,
+    F: ForceLaw,
+{
+    let (position, velocity) = self::center(tree, center);
+    // the radius, mass, radial velocity and squared tangential speed of each entity
+    let mut entities = entities(tree)
+        .iter()
+        .map(|e| {
+            let offset = sub(&(e.x, e.y, e.z), &position);
+            let relative = sub(&(e.vx, e.vy, e.vz), &velocity);
+            let r = dot(&offset, &offset).sqrt();
+            let (radial, tangential) = if r > 0. {
+                let radial = dot(&offset, &relative) / r;
+                let tangential = cross(&offset, &relative);
+                (radial, dot(&tangential, &tangential) / (r * r))
+            } else {
+                // the direction is arbitrary at the center, so count it all as radial
+                (dot(&relative, &relative).sqrt(), 0.)
+            };
+            (r, e.mass, radial, tangential)
+        })
+        .collect::<Vec<_>>();
+    entities.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    if let Binning::Linear { count, .. } | Binning::Logarithmic { count, .. } = binning {
+        assert!(*count > 0, "binning needs at least one shell");
+    }
+    if let Binning::Logarithmic { min, .. } = binning {
+        assert!(
+            *min > 0.,
+            "logarithmic shells must start above zero, not at {}",
+            min
+        );
+    }
+    let edges = match *binning {
+        Binning::Linear { min, max, count } => (0..=count)
+            .map(|i| min + (max - min) * i as f64 / count as f64)
+            .collect::<Vec<_>>(),
+        Binning::Logarithmic { min, max, count } => (0..=count)
+            .map(|i| min * (max / min).powf(i as f64 / count as f64))
+            .collect(),
+        Binning::EqualCount { entities: size } => {
+            let size = size.max(1);
+            let mut edges = vec![0.];
+            let mut start = 0;
+            while start < entities.len() {
+                // leave the last shell with at least half as many, or fold it into this one
+                let end = if 2 * (entities.len() - start) < 3 * size {
+                    entities.len()
+                } else {
+                    start + size
+                };
+                let outer = if end < entities.len() {
+                    0.5 * (entities[end - 1].0 + entities[end].0)
+                } else {
+                    entities[end - 1].0
+                };
+                edges.push(outer);
+                start = end;
+            }
+            edges
+        }
+    };
+
+    let mut start = entities.partition_point(|e| e.0 < edges[0]);
+    edges
+        .windows(2)
+        .enumerate()
+        .map(|(i, edge)| {
+            let (inner, outer) = (edge[0], edge[1]);
+            // the outermost shell includes its outer edge
+            let last = i == edges.len() - 2;
+            let end = start
+                + entities[start..].partition_point(|e| e.0 < outer || (last && e.0 == outer));
+            let shell = Shell::new(inner, outer, &entities[start..end]);
+            start = end;
+            shell
+        })
+        .collect()
+}
+
+impl Shell {
+    /// Returns the shell from `inner` to `outer` holding `entities`, given as their radius, mass,
+    /// radial velocity and squared tangential speed.
+    fn new(inner: f64, outer: f64, entities: &[(f64, f64, f64, f64)]) -> Shell {
+        let mass = entities.iter().map(|e| e.1).sum::<f64>();
+        let volume = 4. / 3. * PI * (outer.powi(3) - inner.powi(3));
+        let mut shell = Shell {
+            inner_radius: inner,
+            outer_radius: outer,
+            count: entities.len(),
+            mass,
+            density: if volume > 0. { mass / volume } else { 0. },
+            radial_velocity: 0.,
+            radial_dispersion: 0.,
+            tangential_dispersion: 0.,
+            velocity_dispersion: 0.,
+            anisotropy: 0.,
+        };
+        if entities.is_empty() {
+            return shell;
+        }
+        // groups of massless entities are weighted evenly
+        let weight = |e: &(f64, f64, f64, f64)| {
+            if mass > 0. {
+                e.1 / mass
+            } else {
+                1. / entities.len() as f64
+            }
+        };
+        let radial_velocity = entities.iter().map(|e| weight(e) * e.2).sum::<f64>();
+        let radial = entities
+            .iter()
+            .map(|e| weight(e) * (e.2 - radial_velocity).powi(2))
+            .sum::<f64>();
+        let tangential = entities.iter().map(|e| weight(e) * e.3).sum::<f64>() / 2.;
+        shell.radial_velocity = radial_velocity;
+        shell.radial_dispersion = radial.sqrt();
+        shell.tangential_dispersion = tangential.sqrt();
+        shell.velocity_dispersion = ((radial + 2. * tangential) / 3.).sqrt();
+        shell.anisotropy = if radial > 0. {
+            1. - tangential / radial
+        } else if tangential > 0. {
+            f64::NEG_INFINITY
+        } else {
+            0.
+        };
+        shell
+    }
+}
+
+/// Returns the entities of `tree`, leaving out test particles.
+fn entities<T, F>(tree: &GravTree<T, F>) -> Vec<Entity>
+where
+    T: AsEntity + Responsive + Clone + Send + Sync,
+    F: ForceLaw,
+{
+    tree.as_vec().iter().map(|b| b.as_entity()).collect()
+}
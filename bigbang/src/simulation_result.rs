@@ -1,6 +1,52 @@
+use crate::as_entity::AsEntity;
+use crate::collisions::friction;
+
 pub struct SimulationResult<'a, T> {
     /// The result of the simulation's collision check
     /// just a vector of references to potential collisions
     pub collisions: Vec<&'a T>,
+    /// The acceleration from the tree's force law, plus that of any external fields and drag
+    /// forces the tree has.
     pub gravitational_acceleration: (f64, f64, f64),
+    /// The potential (energy per unit mass) of the tree's external fields at the entity's position.
+    /// This is zero if the tree has no external fields. See [[crate::external_fields]].
+    pub external_potential: f64,
+    /// The time derivative of `gravitational_acceleration` from the tree's force law, i.e. the
+    /// jerk. This is only calculated if the tree has been asked to with
+    /// [[crate::GravTree::set_derivatives]], and is zero otherwise.
+    pub jerk: (f64, f64, f64),
+    /// The tidal tensor, `T[i][j] = d a_i / d x_j`, of the tree's force law at the entity's
+    /// position. Like `jerk`, this is zero unless the tree has been asked to calculate it.
+    pub tidal_tensor: [[f64; 3]; 3],
+}
+
+impl<'a, T: AsEntity> SimulationResult<'a, T> {
+    /// Sums the frictional contact forces from every entity `entity` collided with, using
+    /// [[crate::collisions::friction]]. Returns the total linear acceleration and the total angular
+    /// acceleration, the latter of which can be applied with [[crate::Entity::spin]].
+    pub fn friction(
+        &self,
+        entity: &T,
+        stiffness: f64,
+        coefficient: f64,
+    ) -> ((f64, f64, f64), (f64, f64, f64)) {
+        self.collisions.iter().fold(
+            ((0., 0., 0.), (0., 0., 0.)),
+            |(acceleration, angular_acceleration), other| {
+                let (a, alpha) = friction(entity, *other, stiffness, coefficient);
+                (
+                    (
+                        acceleration.0 + a.0,
+                        acceleration.1 + a.1,
+                        acceleration.2 + a.2,
+                    ),
+                    (
+                        angular_acceleration.0 + alpha.0,
+                        angular_acceleration.1 + alpha.1,
+                        angular_acceleration.2 + alpha.2,
+                    ),
+                )
+            },
+        )
+    }
 }
@@ -1,5 +1,5 @@
 use super::Dimension;
-#[allow(unused_imports)]
+use crate::as_entity::AsEntity;
 use crate::entity::Entity;
 use std::cmp::Ordering;
 /// Returns the absolute distance in every dimension (the range in every dimension)
@@ -77,3 +77,83 @@ fn find_median_helper(
         find_median_helper(dim, pts, start, high, mid)
     }
 }
+
+/// Component-wise sum of two vectors.
+pub(crate) fn add(a: &(f64, f64, f64), b: &(f64, f64, f64)) -> (f64, f64, f64) {
+    (a.0 + b.0, a.1 + b.1, a.2 + b.2)
+}
+
+/// Component-wise difference of two vectors, `a - b`.
+pub(crate) fn sub(a: &(f64, f64, f64), b: &(f64, f64, f64)) -> (f64, f64, f64) {
+    (a.0 - b.0, a.1 - b.1, a.2 - b.2)
+}
+
+/// Multiplies every component of a vector by `scalar`.
+pub(crate) fn scale(a: &(f64, f64, f64), scalar: f64) -> (f64, f64, f64) {
+    (a.0 * scalar, a.1 * scalar, a.2 * scalar)
+}
+
+/// The dot product of two vectors.
+pub(crate) fn dot(a: &(f64, f64, f64), b: &(f64, f64, f64)) -> f64 {
+    a.0 * b.0 + a.1 * b.1 + a.2 * b.2
+}
+
+/// The cross product of two vectors, `a x b`.
+pub(crate) fn cross(a: &(f64, f64, f64), b: &(f64, f64, f64)) -> (f64, f64, f64) {
+    (
+        a.1 * b.2 - a.2 * b.1,
+        a.2 * b.0 - a.0 * b.2,
+        a.0 * b.1 - a.1 * b.0,
+    )
+}
+
+/// Returns the total mass of `entities`, their center of mass and its velocity. A massless system
+/// is weighted evenly instead, and the center of no entities at all is at rest at the origin.
+pub(crate) fn center_of_mass(entities: &[Entity]) -> (f64, (f64, f64, f64), (f64, f64, f64)) {
+    let mass = entities.iter().map(|e| e.mass).sum::<f64>();
+    let weight = |e: &Entity| {
+        if mass == 0. {
+            1. / entities.len() as f64
+        } else {
+            e.mass / mass
+        }
+    };
+    let (center, velocity) =
+        entities
+            .iter()
+            .fold(((0., 0., 0.), (0., 0., 0.)), |(center, velocity), e| {
+                (
+                    add(&center, &scale(&(e.x, e.y, e.z), weight(e))),
+                    add(&velocity, &scale(&(e.vx, e.vy, e.vz), weight(e))),
+                )
+            });
+    (mass, center, velocity)
+}
+
+/// Moves `entities` into the frame where their center of mass is at rest at the origin.
+pub(crate) fn to_center_of_mass_frame(mut entities: Vec<Entity>) -> Vec<Entity> {
+    let (_, center, velocity) = center_of_mass(&entities);
+    for e in &mut entities {
+        e.x -= center.0;
+        e.y -= center.1;
+        e.z -= center.2;
+        e.vx -= velocity.0;
+        e.vy -= velocity.1;
+        e.vz -= velocity.2;
+    }
+    entities
+}
+
+/// An entity tagged with its index, so that the entities found through a tree can be matched up
+/// with the entities they came from.
+#[derive(Clone)]
+pub(crate) struct Tagged {
+    pub(crate) entity: Entity,
+    pub(crate) index: usize,
+}
+
+impl AsEntity for Tagged {
+    fn as_entity(&self) -> Entity {
+        self.entity.clone()
+    }
+}
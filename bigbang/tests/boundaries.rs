@@ -0,0 +1,154 @@
+extern crate bigbang;
+use bigbang::{
+    AsEntity, Boundary, CalculateCollisions, Domain, GravTree, Integrable, Responsive,
+    SimulationResult,
+};
+
+#[derive(Clone, AsEntity, Integrable)]
+struct Particle {
+    x: f64,
+    y: f64,
+    z: f64,
+    vx: f64,
+    vy: f64,
+    vz: f64,
+    radius: f64,
+    mass: f64,
+}
+
+impl Particle {
+    fn new(x: f64, vx: f64, mass: f64) -> Particle {
+        Particle {
+            x,
+            y: 1.,
+            z: 1.,
+            vx,
+            vy: 0.,
+            vz: 0.,
+            radius: 0.,
+            mass,
+        }
+    }
+}
+
+impl Responsive for Particle {
+    fn respond(&self, simulation_result: SimulationResult<Self>, time_step: f64) -> Self {
+        let (ax, ay, az) = simulation_result.gravitational_acceleration;
+        let (vx, vy, vz) = (
+            self.vx + ax * time_step,
+            self.vy + ay * time_step,
+            self.vz + az * time_step,
+        );
+        Particle {
+            vx,
+            vy,
+            vz,
+            x: self.x + vx * time_step,
+            y: self.y + vy * time_step,
+            z: self.z + vz * time_step,
+            ..self.clone()
+        }
+    }
+}
+
+/// Returns a tree of massless particles moving freely for a unit time step.
+fn free_particles(particles: &[Particle], boundary: Boundary) -> GravTree<Particle> {
+    let mut test_tree = GravTree::new(particles, 1., 3, 0.2, CalculateCollisions::No);
+    test_tree.set_boundary(Some(boundary));
+    test_tree
+}
+
+/// Test that a particle bounces off of a reflecting wall.
+#[test]
+fn reflecting() {
+    let particles = vec![Particle::new(9.5, 1., 0.), Particle::new(5., 0., 0.)];
+    let after_time_step = free_particles(&particles, Boundary::Reflecting(Domain::cube(10.)))
+        .time_step()
+        .as_vec();
+    assert_eq!(after_time_step.len(), 2);
+    let bounced = after_time_step.iter().find(|p| p.vx != 0.).unwrap();
+    assert_eq!(bounced.x, 9.5);
+    assert_eq!(bounced.vx, -1.);
+}
+
+/// Test that a particle which crosses the whole domain in one time step bounces off of both walls.
+#[test]
+fn reflecting_more_than_once() {
+    let particles = vec![Particle::new(9.5, -21., 0.), Particle::new(5., 0., 0.)];
+    let after_time_step = free_particles(&particles, Boundary::Reflecting(Domain::cube(10.)))
+        .time_step()
+        .as_vec();
+    let bounced = after_time_step.iter().find(|p| p.vx != 0.).unwrap();
+    assert!((bounced.x - 8.5).abs() < 1e-12);
+    assert_eq!(bounced.vx, -21.);
+}
+
+/// Test that a domain which entities could not be folded back into is rejected.
+#[test]
+#[should_panic]
+fn flat_domain() {
+    let domain = Domain::new((0., 0., 0.), (10., 0., 10.));
+    free_particles(&[Particle::new(5., 0., 0.)], Boundary::Periodic(domain));
+}
+
+/// Test that a particle which leaves an absorbing domain is removed and reported.
+#[test]
+fn absorbing() {
+    let particles = vec![Particle::new(9.5, 1., 0.), Particle::new(5., 0., 0.)];
+    let after_time_step =
+        free_particles(&particles, Boundary::Absorbing(Domain::cube(10.))).time_step();
+    assert_eq!(after_time_step.get_number_of_entities(), 1);
+    assert_eq!(after_time_step.as_vec()[0].x, 5.);
+    let escapers = after_time_step.get_escapers();
+    assert_eq!(escapers.len(), 1);
+    assert_eq!(escapers[0].x, 10.5);
+    // escapers are only reported for the step they were removed in
+    assert!(after_time_step.time_step().get_escapers().is_empty());
+}
+
+/// Test that a particle which leaves through one wall of a periodic domain comes back in through
+/// the opposite one.
+#[test]
+fn periodic() {
+    let particles = vec![Particle::new(4.5, 1., 0.), Particle::new(0., 0., 0.)];
+    let domain = Domain::new((-5., -5., -5.), (5., 5., 5.));
+    let after_time_step = free_particles(&particles, Boundary::Periodic(domain))
+        .time_step()
+        .as_vec();
+    let wrapped = after_time_step.iter().find(|p| p.vx != 0.).unwrap();
+    assert!((wrapped.x - -4.5).abs() < 1e-12);
+    assert_eq!(wrapped.vx, 1.);
+}
+
+/// Test that the periodic box is only reported for a periodic cube.
+#[test]
+fn periodic_box() {
+    let particles = vec![Particle::new(0., 0., 0.)];
+    let mut test_tree = free_particles(&particles, Boundary::Periodic(Domain::cube(10.)));
+    assert_eq!(test_tree.get_periodic_box(), Some(10.));
+    test_tree.set_boundary(Some(Boundary::Periodic(Domain::new(
+        (0., 0., 0.),
+        (10., 20., 10.),
+    ))));
+    assert_eq!(test_tree.get_periodic_box(), None);
+    test_tree.set_boundary(Some(Boundary::Reflecting(Domain::cube(10.))));
+    assert_eq!(test_tree.get_periodic_box(), None);
+}
+
+/// Test that only unbound particles beyond the escape radius are removed.
+#[test]
+fn escapers() {
+    let particles = vec![
+        Particle::new(0., 0., 100.),
+        // the escape speed at 50 from a mass of 100 is 2
+        Particle::new(50., 10., 0.),
+        Particle::new(-50., -1., 0.),
+        Particle::new(10., 10., 0.),
+    ];
+    let after_time_step =
+        free_particles(&particles, Boundary::Escapers { radius: 20., g: 1. }).time_step();
+    assert_eq!(after_time_step.get_number_of_entities(), 3);
+    let escapers = after_time_step.get_escapers();
+    assert_eq!(escapers.len(), 1);
+    assert!(escapers[0].x > 50.);
+}
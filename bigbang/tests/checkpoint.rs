@@ -0,0 +1,244 @@
+extern crate bigbang;
+use bigbang::{
+    AsEntity, Boundary, CalculateCollisions, CheckpointError, Domain, GravTree, Hermite,
+    Integrable, Responsive, SimulationResult,
+};
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::path::PathBuf;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, AsEntity, Integrable)]
+struct Particle {
+    x: f64,
+    y: f64,
+    z: f64,
+    vx: f64,
+    vy: f64,
+    vz: f64,
+    radius: f64,
+    mass: f64,
+}
+
+impl Particle {
+    fn new(x: f64, y: f64, mass: f64) -> Particle {
+        Particle {
+            x,
+            y,
+            z: 0.,
+            vx: 0.,
+            vy: 0.,
+            vz: 0.,
+            radius: 0.,
+            mass,
+        }
+    }
+}
+
+impl Responsive for Particle {
+    fn respond(&self, simulation_result: SimulationResult<Self>, time_step: f64) -> Self {
+        let (ax, ay, az) = simulation_result.gravitational_acceleration;
+        let (vx, vy, vz) = (
+            self.vx + ax * time_step,
+            self.vy + ay * time_step,
+            self.vz + az * time_step,
+        );
+        Particle {
+            vx,
+            vy,
+            vz,
+            x: self.x + vx * time_step,
+            y: self.y + vy * time_step,
+            z: self.z + vz * time_step,
+            ..self.clone()
+        }
+    }
+}
+
+fn checkpoint_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "bigbang_{}_{}.checkpoint",
+        name,
+        std::process::id()
+    ))
+}
+
+fn tree() -> GravTree<Particle> {
+    let particles = (0..10)
+        .map(|i| Particle::new(i as f64, (i * i % 7) as f64, 1. + i as f64))
+        .collect::<Vec<_>>();
+    let mut tree = GravTree::new(&particles, 0.01, 3, 0.2, CalculateCollisions::No);
+    tree.add_test_particle(Particle::new(3.5, 2.5, 0.));
+    tree.set_collision_groups(0b101);
+    tree.set_rng_state(&[1, 2, 3, 4]);
+    tree
+}
+
+fn sorted(mut particles: Vec<Particle>) -> Vec<Particle> {
+    particles.sort_by(|a, b| a.mass.partial_cmp(&b.mass).unwrap());
+    particles
+}
+
+/// Test that a restarted simulation carries on exactly where the saved one left off.
+#[test]
+fn round_trip() {
+    let path = checkpoint_path("round_trip");
+    let saved = tree().time_step().time_step().time_step();
+    saved.save_checkpoint(&path).unwrap();
+    let loaded = GravTree::<Particle>::load_checkpoint(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.get_time(), saved.get_time());
+    assert_eq!(loaded.get_steps(), 3);
+    assert_eq!(loaded.get_rng_state(), &[1, 2, 3, 4]);
+    assert_eq!(loaded.get_collision_groups(), 0b101);
+    assert_eq!(loaded.test_particles(), saved.test_particles());
+    assert_eq!(sorted(loaded.as_vec()), sorted(saved.as_vec()));
+
+    let (saved, loaded) = (saved.time_step(), loaded.time_step());
+    assert_eq!(sorted(loaded.as_vec()), sorted(saved.as_vec()));
+    assert_eq!(loaded.test_particles(), saved.test_particles());
+}
+
+/// Test that a simulation which integrates its entities itself can only be restarted with an
+/// Integrable type.
+#[test]
+fn integrable_round_trip() {
+    let path = checkpoint_path("integrable_round_trip");
+    let mut saved = tree();
+    saved.set_boundary(Some(Boundary::Reflecting(Domain::cube(10.))));
+    saved.save_checkpoint(&path).unwrap();
+
+    match GravTree::<Particle>::load_checkpoint(&path) {
+        Err(CheckpointError::RequiresIntegrable) => (),
+        _ => panic!("loaded a checkpoint which needs an Integrable type"),
+    }
+    let loaded = GravTree::<Particle>::load_integrable_checkpoint(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(loaded.get_boundary().is_some());
+    assert_eq!(
+        sorted(loaded.time_step().as_vec()),
+        sorted(saved.time_step().as_vec())
+    );
+}
+
+/// Test that checkpoints from newer versions of the format are only read if they say they are
+/// compatible, and that anything they add to the header is skipped.
+#[test]
+fn newer_versions() {
+    let path = checkpoint_path("newer_versions");
+    let saved = tree();
+    saved.save_checkpoint(&path).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+
+    // a newer version which can be read by this one, with an extra field in the header
+    let header_length = u64::from_le_bytes(bytes[16..24].try_into().unwrap()) as usize;
+    let mut newer = bytes[..8].to_vec();
+    newer.extend_from_slice(&2u32.to_le_bytes());
+    newer.extend_from_slice(&1u32.to_le_bytes());
+    newer.extend_from_slice(&(header_length as u64 + 8).to_le_bytes());
+    newer.extend_from_slice(&bytes[24..24 + header_length]);
+    newer.extend_from_slice(&42f64.to_le_bytes());
+    newer.extend_from_slice(&bytes[24 + header_length..]);
+    std::fs::write(&path, &newer).unwrap();
+    let loaded = GravTree::<Particle>::load_checkpoint(&path).unwrap();
+    assert_eq!(sorted(loaded.as_vec()), sorted(saved.as_vec()));
+
+    // a newer version which can not
+    newer[12..16].copy_from_slice(&2u32.to_le_bytes());
+    std::fs::write(&path, &newer).unwrap();
+    match GravTree::<Particle>::load_checkpoint(&path) {
+        Err(CheckpointError::UnsupportedVersion {
+            version: 2,
+            compatible_version: 2,
+        }) => (),
+        _ => panic!("loaded an incompatible checkpoint"),
+    }
+
+    std::fs::write(&path, b"not a checkpoint").unwrap();
+    match GravTree::<Particle>::load_checkpoint(&path) {
+        Err(CheckpointError::NotACheckpoint) => (),
+        _ => panic!("loaded something which is not a checkpoint"),
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Replaces every occurrence of `from` in the header of the checkpoint at `path` with `to`, which
+/// is as long, and returns how many there were.
+fn patch_header(path: &PathBuf, from: &[u8], to: &[u8]) -> usize {
+    let mut bytes = std::fs::read(path).unwrap();
+    let header_length = u64::from_le_bytes(bytes[16..24].try_into().unwrap()) as usize;
+    let mut count = 0;
+    for start in 24..24 + header_length - from.len() {
+        if &bytes[start..start + from.len()] == from {
+            bytes[start..start + from.len()].copy_from_slice(to);
+            count += 1;
+        }
+    }
+    std::fs::write(path, &bytes).unwrap();
+    count
+}
+
+/// Test that a checkpoint whose periodic domain has collapsed is refused rather than loaded into a
+/// tree which could not fold its entities back into it.
+#[test]
+fn degenerate_boundary() {
+    let path = checkpoint_path("degenerate_boundary");
+    let mut saved = tree();
+    saved.set_boundary(Some(Boundary::Periodic(Domain::cube(10.))));
+    saved.save_checkpoint(&path).unwrap();
+
+    // the only sides of length 10 in the header are those of the domain
+    let sides = patch_header(&path, &10f64.to_le_bytes(), &0f64.to_le_bytes());
+    assert_eq!(sides, 3);
+    match GravTree::<Particle>::load_integrable_checkpoint(&path) {
+        Err(CheckpointError::Corrupt(_)) => (),
+        _ => panic!("loaded a checkpoint with a zero-size periodic domain"),
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Test that a checkpoint whose Hermite integrator could not count its block steps is refused
+/// rather than loaded into a tree which would overflow on its first step.
+#[test]
+fn hermite_max_level() {
+    let path = checkpoint_path("hermite_max_level");
+    let mut saved = tree();
+    saved.set_hermite(Some(Hermite {
+        eta: 0.02,
+        initial_eta: 0.01,
+        max_level: 20,
+    }));
+    saved.save_checkpoint(&path).unwrap();
+
+    // the settings are saved as eta, initial_eta and max_level
+    let settings = |max_level: u32| {
+        let mut bytes = 0.02f64.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&0.01f64.to_le_bytes());
+        bytes.extend_from_slice(&max_level.to_le_bytes());
+        bytes
+    };
+    assert_eq!(patch_header(&path, &settings(20), &settings(70)), 1);
+    match GravTree::<Particle>::load_integrable_checkpoint(&path) {
+        Err(CheckpointError::Corrupt(_)) => (),
+        _ => panic!("loaded a checkpoint with a max_level above MAX_LEVEL"),
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Test that a tree which no longer uses anything that needs Integrable can be restarted without
+/// it, however it was set up before.
+#[test]
+fn disabled_boundary_round_trip() {
+    let path = checkpoint_path("disabled_boundary_round_trip");
+    let mut saved = tree();
+    saved.set_boundary(Some(Boundary::Reflecting(Domain::cube(10.))));
+    saved.set_boundary(None);
+    saved.save_checkpoint(&path).unwrap();
+    let loaded = GravTree::<Particle>::load_checkpoint(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(loaded.get_boundary().is_none());
+    assert_eq!(
+        sorted(loaded.time_step().as_vec()),
+        sorted(saved.time_step().as_vec())
+    );
+}
@@ -0,0 +1,40 @@
+//! The entity type shared by the tests which only need a tree to hold their entities.
+
+use bigbang::{AsEntity, CalculateCollisions, Entity, GravTree, Responsive, SimulationResult};
+
+/// An entity which stays where it is.
+#[derive(Clone, AsEntity)]
+pub struct Particle {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub vx: f64,
+    pub vy: f64,
+    pub vz: f64,
+    pub radius: f64,
+    pub mass: f64,
+}
+
+impl Responsive for Particle {
+    fn respond(&self, _: SimulationResult<Self>, _: f64) -> Self {
+        self.clone()
+    }
+}
+
+/// Returns a tree of particles with the positions, velocities, radii and masses of `entities`.
+pub fn tree(entities: &[Entity]) -> GravTree<Particle> {
+    let particles = entities
+        .iter()
+        .map(|e| Particle {
+            x: e.x,
+            y: e.y,
+            z: e.z,
+            vx: e.vx,
+            vy: e.vy,
+            vz: e.vz,
+            radius: e.radius,
+            mass: e.mass,
+        })
+        .collect::<Vec<_>>();
+    GravTree::new(&particles, 0.01, 3, 0.2, CalculateCollisions::No)
+}
@@ -6,7 +6,11 @@
 // The issue probably arises when the total number of entities is less than max_pts?
 
 extern crate bigbang;
-use bigbang::{collisions::soft_body, AsEntity, Entity, GravTree, Responsive, SimulationResult};
+use bigbang::{
+    collisions::{soft_body, Friction},
+    AsEntity, CalculateCollisions, GravTree, Integrable, InteractionMatrix, Responsive,
+    SimulationResult,
+};
 
 #[derive(Clone, PartialEq, AsEntity)]
 struct MyEntity {
@@ -18,6 +22,9 @@ struct MyEntity {
     vz: f64,
     radius: f64,
     mass: f64,
+    collision_group: u32,
+    collision_mask: u32,
+    species: usize,
     collided_with: Vec<MyEntity>,
 }
 
@@ -32,6 +39,9 @@ impl MyEntity {
             vz: 0.,
             radius,
             mass,
+            collision_group: 1,
+            collision_mask: u32::MAX,
+            species: 0,
             collided_with: Vec::new(),
         }
     }
@@ -64,6 +74,9 @@ impl Responsive for MyEntity {
             z: self.z + (vz * time_step),
             radius: self.radius,
             mass: self.mass,
+            collision_group: self.collision_group,
+            collision_mask: self.collision_mask,
+            species: self.species,
             collided_with,
         }
     }
@@ -77,7 +90,13 @@ fn two_entities_collision() {
         MyEntity::new(0., 0., 1., 10., 5.),
     ];
 
-    let test_tree = GravTree::new(&vec_that_wants_to_be_a_kdtree, 0.2, 3, 0.2);
+    let test_tree = GravTree::new(
+        &vec_that_wants_to_be_a_kdtree,
+        0.2,
+        3,
+        0.2,
+        CalculateCollisions::Yes,
+    );
     let after_time_step = test_tree.time_step().as_vec();
 
     // Each entity should have collided with exactly one other entity
@@ -93,13 +112,51 @@ fn two_entities_no_collision() {
         MyEntity::new(0., 0., 1., 10., 5.),
     ];
 
-    let test_tree = GravTree::new(&vec_that_wants_to_be_a_kdtree, 0.2, 3, 0.2);
+    let test_tree = GravTree::new(
+        &vec_that_wants_to_be_a_kdtree,
+        0.2,
+        3,
+        0.2,
+        CalculateCollisions::Yes,
+    );
     let after_time_step = test_tree.time_step().as_vec();
 
     assert_eq!(after_time_step[0].collided_with.len(), 0);
     assert_eq!(after_time_step[1].collided_with.len(), 0);
 }
 
+/// Test that overlapping entities whose groups and masks exclude each other are not reported as
+/// colliding, and that disabling a group at runtime stops it from colliding altogether.
+#[test]
+fn collision_groups_and_masks() {
+    let mut ghost = MyEntity::new(0., 0., 0., 10., 5.);
+    ghost.collision_group = 0b10;
+    ghost.collision_mask = 0b10;
+    let vec_that_wants_to_be_a_kdtree: Vec<MyEntity> = vec![
+        MyEntity::new(0., 0., 1., 10., 5.),
+        MyEntity::new(0., 1., 0., 10., 5.),
+        ghost,
+    ];
+
+    let mut test_tree = GravTree::new(
+        &vec_that_wants_to_be_a_kdtree,
+        0.2,
+        3,
+        0.2,
+        CalculateCollisions::Yes,
+    );
+    for entity in test_tree.time_step().as_vec() {
+        let expected = if entity.collision_group == 0b10 { 0 } else { 1 };
+        assert_eq!(entity.collided_with.len(), expected);
+    }
+
+    test_tree.disable_collision_groups(0b1);
+    assert_eq!(test_tree.get_collision_groups(), !0b1);
+    for entity in test_tree.time_step().as_vec() {
+        assert_eq!(entity.collided_with.len(), 0);
+    }
+}
+
 /// Test that the gravitational acceleration of two distant particles is calculated correctly
 #[test]
 fn two_entities_accel() {
@@ -108,7 +165,13 @@ fn two_entities_accel() {
         MyEntity::new(50., 0., 1., 10., 500.),
     ];
 
-    let test_tree = GravTree::new(&vec_that_wants_to_be_a_kdtree, 0.3, 3, 0.2);
+    let test_tree = GravTree::new(
+        &vec_that_wants_to_be_a_kdtree,
+        0.3,
+        3,
+        0.2,
+        CalculateCollisions::Yes,
+    );
     let _after_time_step = test_tree.time_step().time_step().as_vec();
 
     // 1.0 isn't right but it should at least not be 0, what the current test is suggesting
@@ -155,7 +218,13 @@ fn five_entities_collision() {
         MyEntity::new(0., 1., 1., 10., 5.),
     ];
 
-    let test_tree = GravTree::new(&vec_that_wants_to_be_a_kdtree, 0.2, 3, 0.2);
+    let test_tree = GravTree::new(
+        &vec_that_wants_to_be_a_kdtree,
+        0.2,
+        3,
+        0.2,
+        CalculateCollisions::Yes,
+    );
     let after_time_step = test_tree.time_step().as_vec();
 
     // Each entity should have collided with exactly all four other entities
@@ -165,8 +234,47 @@ fn five_entities_collision() {
     assert_eq!(after_time_step[3].collided_with.len(), 4);
     assert_eq!(after_time_step[4].collided_with.len(), 4);
 }
+
+/// Steps `entities` forward by `time_step` in the same way as [[MyEntity::respond]], but with the
+/// acceleration summed directly over every other entity instead of walking a tree.
+fn direct_summation_step(entities: &[MyEntity], time_step: f64) -> Vec<MyEntity> {
+    entities
+        .iter()
+        .enumerate()
+        .map(|(i, entity)| {
+            let (ax, ay, az) = entities.iter().enumerate().filter(|(j, _)| *j != i).fold(
+                (0., 0., 0.),
+                |acceleration, (_, other)| {
+                    let (dx, dy, dz) = (other.x - entity.x, other.y - entity.y, other.z - entity.z);
+                    let distance = f64::sqrt(dx * dx + dy * dy + dz * dz);
+                    let scale = other.mass / (distance * distance * distance);
+                    (
+                        acceleration.0 + dx * scale,
+                        acceleration.1 + dy * scale,
+                        acceleration.2 + dz * scale,
+                    )
+                },
+            );
+            let (vx, vy, vz) = (
+                entity.vx + ax * time_step,
+                entity.vy + ay * time_step,
+                entity.vz + az * time_step,
+            );
+            MyEntity {
+                vx,
+                vy,
+                vz,
+                x: entity.x + vx * time_step,
+                y: entity.y + vy * time_step,
+                z: entity.z + vz * time_step,
+                ..entity.clone()
+            }
+        })
+        .collect()
+}
+
 /// Test that the gravitational acceleration of five particles is calculated correctly
-/// by verifying their velocity afterwards
+/// by verifying their velocity afterwards against a direct summation
 #[test]
 fn five_entities_accel() {
     let vec_that_wants_to_be_a_kdtree: Vec<MyEntity> = vec![
@@ -177,22 +285,241 @@ fn five_entities_accel() {
         MyEntity::new(50., 100., 1., 10., 500.),
     ];
 
-    let test_tree = GravTree::new(&vec_that_wants_to_be_a_kdtree, 0.3, 3, 0.2);
+    let test_tree = GravTree::new(
+        &vec_that_wants_to_be_a_kdtree,
+        0.3,
+        3,
+        0.2,
+        CalculateCollisions::No,
+    );
     let after_time_step = test_tree.time_step().time_step().as_vec();
+    let expected = direct_summation_step(
+        &direct_summation_step(&vec_that_wants_to_be_a_kdtree, 0.3),
+        0.3,
+    );
 
-    assert_eq!(after_time_step[0].vx, 234.62426718543517);
-    assert_eq!(after_time_step[0].vy, -308.1666357163429);
-    assert_eq!(after_time_step[0].vz, 6.374453794316003);
+    assert_eq!(after_time_step.len(), expected.len());
+    // the tree reorders its entities, so match them up by where they ended up
+    for entity in after_time_step {
+        let closest = expected
+            .iter()
+            .min_by(|a, b| {
+                let distance_to = |other: &MyEntity| {
+                    (other.x - entity.x).powi(2)
+                        + (other.y - entity.y).powi(2)
+                        + (other.z - entity.z).powi(2)
+                };
+                distance_to(a).partial_cmp(&distance_to(b)).unwrap()
+            })
+            .unwrap();
+        assert!((entity.vx - closest.vx).abs() < 1e-12);
+        assert!((entity.vy - closest.vy).abs() < 1e-12);
+        assert!((entity.vz - closest.vz).abs() < 1e-12);
+    }
+}
 
-    assert_eq!(after_time_step[1].vx, -728.0140671441542);
-    assert_eq!(after_time_step[1].vy, 961.0687440357227);
-    assert_eq!(after_time_step[1].vz, -22.318277419464977);
+/// Test that friction opposes the sliding of one entity across another, and that it exerts a
+/// torque which spins the sliding entity up in the direction of rolling.
+#[test]
+fn sliding_entity_friction() {
+    let mut sliding = MyEntity::new(0., 0., 0., 1., 1.);
+    sliding.vx = 1.;
+    let ground = MyEntity::new(0., -1.5, 0., 1., 1.);
 
-    assert_eq!(after_time_step[2].vx, 68.99261434517841);
-    assert_eq!(after_time_step[2].vy, 202.1872542350379);
-    assert_eq!(after_time_step[2].vz, 0.28488247914098197);
+    let ((ax, ay, az), (alpha_x, alpha_y, alpha_z)) =
+        bigbang::collisions::friction(&sliding, &ground, 10., 0.5);
 
-    assert_eq!(after_time_step[3].vx, -232.853993009126);
-    assert_eq!(after_time_step[3].vy, 189.20383841899522);
-    assert_eq!(after_time_step[3].vz, -2.014092604958015);
+    // friction = 0.5 * (10 * 0.5 overlap) = 2.5, opposing the motion
+    assert!((ax + 2.5).abs() < 1e-12);
+    assert_eq!((ay, az), (0., 0.));
+    // the torque spins the entity clockwise about z, which is rolling in the +x direction
+    assert_eq!((alpha_x, alpha_y), (0., 0.));
+    assert!(alpha_z < 0.);
+}
+
+/// An entity which spins, but leaves its spin alone when it responds.
+#[derive(Clone, PartialEq, AsEntity, Integrable)]
+struct Ball {
+    x: f64,
+    y: f64,
+    z: f64,
+    vx: f64,
+    vy: f64,
+    vz: f64,
+    wx: f64,
+    wy: f64,
+    wz: f64,
+    radius: f64,
+    mass: f64,
+}
+
+impl Ball {
+    fn new(y: f64, vx: f64) -> Ball {
+        Ball {
+            x: 0.,
+            y,
+            z: 0.,
+            vx,
+            vy: 0.,
+            vz: 0.,
+            wx: 0.,
+            wy: 0.,
+            wz: 0.,
+            radius: 1.,
+            mass: 1.,
+        }
+    }
+}
+
+impl Responsive for Ball {
+    fn respond(&self, _simulation_result: SimulationResult<Self>, time_step: f64) -> Self {
+        Ball {
+            x: self.x + self.vx * time_step,
+            ..self.clone()
+        }
+    }
+}
+
+/// Test that a tree with friction spins a sliding entity up itself, and leaves spin alone without.
+#[test]
+fn tree_applies_friction_torque() {
+    let mut test_tree = GravTree::new(
+        &[Ball::new(0., 1.), Ball::new(-1.5, 0.)],
+        0.1,
+        3,
+        0.2,
+        CalculateCollisions::Yes,
+    );
+    let unchanged = test_tree.time_step().as_vec();
+    assert!(unchanged.iter().all(|b| (b.wx, b.wy, b.wz) == (0., 0., 0.)));
+
+    test_tree.set_friction(Some(Friction::new(10., 0.5)));
+    let after_time_step = test_tree.time_step().as_vec();
+    let sliding = after_time_step.iter().find(|b| b.y == 0.).unwrap();
+    // the torque of 2.5 about z on an inertia of 0.4, for 0.1
+    assert_eq!((sliding.wx, sliding.wy), (0., 0.));
+    assert!((sliding.wz + 0.625).abs() < 1e-12);
+}
+
+/// Test that friction on a point-like entity, which has no moment of inertia, and on a massless
+/// entity leaves their spin and velocity finite.
+#[test]
+fn friction_on_point_and_massless_entities() {
+    let point = Ball {
+        radius: 0.,
+        ..Ball::new(0., 1.)
+    };
+    let massless = Ball {
+        mass: 0.,
+        ..Ball::new(-1.5, 1.)
+    };
+    let mut test_tree = GravTree::new(
+        &[point.clone(), Ball::new(-0.5, 0.), massless.clone()],
+        0.1,
+        3,
+        0.2,
+        CalculateCollisions::Yes,
+    );
+    test_tree.set_friction(Some(Friction::new(10., 0.5)));
+    for ball in test_tree.time_step().as_vec() {
+        assert!([ball.vx, ball.vy, ball.vz, ball.wx, ball.wy, ball.wz]
+            .iter()
+            .all(|value| value.is_finite()));
+    }
+
+    let ground = Ball::new(-0.5, 0.);
+    let (a, alpha) = bigbang::collisions::friction(&point, &ground, 10., 0.5);
+    assert!(a.0 < 0.);
+    assert_eq!(alpha, (0., 0., 0.));
+    let (a, alpha) = bigbang::collisions::friction(&massless, &ground, 10., 0.5);
+    assert_eq!((a, alpha), ((0., 0., 0.), (0., 0., 0.)));
+}
+
+/// Test that test particles are pulled by the entities in the tree without pulling on them.
+#[test]
+fn test_particles_feel_but_do_not_exert_gravity() {
+    let mut test_tree = GravTree::new(
+        &[MyEntity::new(0., 0., 0., 1., 100.)],
+        0.2,
+        3,
+        0.2,
+        CalculateCollisions::Yes,
+    );
+    test_tree.add_test_particle(MyEntity::new(10., 0., 0., 1., 1000.));
+
+    let after_time_step = test_tree.time_step();
+    let massive = &after_time_step.as_vec()[0];
+    let tracer = &after_time_step.test_particles()[0];
+    assert_eq!((massive.vx, massive.vy, massive.vz), (0., 0., 0.));
+    assert!(tracer.vx < 0.);
+    assert_eq!(after_time_step.get_number_of_entities(), 1);
+}
+
+/// Test that entities with zero mass do not break tree construction or produce NaN accelerations.
+#[test]
+fn zero_mass_entities() {
+    let vec_that_wants_to_be_a_kdtree: Vec<MyEntity> = (0..20)
+        .map(|i| MyEntity::new(i as f64 * 10., 0., 0., 1., 0.))
+        .collect();
+
+    let test_tree = GravTree::new(
+        &vec_that_wants_to_be_a_kdtree,
+        0.2,
+        3,
+        0.2,
+        CalculateCollisions::Yes,
+    );
+    for entity in test_tree.time_step().as_vec() {
+        assert_eq!((entity.vx, entity.vy, entity.vz), (0., 0., 0.));
+    }
+}
+
+/// Test that the interaction matrix disables gravity one way between species, both from individual
+/// entities and from approximated nodes, and that it disables collisions between species.
+#[test]
+fn species_interaction_matrix() {
+    let mut vec_that_wants_to_be_a_kdtree: Vec<MyEntity> = (0..20)
+        .map(|i| {
+            MyEntity::new(
+                100. + (i % 4) as f64 * 0.1,
+                (i / 4) as f64 * 0.1,
+                0.,
+                0.01,
+                10.,
+            )
+        })
+        .collect();
+    let mut gas = MyEntity::new(0., 0., 0., 10., 5.);
+    gas.species = 1;
+    let mut other_gas = MyEntity::new(0., 1., 0., 10., 5.);
+    other_gas.species = 1;
+    let star = MyEntity::new(1., 0., 0., 10., 5.);
+    vec_that_wants_to_be_a_kdtree.extend(vec![gas, other_gas, star]);
+
+    let mut interactions = InteractionMatrix::new(2);
+    interactions.set_gravity(1, 0, 0.);
+    interactions.set_collisions(0, 1, false);
+    let mut test_tree = GravTree::new(
+        &vec_that_wants_to_be_a_kdtree,
+        0.2,
+        3,
+        0.2,
+        CalculateCollisions::Yes,
+    );
+    test_tree.set_interaction_matrix(Some(interactions));
+
+    let after_time_step = test_tree.time_step().as_vec();
+    let gas: Vec<&MyEntity> = after_time_step.iter().filter(|x| x.species == 1).collect();
+    assert_eq!(gas.len(), 2);
+    for entity in gas {
+        // the only gravity gas feels is the other gas, which is directly above or below it
+        assert_eq!(entity.vx, 0.);
+        assert_eq!(entity.collided_with.len(), 1);
+    }
+    let star = after_time_step
+        .iter()
+        .find(|x| x.radius == 10. && x.species == 0);
+    // the star still feels the gas, which is much closer than the cluster
+    assert!(star.unwrap().vx < 0.);
+    assert_eq!(star.unwrap().collided_with.len(), 0);
 }
@@ -0,0 +1,124 @@
+extern crate bigbang;
+use bigbang::{
+    AsEntity, CalculateCollisions, Cosmology, GravTree, Integrable, Responsive, SimulationResult,
+    TimeVariable,
+};
+
+#[derive(Clone, PartialEq, AsEntity, Integrable)]
+struct Particle {
+    x: f64,
+    y: f64,
+    z: f64,
+    vx: f64,
+    vy: f64,
+    vz: f64,
+    radius: f64,
+    mass: f64,
+}
+
+impl Particle {
+    fn new(x: f64, vx: f64, mass: f64) -> Particle {
+        Particle {
+            x,
+            y: 0.,
+            z: 0.,
+            vx,
+            vy: 0.,
+            vz: 0.,
+            radius: 0.,
+            mass,
+        }
+    }
+}
+
+impl Responsive for Particle {
+    fn respond(&self, simulation_result: SimulationResult<Self>, time_step: f64) -> Self {
+        let (ax, ay, az) = simulation_result.gravitational_acceleration;
+        let (vx, vy, vz) = (
+            self.vx + ax * time_step,
+            self.vy + ay * time_step,
+            self.vz + az * time_step,
+        );
+        Particle {
+            vx,
+            vy,
+            vz,
+            x: self.x + vx * time_step,
+            y: self.y + vy * time_step,
+            z: self.z + vz * time_step,
+            ..self.clone()
+        }
+    }
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-6 * b.abs().max(1.)
+}
+
+/// Test the cosmological integrals against the analytic Einstein-de Sitter solution.
+#[test]
+fn einstein_de_sitter() {
+    let eds = Cosmology::new(1., 0., 0.5);
+    assert!(close(eds.hubble(0.25), 0.5 * 8.));
+    // t = 2 / (3 H0) a^(3/2)
+    assert!(close(eds.cosmic_time(0.25), 2. / 1.5 * 0.125));
+    assert!(close(eds.scale_factor(2. / 1.5 * 0.125), 0.25));
+    // ∫ dt / a^2 = 2 / H0 (a0^-1/2 - a1^-1/2) and ∫ dt / a = 2 / H0 (a1^1/2 - a0^1/2)
+    assert!(close(eds.drift_factor(0.25, 1.), 4. * (2. - 1.)));
+    assert!(close(eds.kick_factor(0.25, 1.), 4. * (1. - 0.5)));
+}
+
+/// Test that a free particle's peculiar velocity decays as 1 / a, and that it drifts by the
+/// drift factor.
+#[test]
+fn comoving_free_particle() {
+    let eds = Cosmology::new(1., 0., 0.5);
+    let mut test_tree = GravTree::new(
+        &[Particle::new(1., 2., 0.)],
+        0.75,
+        3,
+        0.2,
+        CalculateCollisions::No,
+    );
+    test_tree.set_cosmology(eds, TimeVariable::ScaleFactor);
+    test_tree.set_time(0.25);
+
+    let after_time_step = test_tree.time_step();
+    assert!(close(after_time_step.get_scale_factor().unwrap(), 1.));
+    let particle = &after_time_step.as_vec()[0];
+    assert!(close(particle.vx, 2. * 0.25));
+    assert!(close(
+        particle.x,
+        1. + 2. * 0.25 * eds.drift_factor(0.25, 1.)
+    ));
+
+    // measuring time in cosmic time gives the same result
+    let mut test_tree = GravTree::new(
+        &[Particle::new(1., 2., 0.)],
+        eds.cosmic_time(1.) - eds.cosmic_time(0.25),
+        3,
+        0.2,
+        CalculateCollisions::No,
+    );
+    test_tree.set_cosmology(eds, TimeVariable::CosmicTime);
+    test_tree.set_time(eds.cosmic_time(0.25));
+    let particle = &test_tree.time_step().as_vec()[0];
+    assert!(close(particle.vx, 2. * 0.25));
+}
+
+/// Test that entities attract their nearest periodic image and are wrapped back into the box.
+#[test]
+fn periodic_box() {
+    let particles = vec![Particle::new(0.5, -1., 1.), Particle::new(9.5, 0., 1.)];
+    let mut test_tree = GravTree::new(&particles, 1., 3, 0.2, CalculateCollisions::No);
+    test_tree.set_periodic_box(Some(10.));
+
+    let after_time_step = test_tree.time_step().as_vec();
+    // each is pulled towards the other across the boundary, and ends up on the other side of it
+    let left = after_time_step.iter().find(|p| p.vx < 0.).unwrap();
+    let right = after_time_step.iter().find(|p| p.vx > 0.).unwrap();
+    assert!(close(left.vx, -2.));
+    assert!(close(left.x, 8.5));
+    assert!(close(right.vx, 1.));
+    assert!(close(right.x, 0.5));
+}
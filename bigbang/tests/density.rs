@@ -0,0 +1,136 @@
+extern crate bigbang;
+use bigbang::density::{DensityEstimator, Kernel};
+use bigbang::ics::{InitialConditions, PlummerSphere, UniformCube};
+use bigbang::{AsEntity, Entity};
+use std::f64::consts::PI;
+
+mod common;
+use common::tree;
+
+const KERNELS: [Kernel; 3] = [Kernel::CubicSpline, Kernel::WendlandC2, Kernel::WendlandC4];
+
+/// Test that every kernel integrates to one over its support, and is zero beyond it.
+#[test]
+fn normalised() {
+    for kernel in KERNELS.iter() {
+        let steps = 10_000;
+        let h = 2.;
+        let integral = (0..steps)
+            .map(|i| {
+                let r = (i as f64 + 0.5) / steps as f64 * h;
+                4. * PI * r * r * kernel.value(r, h) * h / steps as f64
+            })
+            .sum::<f64>();
+        assert!((integral - 1.).abs() < 1e-6, "{:?} {}", kernel, integral);
+        assert_eq!(kernel.value(h, h), 0.);
+        assert_eq!(kernel.value(3., h), 0.);
+        assert!(kernel.value(0., h) > kernel.value(1., h));
+    }
+}
+
+/// Test that the density inside a uniform cube is found, with every smoothing length converged.
+#[test]
+fn uniform() {
+    let entities = UniformCube {
+        mass: 8.,
+        side: 2.,
+        virial_ratio: 0.,
+    }
+    .generate(500, 3);
+    for kernel in KERNELS.iter() {
+        let estimator = DensityEstimator::new(*kernel, 64);
+        let densities = estimator.estimate(&entities);
+        assert_eq!(densities.len(), entities.len());
+        let worst = densities
+            .iter()
+            .map(|d| (d.neighbours - 64.).abs())
+            .fold(0., f64::max);
+        assert!(worst <= 64e-4, "{:?} {}", kernel, worst);
+        // away from the faces, where the cube has no neighbours on one side
+        let inner = entities
+            .iter()
+            .zip(&densities)
+            .filter(|(e, _)| e.x.abs() < 0.5 && e.y.abs() < 0.5 && e.z.abs() < 0.5)
+            .map(|(_, d)| d.density)
+            .collect::<Vec<_>>();
+        let mean = inner.iter().sum::<f64>() / inner.len() as f64;
+        // a little high, as each smoothing length is fit to the entities which happen to be near,
+        // and only good to a tenth or so from the few dozen entities in the middle
+        assert!((mean - 1.).abs() < 0.2, "{:?} {}", kernel, mean);
+        let faces = entities
+            .iter()
+            .zip(&densities)
+            .filter(|(e, _)| e.x.abs() > 0.98)
+            .map(|(_, d)| d.density)
+            .sum::<f64>();
+        assert!(faces < 0.9 * mean * entities.iter().filter(|e| e.x.abs() > 0.98).count() as f64);
+    }
+}
+
+/// Test the density of a Plummer sphere found through a tree.
+#[test]
+fn plummer() {
+    let entities = PlummerSphere {
+        mass: 1.,
+        scale_radius: 1.,
+    }
+    .generate(500, 8);
+    let tree = tree(&entities);
+    let estimator = DensityEstimator::new(Kernel::WendlandC2, 50);
+    let found = estimator.estimate_tree(&tree);
+    assert_eq!(found.len(), 500);
+
+    // the same densities are found without the tree
+    let entities = found.iter().map(|(p, _)| p.as_entity()).collect::<Vec<_>>();
+    let densities = estimator.estimate(&entities);
+    for ((_, a), b) in found.iter().zip(&densities) {
+        assert!((a.density - b.density).abs() < 1e-9 * b.density);
+    }
+
+    // the volume each entity fills, m / rho, adds up to the volume of each shell, as well as a
+    // few hundred entities can resolve it
+    let shell = |low: f64, high: f64| {
+        found.iter().filter(move |(p, _)| {
+            let r = (p.x * p.x + p.y * p.y + p.z * p.z).sqrt();
+            r > low && r < high
+        })
+    };
+    for &(low, high) in [(0.2, 0.5), (0.8, 1.2), (1.5, 2.)].iter() {
+        let filled = shell(low, high)
+            .map(|(p, d)| p.mass / d.density)
+            .sum::<f64>();
+        let volume = 4. / 3. * PI * (high.powi(3) - low.powi(3));
+        assert!(
+            (filled / volume - 1.).abs() < 0.25,
+            "{} {}",
+            low,
+            filled / volume
+        );
+    }
+    // smoothing lengths grow outwards, where entities are further apart
+    let h = |low: f64, high: f64| {
+        let lengths = shell(low, high)
+            .map(|(_, d)| d.smoothing_length)
+            .collect::<Vec<_>>();
+        lengths.iter().sum::<f64>() / lengths.len() as f64
+    };
+    assert!(h(0., 0.5) < h(2., 3.));
+}
+
+/// Test that an entity which no search radius can reach does not stall the search for the others.
+#[test]
+fn unreachable() {
+    let entities = vec![
+        Entity::default(),
+        Entity {
+            x: 1.,
+            ..Default::default()
+        },
+        Entity {
+            x: f64::NAN,
+            ..Default::default()
+        },
+    ];
+    let densities = DensityEstimator::new(Kernel::WendlandC2, 2).estimate(&entities);
+    assert_eq!(densities.len(), 3);
+}
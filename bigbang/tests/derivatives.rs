@@ -0,0 +1,131 @@
+extern crate bigbang;
+use bigbang::force_laws::Newtonian;
+use bigbang::{
+    AsEntity, CalculateCollisions, Entity, ForceLaw, GravTree, Responsive, SimulationResult,
+};
+
+#[derive(Clone, AsEntity)]
+struct Probe {
+    x: f64,
+    y: f64,
+    z: f64,
+    vx: f64,
+    vy: f64,
+    vz: f64,
+    radius: f64,
+    mass: f64,
+    jerk: (f64, f64, f64),
+    tidal_tensor: [[f64; 3]; 3],
+}
+
+impl Probe {
+    fn new(x: f64, vy: f64) -> Probe {
+        Probe {
+            x,
+            y: 0.,
+            z: 0.,
+            vx: 0.,
+            vy,
+            vz: 0.,
+            radius: 0.,
+            mass: 1.,
+            jerk: (0., 0., 0.),
+            tidal_tensor: [[0.; 3]; 3],
+        }
+    }
+}
+
+impl Responsive for Probe {
+    fn respond(&self, simulation_result: SimulationResult<Self>, _time_step: f64) -> Self {
+        Probe {
+            jerk: simulation_result.jerk,
+            tidal_tensor: simulation_result.tidal_tensor,
+            ..self.clone()
+        }
+    }
+}
+
+/// Newtonian gravity without its closed form gradient, so that the default one is used.
+#[derive(Clone)]
+struct NumericalNewtonian(Newtonian);
+
+impl ForceLaw for NumericalNewtonian {
+    fn source(&self, entity: &Entity) -> f64 {
+        self.0.source(entity)
+    }
+
+    fn acceleration(
+        &self,
+        target: &Entity,
+        displacement: (f64, f64, f64),
+        source: f64,
+    ) -> (f64, f64, f64) {
+        self.0.acceleration(target, displacement, source)
+    }
+}
+
+/// Test the jerk and tidal tensor of a unit mass passing by at unit distance.
+#[test]
+fn two_body_derivatives() {
+    let probes = vec![Probe::new(0., 0.), Probe::new(1., 1.)];
+    let mut test_tree = GravTree::new(&probes, 0.1, 3, 0.2, CalculateCollisions::No);
+    let without = test_tree.time_step().as_vec();
+    assert!(without.iter().all(|probe| probe.jerk == (0., 0., 0.)));
+
+    test_tree.set_derivatives(true);
+    let after_time_step = test_tree.time_step().as_vec();
+    let probe = after_time_step.iter().find(|probe| probe.x == 0.).unwrap();
+    // j = G m (v / r^3 - 3 (r . v) r / r^5)
+    assert_eq!(probe.jerk, (0., 1., 0.));
+    // T = G m (3 r r^T - r^2 I) / r^5
+    assert_eq!(
+        probe.tidal_tensor,
+        [[2., 0., 0.], [0., -1., 0.], [0., 0., -1.]]
+    );
+}
+
+/// Test that an entity is left out of its own tidal tensor, but not out of that of an equal
+/// entity at the same place.
+#[test]
+fn coincident_derivatives() {
+    let law = Newtonian {
+        g: 1.,
+        softening: 0.1,
+    };
+    let alone = vec![Probe::new(0., 0.)];
+    let mut test_tree =
+        GravTree::with_force_law(&alone, 0.1, 3, 0.2, CalculateCollisions::No, law.clone());
+    test_tree.set_derivatives(true);
+    assert_eq!(test_tree.time_step().as_vec()[0].tidal_tensor, [[0.; 3]; 3]);
+
+    let twins = vec![Probe::new(0., 0.), Probe::new(0., 0.)];
+    let mut test_tree = GravTree::with_force_law(&twins, 0.1, 3, 0.2, CalculateCollisions::No, law);
+    test_tree.set_derivatives(true);
+    // the softened tidal tensor at the center of a unit mass is -G m / softening^3 I
+    for probe in test_tree.time_step().as_vec() {
+        for (i, row) in probe.tidal_tensor.iter().enumerate() {
+            for (j, entry) in row.iter().enumerate() {
+                let expected = if i == j { -1000. } else { 0. };
+                assert!((entry - expected).abs() < 1e-9, "{}", entry);
+            }
+        }
+    }
+}
+
+/// Test that the default numerical gradient agrees with the closed form one.
+#[test]
+fn numerical_gradient() {
+    let newtonian = Newtonian {
+        g: 2.,
+        softening: 0.1,
+    };
+    let target = Entity::default();
+    let displacement = (0.3, -1.2, 0.7);
+    let exact = newtonian.gradient(&target, displacement, 3.);
+    let numerical = NumericalNewtonian(newtonian).gradient(&target, displacement, 3.);
+    for i in 0..3 {
+        for j in 0..3 {
+            assert!((exact[i][j] - numerical[i][j]).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+extern crate bigbang;
+use bigbang::drag::{Background, Damping, Epstein, LinearDrag, Stokes};
+use bigbang::{AsEntity, CalculateCollisions, Drag, GravTree, Responsive, SimulationResult};
+
+#[derive(Clone, PartialEq, AsEntity)]
+struct Grain {
+    x: f64,
+    y: f64,
+    z: f64,
+    vx: f64,
+    vy: f64,
+    vz: f64,
+    radius: f64,
+    mass: f64,
+    species: usize,
+}
+
+impl Grain {
+    fn new(x: f64, vx: f64, species: usize) -> Grain {
+        Grain {
+            x,
+            y: 0.,
+            z: 0.,
+            vx,
+            vy: 0.,
+            vz: 0.,
+            radius: 1.,
+            mass: 0.,
+            species,
+        }
+    }
+}
+
+impl Responsive for Grain {
+    fn respond(&self, simulation_result: SimulationResult<Self>, time_step: f64) -> Self {
+        let (ax, ay, az) = simulation_result.gravitational_acceleration;
+        Grain {
+            vx: self.vx + ax * time_step,
+            vy: self.vy + ay * time_step,
+            vz: self.vz + az * time_step,
+            ..self.clone()
+        }
+    }
+}
+
+/// Test that drag acts relative to the background and that species drag only acts on its species.
+#[test]
+fn drag_per_species() {
+    let grains = vec![Grain::new(0., 2., 0), Grain::new(100., 2., 1)];
+    let mut test_tree = GravTree::new(&grains, 0.1, 3, 0.2, CalculateCollisions::No);
+    test_tree.add_drag(Damping { rate: 1. });
+    test_tree.add_species_drag(
+        1,
+        LinearDrag {
+            rate: 2.,
+            background: Background::Uniform((1., 0., 0.)),
+        },
+    );
+
+    let after_time_step = test_tree.time_step().as_vec();
+    let damped = after_time_step.iter().find(|x| x.species == 0).unwrap();
+    // 2 - 0.1 * 2
+    assert!((damped.vx - 1.8).abs() < 1e-12);
+    let dragged = after_time_step.iter().find(|x| x.species == 1).unwrap();
+    // 2 - 0.1 * (2 + 2 * (2 - 1))
+    assert!((dragged.vx - 1.6).abs() < 1e-12);
+}
+
+/// Test that the Epstein stopping time is derived from the grain's bulk density and size.
+#[test]
+fn epstein_stopping_time() {
+    let epstein = Epstein {
+        gas_density: 2.,
+        thermal_velocity: 3.,
+        background: Background::Field(Box::new(|_position, _time| (0., 1., 0.))),
+    };
+    let mut grain = Grain::new(0., 0., 0).as_entity();
+    grain.radius = 0.5;
+    grain.mass = 4. / 3. * std::f64::consts::PI * 0.125 * 6.;
+    // a bulk density of 6 gives a stopping time of 6 * 0.5 / (2 * 3) = 0.5
+    let (ax, ay, az) = epstein.acceleration(&grain, 0.);
+    assert_eq!((ax, az), (0., 0.));
+    assert!((ay - 2.).abs() < 1e-12);
+}
+
+/// Test that massless grains, whose stopping time would be zero, feel no Epstein or Stokes drag.
+#[test]
+fn massless_grain() {
+    let grain = Grain::new(0., 5., 0).as_entity();
+    let epstein = Epstein {
+        gas_density: 2.,
+        thermal_velocity: 3.,
+        background: Background::Uniform((1., 0., 0.)),
+    };
+    let stokes = Stokes {
+        gas_density: 2.,
+        viscosity: 0.1,
+        background: Background::Uniform((1., 0., 0.)),
+    };
+    assert_eq!(epstein.acceleration(&grain, 0.), (0., 0., 0.));
+    assert_eq!(stokes.acceleration(&grain, 0.), (0., 0., 0.));
+}
@@ -0,0 +1,168 @@
+extern crate bigbang;
+use bigbang::external_fields::{
+    CompositeField, Hernquist, Logarithmic, MiyamotoNagai, Moving, Nfw, PointMass, TimeScaled,
+    UniformField,
+};
+use bigbang::SimulationResult;
+use bigbang::{AsEntity, CalculateCollisions, ExternalField, GravTree, Responsive};
+
+#[derive(Clone, PartialEq, AsEntity)]
+struct MyEntity {
+    x: f64,
+    y: f64,
+    z: f64,
+    vx: f64,
+    vy: f64,
+    vz: f64,
+    radius: f64,
+    mass: f64,
+    potential: f64,
+}
+
+impl Responsive for MyEntity {
+    fn respond(&self, simulation_result: SimulationResult<Self>, time_step: f64) -> Self {
+        let (ax, ay, az) = simulation_result.gravitational_acceleration;
+        let (vx, vy, vz) = (
+            self.vx + ax * time_step,
+            self.vy + ay * time_step,
+            self.vz + az * time_step,
+        );
+        MyEntity {
+            vx,
+            vy,
+            vz,
+            x: self.x + vx * time_step,
+            y: self.y + vy * time_step,
+            z: self.z + vz * time_step,
+            potential: simulation_result.external_potential,
+            ..self.clone()
+        }
+    }
+}
+
+/// Checks that the acceleration of a field is the negative gradient of its potential.
+fn assert_consistent<F: ExternalField>(field: &F, position: (f64, f64, f64), time: f64) {
+    let h = 1e-6;
+    let (x, y, z) = position;
+    let gradient = (
+        (field.potential((x + h, y, z), time) - field.potential((x - h, y, z), time)) / (2. * h),
+        (field.potential((x, y + h, z), time) - field.potential((x, y - h, z), time)) / (2. * h),
+        (field.potential((x, y, z + h), time) - field.potential((x, y, z - h), time)) / (2. * h),
+    );
+    let acceleration = field.acceleration(position, time);
+    assert!((acceleration.0 + gradient.0).abs() < 1e-6);
+    assert!((acceleration.1 + gradient.1).abs() < 1e-6);
+    assert!((acceleration.2 + gradient.2).abs() < 1e-6);
+}
+
+/// Test that every built-in field's acceleration agrees with its potential.
+#[test]
+fn fields_are_gradients_of_their_potentials() {
+    let position = (1.3, -0.7, 0.4);
+    assert_consistent(
+        &PointMass {
+            mass: 2.,
+            position: (0.1, 0.2, 0.3),
+            softening: 0.05,
+        },
+        position,
+        0.,
+    );
+    assert_consistent(
+        &UniformField {
+            acceleration: (0., 0., -9.8),
+        },
+        position,
+        0.,
+    );
+    assert_consistent(
+        &MiyamotoNagai {
+            mass: 1.,
+            a: 3.,
+            b: 0.3,
+        },
+        position,
+        0.,
+    );
+    assert_consistent(
+        &Hernquist {
+            mass: 5.,
+            scale_radius: 0.5,
+        },
+        position,
+        0.,
+    );
+    assert_consistent(
+        &Nfw {
+            mass: 5.,
+            scale_radius: 2.,
+        },
+        position,
+        0.,
+    );
+    assert_consistent(
+        &Logarithmic {
+            v0: 1.,
+            core_radius: 0.2,
+            flattening: 0.9,
+        },
+        position,
+        0.,
+    );
+    assert_consistent(
+        &TimeScaled {
+            field: Moving {
+                field: CompositeField::new()
+                    .with(Hernquist {
+                        mass: 1.,
+                        scale_radius: 1.,
+                    })
+                    .with(Nfw {
+                        mass: 1.,
+                        scale_radius: 1.,
+                    }),
+                center: Box::new(|t| (t, 0., 0.)),
+            },
+            amplitude: Box::new(|t| 1. - t),
+        },
+        position,
+        0.25,
+    );
+}
+
+/// Test that the tree adds the acceleration and potential of its external fields to every entity,
+/// evaluated at the current simulation time.
+#[test]
+fn tree_applies_external_fields() {
+    let entity = MyEntity {
+        x: 0.,
+        y: 0.,
+        z: 0.,
+        vx: 0.,
+        vy: 0.,
+        vz: 0.,
+        radius: 1.,
+        mass: 1.,
+        potential: 0.,
+    };
+    let mut test_tree = GravTree::new(&[entity], 0.5, 3, 0.2, CalculateCollisions::No);
+    test_tree.add_external_field(UniformField {
+        acceleration: (0., 0., -2.),
+    });
+    test_tree.add_external_field(TimeScaled {
+        field: UniformField {
+            acceleration: (1., 0., 0.),
+        },
+        amplitude: Box::new(|t| t),
+    });
+
+    let after_time_step = test_tree.time_step();
+    assert_eq!(after_time_step.get_time(), 0.5);
+    let entity = &after_time_step.as_vec()[0];
+    // the time-scaled field is zero at time zero
+    assert_eq!((entity.vx, entity.vy, entity.vz), (0., 0., -1.));
+    assert_eq!(entity.potential, 0.);
+
+    let entity = &after_time_step.time_step().as_vec()[0];
+    assert_eq!((entity.vx, entity.vy, entity.vz), (0.25, 0., -2.));
+}
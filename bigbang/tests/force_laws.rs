@@ -0,0 +1,160 @@
+extern crate bigbang;
+use bigbang::force_laws::{Coulomb, Mond, Newtonian, Yukawa};
+use bigbang::{AsEntity, CalculateCollisions, ForceLaw, GravTree, Responsive, SimulationResult};
+
+#[derive(Clone, PartialEq, AsEntity)]
+struct Charged {
+    x: f64,
+    y: f64,
+    z: f64,
+    vx: f64,
+    vy: f64,
+    vz: f64,
+    radius: f64,
+    mass: f64,
+    charge: f64,
+    acceleration: (f64, f64, f64),
+}
+
+impl Charged {
+    fn new(x: f64, y: f64, z: f64, mass: f64, charge: f64) -> Charged {
+        Charged {
+            x,
+            y,
+            z,
+            vx: 0.,
+            vy: 0.,
+            vz: 0.,
+            radius: 0.01,
+            mass,
+            charge,
+            acceleration: (0., 0., 0.),
+        }
+    }
+}
+
+impl Responsive for Charged {
+    fn respond(&self, simulation_result: SimulationResult<Self>, _time_step: f64) -> Self {
+        Charged {
+            acceleration: simulation_result.gravitational_acceleration,
+            ..self.clone()
+        }
+    }
+}
+
+/// Steps a tree of `entities` under `law` once and returns the acceleration of each entity.
+fn accelerations<F: ForceLaw>(entities: &[Charged], law: F, theta: f64) -> Vec<Charged> {
+    GravTree::with_force_law(entities, 0.1, 3, theta, CalculateCollisions::No, law)
+        .time_step()
+        .as_vec()
+}
+
+/// Test that Newtonian gravity is inverse square and attractive.
+#[test]
+fn newtonian_two_bodies() {
+    let entities = vec![
+        Charged::new(0., 0., 0., 1., 0.),
+        Charged::new(10., 0., 0., 500., 0.),
+    ];
+    let after = accelerations(
+        &entities,
+        Newtonian {
+            g: 2.,
+            softening: 0.,
+        },
+        0.2,
+    );
+    let light = after.iter().find(|x| x.mass == 1.).unwrap();
+    assert!((light.acceleration.0 - 10.).abs() < 1e-12);
+    let heavy = after.iter().find(|x| x.mass == 500.).unwrap();
+    assert!((heavy.acceleration.0 + 0.02).abs() < 1e-12);
+}
+
+/// Test that like charges repel, opposite charges attract, and that the net charge of a node is
+/// signed, so that a distant neutral cluster exerts almost nothing.
+#[test]
+fn coulomb_signed_charges() {
+    let law = Coulomb {
+        k: 1.,
+        softening: 0.,
+    };
+    let after = accelerations(
+        &[
+            Charged::new(0., 0., 0., 1., 1.),
+            Charged::new(1., 0., 0., 1., 1.),
+        ],
+        law.clone(),
+        0.2,
+    );
+    let left = after.iter().find(|x| x.x == 0.).unwrap();
+    assert!((left.acceleration.0 + 1.).abs() < 1e-12);
+
+    let after = accelerations(
+        &[
+            Charged::new(0., 0., 0., 1., 1.),
+            Charged::new(1., 0., 0., 1., -1.),
+        ],
+        law.clone(),
+        0.2,
+    );
+    let left = after.iter().find(|x| x.x == 0.).unwrap();
+    assert!((left.acceleration.0 - 1.).abs() < 1e-12);
+
+    // a neutral cluster far away, which the tree approximates as a node
+    let mut entities: Vec<Charged> = (0..32)
+        .map(|i| {
+            let charge = if i % 2 == 0 { 1. } else { -1. };
+            Charged::new(
+                100. + (i % 4) as f64 * 0.1,
+                (i / 4) as f64 * 0.1,
+                0.,
+                1.,
+                charge,
+            )
+        })
+        .collect();
+    entities.push(Charged::new(0., 0., 0., 1., 1.));
+    let after = accelerations(&entities, law, 0.5);
+    let probe = after.iter().find(|x| x.x == 0.).unwrap();
+    assert!(probe.acceleration.0.abs() < 1e-4);
+}
+
+/// Test that the screened interaction matches Coulomb at short range and is much weaker beyond
+/// the screening length.
+#[test]
+fn yukawa_screening() {
+    let coulomb = Coulomb {
+        k: 1.,
+        softening: 0.,
+    };
+    let yukawa = Yukawa {
+        k: 1.,
+        screening_length: 1.,
+        softening: 0.,
+    };
+    let target = Charged::new(0., 0., 0., 1., 1.).as_entity();
+    let near_coulomb = coulomb.acceleration(&target, (0.001, 0., 0.), 1.);
+    let near_yukawa = yukawa.acceleration(&target, (0.001, 0., 0.), 1.);
+    assert!((near_yukawa.0 / near_coulomb.0 - 1.).abs() < 1e-5);
+
+    let far_coulomb = coulomb.acceleration(&target, (10., 0., 0.), 1.);
+    let far_yukawa = yukawa.acceleration(&target, (10., 0., 0.), 1.);
+    assert!(far_yukawa.0 / far_coulomb.0 < 1e-3);
+}
+
+/// Test that MOND is Newtonian at high accelerations and tends to sqrt(a0 a_N) at low ones.
+#[test]
+fn mond_interpolation() {
+    let mond = Mond {
+        g: 1.,
+        a0: 1e-3,
+        softening: 0.,
+    };
+    let target = Charged::new(0., 0., 0., 1., 0.).as_entity();
+    let strong = mond.acceleration(&target, (1., 0., 0.), 1.);
+    assert!((strong.0 - 1.).abs() < 2e-3);
+
+    let weak = mond.acceleration(&target, (1000., 0., 0.), 1.);
+    let newtonian: f64 = 1e-6;
+    assert!((weak.0 / (1e-3 * newtonian).sqrt() - 1.).abs() < 0.05);
+}
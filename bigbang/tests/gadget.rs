@@ -0,0 +1,206 @@
+extern crate bigbang;
+use bigbang::formats::gadget::{GadgetError, GadgetFormat, GadgetParticle, Snapshot};
+use bigbang::{
+    AsEntity, Boundary, CalculateCollisions, Cosmology, Domain, GravTree, Integrable, Responsive,
+    SimulationResult, TimeVariable,
+};
+
+#[derive(Clone, AsEntity, Integrable)]
+struct Particle {
+    x: f64,
+    y: f64,
+    z: f64,
+    vx: f64,
+    vy: f64,
+    vz: f64,
+    radius: f64,
+    mass: f64,
+    species: usize,
+}
+
+impl Particle {
+    fn new(species: usize) -> Particle {
+        Particle {
+            x: 1.,
+            y: 2.,
+            z: 3.,
+            vx: 0.,
+            vy: 0.,
+            vz: 0.,
+            radius: 0.,
+            mass: 1.,
+            species,
+        }
+    }
+}
+
+impl Responsive for Particle {
+    fn respond(&self, _simulation_result: SimulationResult<Self>, _time_step: f64) -> Self {
+        self.clone()
+    }
+}
+
+/// Returns a small snapshot with dark matter of a single mass and stars of different masses, in
+/// no particular order. Every value is exactly representable in single precision.
+fn snapshot() -> Snapshot {
+    let particle = |particle_type: usize, id: u64, mass: f64| GadgetParticle {
+        particle_type,
+        id,
+        x: id as f64 * 0.5,
+        y: -(id as f64),
+        z: 0.25,
+        vx: 1.5,
+        vy: id as f64 * 2.,
+        vz: -0.125,
+        mass,
+    };
+    let mut snapshot = Snapshot {
+        header: Default::default(),
+        particles: vec![
+            particle(4, 7, 0.5),
+            particle(1, 1, 2.),
+            particle(1, 2, 2.),
+            particle(4, 8, 0.75),
+            particle(1, 3, 2.),
+        ],
+    };
+    snapshot.header.time = 0.5;
+    snapshot.header.redshift = 1.;
+    snapshot.header.box_size = 100.;
+    snapshot.header.hubble_param = 0.7;
+    snapshot.header.num_files = 1;
+    snapshot
+}
+
+fn sorted(mut particles: Vec<GadgetParticle>) -> Vec<GadgetParticle> {
+    particles.sort_by_key(|particle| particle.id);
+    particles
+}
+
+/// Test that snapshots written in either format are read back as they were.
+#[test]
+fn round_trip() {
+    let snapshot = snapshot();
+    for format in &[GadgetFormat::One, GadgetFormat::Two] {
+        let bytes = snapshot.to_bytes(*format).unwrap();
+        let read = Snapshot::from_bytes(&bytes).unwrap();
+        let mut expected = snapshot.header.clone();
+        expected.npart_total = [0, 3, 0, 0, 2, 0];
+        assert_eq!(read.header, expected);
+        assert_eq!(sorted(read.particles), sorted(snapshot.particles.clone()));
+    }
+
+    let path = std::env::temp_dir().join(format!("bigbang_{}.gadget", std::process::id()));
+    snapshot.write(&path, GadgetFormat::Two).unwrap();
+    let read = Snapshot::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(read.particles.len(), 5);
+}
+
+/// Test that the dark matter, which all has the same mass, goes in the mass table and the stars
+/// go in the mass block.
+#[test]
+fn mass_table() {
+    let bytes = snapshot().to_bytes(GadgetFormat::One).unwrap();
+    // skip the header's record marker and the particle counts
+    let mass = |particle_type: usize| {
+        let start = 4 + 24 + 8 * particle_type;
+        f64::from_le_bytes([
+            bytes[start],
+            bytes[start + 1],
+            bytes[start + 2],
+            bytes[start + 3],
+            bytes[start + 4],
+            bytes[start + 5],
+            bytes[start + 6],
+            bytes[start + 7],
+        ])
+    };
+    assert_eq!(mass(1), 2.);
+    assert_eq!(mass(4), 0.);
+    // the header, positions, velocities, IDs and two star masses, each with its markers
+    assert_eq!(
+        bytes.len(),
+        (256 + 8) + 2 * (5 * 12 + 8) + (5 * 4 + 8) + (2 * 4 + 8)
+    );
+}
+
+/// Test that the header is filled in from the state of a tree.
+#[test]
+fn from_tree() {
+    let particles = vec![Particle::new(0); 4];
+    let mut tree = GravTree::new(&particles, 0.01, 3, 0.2, CalculateCollisions::No);
+    tree.set_cosmology(Cosmology::new(0.3, 0.7, 0.1), TimeVariable::ScaleFactor);
+    tree.set_time(0.25);
+    tree.set_boundary(Some(Boundary::Periodic(Domain::cube(50.))));
+    let snapshot = Snapshot::from_tree(&tree).unwrap();
+    assert_eq!(snapshot.header.time, 0.25);
+    assert_eq!(snapshot.header.redshift, 3.);
+    assert_eq!(snapshot.header.box_size, 50.);
+    assert_eq!(snapshot.header.omega0, 0.3);
+    assert_eq!(snapshot.header.omega_lambda, 0.7);
+    let mut ids = snapshot
+        .particles
+        .iter()
+        .map(|particle| particle.id)
+        .collect::<Vec<_>>();
+    ids.sort();
+    assert_eq!(ids, vec![1, 2, 3, 4]);
+    assert!(snapshot
+        .particles
+        .iter()
+        .all(|particle| particle.particle_type == 0 && particle.as_entity().mass == 1.));
+}
+
+/// Test that a comoving tree's peculiar velocities are written as GADGET stores them, and come
+/// back exactly from the file.
+#[test]
+fn comoving_velocities() {
+    let mut particle = Particle::new(1);
+    particle.vx = 1.5;
+    particle.vz = -0.25;
+    let mut tree = GravTree::new(&[particle], 0.01, 3, 0.2, CalculateCollisions::No);
+    tree.set_cosmology(Cosmology::new(0.3, 0.7, 0.1), TimeVariable::ScaleFactor);
+    tree.set_time(0.25);
+    let snapshot = Snapshot::from_tree(&tree).unwrap();
+    assert_eq!(snapshot.particles[0].vx, 3.);
+    assert_eq!(snapshot.particles[0].vz, -0.5);
+
+    let bytes = snapshot.to_bytes(GadgetFormat::One).unwrap();
+    let read = Snapshot::from_bytes(&bytes).unwrap().comoving_particles();
+    let entity = read[0].as_entity();
+    assert_eq!((entity.vx, entity.vy, entity.vz), (1.5, 0., -0.25));
+}
+
+/// Test that broken snapshots and entities without a GADGET type are refused.
+#[test]
+fn errors() {
+    let bytes = snapshot().to_bytes(GadgetFormat::Two).unwrap();
+    match Snapshot::from_bytes(&bytes[..bytes.len() - 10]) {
+        Err(GadgetError::Malformed(_)) => (),
+        _ => panic!("read a truncated snapshot"),
+    }
+    match Snapshot::from_bytes(b"not a snapshot") {
+        Err(GadgetError::Malformed(_)) => (),
+        _ => panic!("read something which is not a snapshot"),
+    }
+
+    let tree = GravTree::new(&[Particle::new(6)], 0.01, 3, 0.2, CalculateCollisions::No);
+    match Snapshot::from_tree(&tree) {
+        Err(GadgetError::UnsupportedType(6)) => (),
+        _ => panic!("wrote an entity with no GADGET type"),
+    }
+
+    // GADGET's box is a cube with a corner at the origin
+    let mut tree = GravTree::new(&[Particle::new(0)], 0.01, 3, 0.2, CalculateCollisions::No);
+    for domain in &[
+        Domain::new((0., 0., 0.), (50., 50., 20.)),
+        Domain::new((-25., -25., -25.), (25., 25., 25.)),
+    ] {
+        tree.set_boundary(Some(Boundary::Periodic(domain.clone())));
+        match Snapshot::from_tree(&tree) {
+            Err(GadgetError::Unrepresentable(_)) => (),
+            _ => panic!("wrote a periodic domain GADGET has no box for"),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+extern crate bigbang;
+use bigbang::groups::FriendsOfFriends;
+use bigbang::ics::{InitialConditions, PlummerSphere, UniformCube};
+use bigbang::Entity;
+
+mod common;
+use common::tree;
+
+fn entity(x: f64, y: f64, vx: f64, mass: f64) -> Entity {
+    Entity {
+        x,
+        y,
+        vx,
+        mass,
+        ..Default::default()
+    }
+}
+
+/// Test that friends of friends are linked in chains, and that groups and their statistics are
+/// found.
+#[test]
+fn chains() {
+    let entities = vec![
+        // a chain of three, each only linked to its neighbours
+        entity(0., 0., 1., 1.),
+        entity(0.9, 0., 3., 1.),
+        entity(1.8, 0., 2., 2.),
+        // a lone entity
+        entity(5., 5., 0., 10.),
+        // a heavier pair
+        entity(10., 0., -1., 3.),
+        entity(10., 0.5, 1., 3.),
+    ];
+    let groups = FriendsOfFriends::new(1.).find(&entities);
+    assert_eq!(groups.groups.len(), 2);
+    assert_eq!(
+        groups.membership,
+        vec![Some(1), Some(1), Some(1), None, Some(0), Some(0)]
+    );
+
+    let pair = &groups.groups[0];
+    assert_eq!(pair.members, vec![4, 5]);
+    assert_eq!(pair.mass, 6.);
+    assert_eq!(pair.center_of_mass, (10., 0.25, 0.));
+    assert_eq!(pair.velocity, (0., 0., 0.));
+    assert!((pair.velocity_dispersion - (1f64 / 3.).sqrt()).abs() < 1e-12);
+
+    let chain = &groups.groups[1];
+    assert_eq!(chain.members, vec![0, 1, 2]);
+    assert!((chain.center_of_mass.0 - 1.125).abs() < 1e-12);
+    assert!((chain.velocity.0 - 2.).abs() < 1e-12);
+
+    // raising the minimum size leaves the pair out
+    let mut finder = FriendsOfFriends::new(1.);
+    finder.min_members = 3;
+    let groups = finder.find(&entities);
+    assert_eq!(groups.groups.len(), 1);
+    assert_eq!(groups.membership[4], None);
+    assert_eq!(groups.membership[0], Some(0));
+
+    // keeping lone entities makes every entity a member
+    finder.min_members = 1;
+    let groups = finder.find(&entities);
+    assert_eq!(groups.groups.len(), 3);
+    assert!(groups.membership.iter().all(|m| m.is_some()));
+    assert!(FriendsOfFriends::new(1.).find(&[]).groups.is_empty());
+}
+
+/// Test that entities exactly the linking length apart are friends, and that a massless group is
+/// centered on the even average of its members.
+#[test]
+fn massless_at_linking_length() {
+    let entities = vec![entity(0., 0., 1., 0.), entity(1., 0., 2., 0.)];
+    let groups = FriendsOfFriends::new(1.).find(&entities);
+    assert_eq!(groups.groups.len(), 1);
+    let pair = &groups.groups[0];
+    assert_eq!(pair.mass, 0.);
+    assert_eq!(pair.center_of_mass, (0.5, 0., 0.));
+    assert_eq!(pair.velocity, (1.5, 0., 0.));
+    assert!((pair.velocity_dispersion - (0.25f64 / 3.).sqrt()).abs() < 1e-12);
+}
+
+/// Test that the groups found through the tree match those found by comparing every pair.
+#[test]
+fn brute_force() {
+    let entities = UniformCube {
+        mass: 1.,
+        side: 10.,
+        virial_ratio: 0.,
+    }
+    .generate(800, 11);
+    let finder = FriendsOfFriends::from_mean_separation(0.5, 800, 1000.);
+    let groups = finder.find(&entities);
+
+    let n = entities.len();
+    let mut labels = (0..n).collect::<Vec<_>>();
+    loop {
+        let mut changed = false;
+        for i in 0..n {
+            for j in 0..n {
+                let (a, b) = (&entities[i], &entities[j]);
+                let distance =
+                    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt();
+                if distance <= finder.linking_length && labels[j] < labels[i] {
+                    labels[i] = labels[j];
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    let mut sizes = vec![0; n];
+    for &label in &labels {
+        sizes[label] += 1;
+    }
+    for i in 0..n {
+        for j in 0..n {
+            let same = labels[i] == labels[j];
+            if sizes[labels[i]] >= 2 {
+                assert_eq!(same, groups.membership[i] == groups.membership[j]);
+            } else {
+                assert_eq!(groups.membership[i], None);
+            }
+        }
+    }
+    assert!(groups.groups.len() > 10);
+}
+
+/// Test that two separate clusters in a tree are found as the two largest groups.
+#[test]
+fn clusters() {
+    let cluster = |mass: f64, offset: f64, seed: u64| {
+        PlummerSphere {
+            mass,
+            scale_radius: 0.1,
+        }
+        .generate(300, seed)
+        .into_iter()
+        .map(move |e| Entity {
+            x: e.x + offset,
+            ..e
+        })
+    };
+    let entities = cluster(1., -5., 1)
+        .chain(cluster(2., 5., 2))
+        .collect::<Vec<_>>();
+    let tree = tree(&entities);
+    let mut finder = FriendsOfFriends::new(0.1);
+    finder.min_members = 50;
+    let (bodies, groups) = finder.find_in_tree(&tree);
+    assert_eq!(groups.groups.len(), 2);
+    let (heavy, light) = (&groups.groups[0], &groups.groups[1]);
+    assert!(heavy.mass > light.mass);
+    assert!((heavy.center_of_mass.0 - 5.).abs() < 0.1);
+    assert!((light.center_of_mass.0 + 5.).abs() < 0.1);
+    assert!(heavy.velocity_dispersion > light.velocity_dispersion);
+    for &member in &heavy.members {
+        assert!(bodies[member].x > 0.);
+    }
+}
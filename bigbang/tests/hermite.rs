@@ -0,0 +1,151 @@
+extern crate bigbang;
+use bigbang::hermite::MAX_LEVEL;
+use bigbang::{
+    AsEntity, CalculateCollisions, Cosmology, GravTree, Hermite, Integrable, PostNewtonian,
+    Regularization, Responsive, SimulationResult, TimeVariable,
+};
+
+#[derive(Clone, AsEntity, Integrable)]
+struct Star {
+    x: f64,
+    y: f64,
+    z: f64,
+    vx: f64,
+    vy: f64,
+    vz: f64,
+    radius: f64,
+    mass: f64,
+}
+
+impl Star {
+    fn new(x: f64, vy: f64) -> Star {
+        Star {
+            x,
+            y: 0.,
+            z: 0.,
+            vx: 0.,
+            vy,
+            vz: 0.,
+            radius: 0.,
+            mass: 1.,
+        }
+    }
+}
+
+impl Responsive for Star {
+    fn respond(&self, _simulation_result: SimulationResult<Self>, _time_step: f64) -> Self {
+        panic!("entities integrated with the Hermite integrator should not respond");
+    }
+}
+
+/// Returns a binary of two unit masses at apocenter, 1 apart, and its period.
+fn binary(eccentricity: f64) -> (Vec<Star>, f64) {
+    let semi_major_axis = 1. / (1. + eccentricity);
+    let speed = f64::sqrt(2. / semi_major_axis * (1. - eccentricity) / (1. + eccentricity));
+    let period = 2. * std::f64::consts::PI * f64::sqrt(semi_major_axis.powi(3) / 2.);
+    (
+        vec![Star::new(0.5, speed / 2.), Star::new(-0.5, -speed / 2.)],
+        period,
+    )
+}
+
+fn energy(stars: &[Star]) -> f64 {
+    let kinetic = stars
+        .iter()
+        .map(|s| 0.5 * s.mass * (s.vx * s.vx + s.vy * s.vy + s.vz * s.vz))
+        .sum::<f64>();
+    let (a, b) = (&stars[0], &stars[1]);
+    let r = ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt();
+    kinetic - a.mass * b.mass / r
+}
+
+/// Test that a circular binary keeps its energy over several orbits.
+#[test]
+fn circular_binary_energy() {
+    let (stars, period) = binary(0.);
+    let mut test_tree = GravTree::new(&stars, period / 4., 3, 0.2, CalculateCollisions::No);
+    test_tree.set_hermite(Some(Hermite::default()));
+    let initial_energy = energy(&stars);
+    for _ in 0..20 {
+        test_tree = test_tree.time_step();
+    }
+    let stars = test_tree.as_vec();
+    assert!(((energy(&stars) - initial_energy) / initial_energy).abs() < 1e-5);
+}
+
+/// Test that a very eccentric binary comes back to where it started after one orbit taken as a
+/// single global time step, which needs much shorter block steps around pericenter.
+#[test]
+fn eccentric_binary_block_steps() {
+    let (stars, period) = binary(0.9);
+    let mut test_tree = GravTree::new(&stars, period, 3, 0.2, CalculateCollisions::No);
+    test_tree.set_hermite(Some(Hermite::default()));
+
+    let mut after_orbit = test_tree.time_step().as_vec();
+    after_orbit.sort_by(|a, b| b.x.partial_cmp(&a.x).unwrap());
+    for (star, start) in after_orbit.iter().zip(stars.iter()) {
+        assert!((star.x - start.x).abs() < 1e-4);
+        assert!(star.y.abs() < 1e-3);
+    }
+    assert!(((energy(&after_orbit) - energy(&stars)) / energy(&stars)).abs() < 1e-5);
+}
+
+/// Test that the Hermite integrator cannot be combined with comoving integration, which would
+/// otherwise take precedence over it.
+#[test]
+#[should_panic]
+fn hermite_in_comoving_coordinates() {
+    let (stars, period) = binary(0.);
+    let mut test_tree = GravTree::new(&stars, period, 3, 0.2, CalculateCollisions::No);
+    test_tree.set_cosmology(Cosmology::new(1., 0., 1.), TimeVariable::ScaleFactor);
+    test_tree.set_hermite(Some(Hermite::default()));
+}
+
+/// Test that settings the Hermite integrator does not apply are refused while it is in use, and
+/// accepted again once it is turned off.
+#[test]
+fn hermite_refuses_other_settings() {
+    let (stars, period) = binary(0.);
+    let mut test_tree = GravTree::new(&stars, period, 3, 0.2, CalculateCollisions::No);
+    test_tree.set_hermite(Some(Hermite::default()));
+    let refused = |set: fn(&mut GravTree<Star>)| {
+        let mut test_tree = GravTree::new(&stars, period, 3, 0.2, CalculateCollisions::No);
+        test_tree.set_hermite(Some(Hermite::default()));
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| set(&mut test_tree))).is_err()
+    };
+    assert!(refused(
+        |tree| tree.set_regularization(Some(Regularization::new(0.1)))
+    ));
+    assert!(refused(
+        |tree| tree.set_post_newtonian(Some(PostNewtonian::new(10., 1.)))
+    ));
+
+    test_tree.set_hermite(None);
+    test_tree.set_regularization(Some(Regularization::new(0.1)));
+    test_tree.set_post_newtonian(Some(PostNewtonian::new(10., 1.)));
+}
+
+/// Test that the deepest block steps allowed can still be counted in ticks.
+#[test]
+fn max_level() {
+    let (stars, period) = binary(0.9);
+    let mut test_tree = GravTree::new(&stars, period, 3, 0.2, CalculateCollisions::No);
+    test_tree.set_hermite(Some(Hermite {
+        max_level: MAX_LEVEL,
+        ..Hermite::default()
+    }));
+    let after_orbit = test_tree.time_step().as_vec();
+    assert!(after_orbit.iter().all(|star| star.x.is_finite()));
+}
+
+/// Test that block steps too short to count in ticks are refused.
+#[test]
+#[should_panic]
+fn max_level_too_deep() {
+    let (stars, period) = binary(0.);
+    let mut test_tree = GravTree::new(&stars, period, 3, 0.2, CalculateCollisions::No);
+    test_tree.set_hermite(Some(Hermite {
+        max_level: 64,
+        ..Hermite::default()
+    }));
+}
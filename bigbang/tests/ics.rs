@@ -0,0 +1,320 @@
+extern crate bigbang;
+use bigbang::ics::{
+    parabolic_merger, rotate, virial_ratio, DiskGalaxy, HernquistHalo, InitialConditions,
+    KingModel, NfwHalo, PlummerSphere, UniformCube, UniformSphere, COLD_COLLAPSE_VIRIAL_RATIO,
+};
+use bigbang::Entity;
+
+/// Returns the radius which holds half of the mass of `entities`, which all have the same mass.
+fn half_mass_radius(entities: &[Entity]) -> f64 {
+    let mut radii = entities
+        .iter()
+        .map(|e| (e.x * e.x + e.y * e.y + e.z * e.z).sqrt())
+        .collect::<Vec<_>>();
+    radii.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    radii[radii.len() / 2]
+}
+
+/// Test that the same seed gives the same entities, in their center of mass frame.
+#[test]
+fn seeded() {
+    let model = PlummerSphere {
+        mass: 10.,
+        scale_radius: 1.,
+    };
+    let a = model.generate(500, 7);
+    let b = model.generate(500, 7);
+    let c = model.generate(500, 8);
+    assert_eq!(a.len(), 500);
+    assert!(a == b);
+    assert!(a != c);
+
+    let mass = a.iter().map(|e| e.mass).sum::<f64>();
+    assert!((mass - 10.).abs() < 1e-9);
+    let momentum = a.iter().map(|e| e.mass * e.vx).sum::<f64>();
+    let center = a.iter().map(|e| e.mass * e.y).sum::<f64>();
+    assert!(momentum.abs() < 1e-9);
+    assert!(center.abs() < 1e-9);
+}
+
+/// Test that the models with distribution functions are in virial equilibrium.
+#[test]
+fn equilibrium() {
+    let models: Vec<(&str, Box<dyn InitialConditions>)> = vec![
+        (
+            "Plummer",
+            Box::new(PlummerSphere {
+                mass: 1.,
+                scale_radius: 1.,
+            }),
+        ),
+        (
+            "King",
+            Box::new(KingModel {
+                mass: 2.,
+                core_radius: 0.5,
+                w0: 6.,
+            }),
+        ),
+        (
+            "Hernquist",
+            Box::new(HernquistHalo {
+                mass: 3.,
+                scale_radius: 1.,
+                max_radius: 100.,
+            }),
+        ),
+        (
+            "NFW",
+            Box::new(NfwHalo {
+                mass: 5.,
+                scale_radius: 2.,
+                concentration: 10.,
+            }),
+        ),
+    ];
+    for (name, model) in models {
+        let ratio = virial_ratio(&model.generate(3000, 1));
+        assert!(
+            (ratio - 0.5).abs() < 0.04,
+            "{} has a ratio of {}",
+            name,
+            ratio
+        );
+    }
+}
+
+/// Test the sizes of the Plummer and King models against their known values.
+#[test]
+fn profiles() {
+    let plummer = PlummerSphere {
+        mass: 1.,
+        scale_radius: 2.,
+    }
+    .generate(10_000, 3);
+    let radius = half_mass_radius(&plummer);
+    assert!((radius / 2. - 1.305).abs() < 0.05, "{}", radius);
+
+    // a W0 = 6 King model has a concentration log10(r_t / r_0) of about 1.25
+    let king = KingModel {
+        mass: 1.,
+        core_radius: 1.,
+        w0: 6.,
+    };
+    let concentration = king.tidal_radius().log10();
+    assert!((concentration - 1.25).abs() < 0.03, "{}", concentration);
+    let entities = king.generate(2000, 3);
+    let tidal_radius = king.tidal_radius() * 1.01;
+    assert!(entities
+        .iter()
+        .all(|e| (e.x * e.x + e.y * e.y + e.z * e.z).sqrt() < tidal_radius));
+}
+
+/// Test that the uniform models fill their shapes and have the virial ratio they are given.
+#[test]
+fn uniform() {
+    let sphere = UniformSphere {
+        mass: 1.,
+        radius: 3.,
+        virial_ratio: 0.5,
+    }
+    .generate(3000, 5);
+    let ratio = virial_ratio(&sphere);
+    assert!((ratio - 0.5).abs() < 0.02, "{}", ratio);
+    assert!(sphere
+        .iter()
+        .all(|e| (e.x * e.x + e.y * e.y + e.z * e.z).sqrt() < 3.1));
+
+    let cube = UniformCube {
+        mass: 1.,
+        side: 2.,
+        virial_ratio: 0.25,
+    }
+    .generate(3000, 5);
+    let ratio = virial_ratio(&cube);
+    assert!((ratio - 0.25).abs() < 0.01, "{}", ratio);
+    assert!(cube
+        .iter()
+        .all(|e| e.x.abs() < 1.1 && e.y.abs() < 1.1 && e.z.abs() < 1.1));
+
+    let collapse = UniformSphere {
+        mass: 1.,
+        radius: 1.,
+        virial_ratio: 0.,
+    }
+    .generate(100, 5);
+    assert!(collapse
+        .iter()
+        .all(|e| e.vx == 0. && e.vy == 0. && e.vz == 0.));
+}
+
+/// Test that a cold collapse is a uniform sphere with the cold collapse virial ratio.
+#[test]
+fn cold_collapse() {
+    let model = UniformSphere::cold_collapse(2., 3.);
+    assert_eq!(model.virial_ratio, COLD_COLLAPSE_VIRIAL_RATIO);
+    let collapse = model.generate(3000, 5);
+    let ratio = virial_ratio(&collapse);
+    assert!(
+        (ratio - COLD_COLLAPSE_VIRIAL_RATIO).abs() < 0.005,
+        "{}",
+        ratio
+    );
+    assert!(collapse
+        .iter()
+        .all(|e| (e.x * e.x + e.y * e.y + e.z * e.z).sqrt() < 3.1));
+}
+
+fn galaxy() -> DiskGalaxy {
+    DiskGalaxy {
+        disk_mass: 1.,
+        disk_scale_length: 1.,
+        disk_scale_height: 0.1,
+        toomre_q: 1.5,
+        bulge: Some(HernquistHalo {
+            mass: 0.5,
+            scale_radius: 0.2,
+            max_radius: 5.,
+        }),
+        halo: Some(NfwHalo {
+            mass: 8.,
+            scale_radius: 5.,
+            concentration: 10.,
+        }),
+    }
+}
+
+/// Returns the mass-weighted center and velocity of `entities`.
+fn center(entities: &[Entity]) -> ((f64, f64, f64), (f64, f64, f64)) {
+    let mass = entities.iter().map(|e| e.mass).sum::<f64>();
+    let sum =
+        |f: &dyn Fn(&Entity) -> f64| entities.iter().map(|e| e.mass * f(e)).sum::<f64>() / mass;
+    (
+        (sum(&|e| e.x), sum(&|e| e.y), sum(&|e| e.z)),
+        (sum(&|e| e.vx), sum(&|e| e.vy), sum(&|e| e.vz)),
+    )
+}
+
+/// Test that a cold disk moves at the circular velocity of the mass within each entity.
+#[test]
+fn disk_rotation() {
+    let disk = DiskGalaxy {
+        toomre_q: 0.,
+        bulge: None,
+        halo: None,
+        ..galaxy()
+    };
+    let entities = disk.generate(2000, 9);
+    assert_eq!(entities.len(), 2000);
+    let radius = |e: &Entity| (e.x * e.x + e.y * e.y + e.z * e.z).sqrt();
+    for e in entities.iter().step_by(97) {
+        let r = radius(e);
+        let cylindrical = (e.x * e.x + e.y * e.y).sqrt();
+        let within = entities
+            .iter()
+            .filter(|other| radius(other) < r)
+            .map(|other| other.mass)
+            .sum::<f64>();
+        let circular = (within * cylindrical * cylindrical / (r * r * r)).sqrt();
+        // the tangential velocity, counterclockwise about z
+        let tangential = (e.x * e.vy - e.y * e.vx) / cylindrical;
+        // up to the small velocity the galaxy is shifted by to put it at rest
+        assert!(
+            (tangential - circular).abs() < 0.01,
+            "{} {}",
+            tangential,
+            circular
+        );
+    }
+}
+
+/// Test that the components of a galaxy share the entities by mass, and that the galaxy rotates.
+#[test]
+fn disk_galaxy() {
+    let galaxy = galaxy();
+    let entities = galaxy.generate(3000, 4);
+    assert_eq!(entities.len(), 3000);
+    let mass = entities.iter().map(|e| e.mass).sum::<f64>();
+    assert!((mass - galaxy.mass()).abs() < 1e-9);
+    assert!(entities
+        .iter()
+        .all(|e| (e.mass - mass / 3000.).abs() < 1e-3 * e.mass));
+    let spin = entities
+        .iter()
+        .map(|e| e.mass * (e.x * e.vy - e.y * e.vx))
+        .sum::<f64>();
+    assert!(spin > 0.);
+    let (position, velocity) = center(&entities);
+    assert!(position.0.abs() < 1e-9 && velocity.2.abs() < 1e-9);
+
+    // tilting the galaxy onto its side turns its spin from z to y
+    let tilted = rotate(entities, std::f64::consts::FRAC_PI_2, 0.);
+    let spin_y = tilted
+        .iter()
+        .map(|e| e.mass * (e.z * e.vx - e.x * e.vz))
+        .sum::<f64>();
+    assert!((spin_y + spin).abs() < 1e-9 * spin.abs());
+}
+
+/// Test that the components of a galaxy always share exactly the number of entities asked for.
+#[test]
+fn disk_galaxy_count() {
+    // with an odd count, the bulge and halo would each round half an entity up
+    let spheroids = DiskGalaxy {
+        disk_mass: 0.,
+        halo: Some(NfwHalo {
+            mass: 0.5,
+            scale_radius: 5.,
+            concentration: 10.,
+        }),
+        ..galaxy()
+    };
+    for count in 1..20 {
+        assert_eq!(spheroids.generate(count, 2).len(), count);
+        assert_eq!(galaxy().generate(count, 2).len(), count);
+    }
+}
+
+/// Test that a galaxy without any mass to share its entities out by is refused.
+#[test]
+#[should_panic]
+fn massless_disk_galaxy() {
+    DiskGalaxy {
+        disk_mass: 0.,
+        bulge: None,
+        halo: None,
+        ..galaxy()
+    }
+    .generate(1000, 2);
+}
+
+/// Test that two galaxies are placed on a parabolic orbit in their center of mass frame.
+#[test]
+fn merger() {
+    let first = galaxy().generate(1000, 1);
+    let second = PlummerSphere {
+        mass: 3.,
+        scale_radius: 1.,
+    }
+    .generate(400, 2);
+    let merged = parabolic_merger(first, second, 2., 30.);
+    assert_eq!(merged.len(), 1400);
+    let (position, velocity) = center(&merged);
+    assert!(position.0.abs() < 1e-9 && position.1.abs() < 1e-9);
+    assert!(velocity.0.abs() < 1e-9 && velocity.1.abs() < 1e-9);
+
+    let (p1, v1) = center(&merged[..1000]);
+    let (p2, v2) = center(&merged[1000..]);
+    let d = (p2.0 - p1.0, p2.1 - p1.1, p2.2 - p1.2);
+    let v = (v2.0 - v1.0, v2.1 - v1.1, v2.2 - v1.2);
+    let separation = (d.0 * d.0 + d.1 * d.1 + d.2 * d.2).sqrt();
+    assert!((separation - 30.).abs() < 1e-9);
+    // a parabolic orbit has zero energy, and the galaxies are falling together
+    let total = galaxy().mass() + 3.;
+    let energy = 0.5 * (v.0 * v.0 + v.1 * v.1 + v.2 * v.2) - total / separation;
+    assert!(energy.abs() < 1e-9);
+    assert!(d.0 * v.0 + d.1 * v.1 < 0.);
+    // the angular momentum of the relative orbit gives the pericenter, h^2 = 2 G M q
+    let h = d.0 * v.1 - d.1 * v.0;
+    assert!((h * h / (2. * total) - 2.).abs() < 1e-9);
+}
@@ -0,0 +1,167 @@
+extern crate bigbang;
+use bigbang::kepler::{
+    orbital_elements, planetary_system, planets, solar_system, Body, Elements, G_SOLAR,
+};
+use bigbang::{AsEntity, CalculateCollisions, GravTree, Responsive, SimulationResult};
+use std::f64::consts::PI;
+
+#[derive(Clone, AsEntity)]
+struct Particle {
+    x: f64,
+    y: f64,
+    z: f64,
+    vx: f64,
+    vy: f64,
+    vz: f64,
+    radius: f64,
+    mass: f64,
+    id: usize,
+}
+
+impl Responsive for Particle {
+    fn respond(&self, _: SimulationResult<Self>, _: f64) -> Self {
+        self.clone()
+    }
+}
+
+fn close(a: &Elements, b: &Elements, tolerance: f64) -> bool {
+    let angle = |x: f64, y: f64| {
+        let difference = (x - y).rem_euclid(2. * PI);
+        difference.min(2. * PI - difference) < tolerance
+    };
+    (a.semi_major_axis - b.semi_major_axis).abs() < tolerance * b.semi_major_axis.abs()
+        && (a.eccentricity - b.eccentricity).abs() < tolerance
+        && (a.inclination - b.inclination).abs() < tolerance
+        && angle(a.longitude_of_ascending_node, b.longitude_of_ascending_node)
+        && angle(a.argument_of_periapsis, b.argument_of_periapsis)
+        && angle(a.mean_anomaly, b.mean_anomaly)
+}
+
+/// Test that elliptic and hyperbolic elements survive a round trip through positions and
+/// velocities.
+#[test]
+fn round_trip() {
+    let orbits = [
+        (2., 0.3, 0.4, 1.2, 2.5, 0.7),
+        (0.5, 0.95, 2.8, 5.9, 0.2, 3.5),
+        (10., 0.01, 0.05, 3., 4., 6.),
+        (-3., 1.5, 1., 0.5, 1.5, 2.),
+        (-0.2, 4., 0.3, 4., 5., -7.),
+    ];
+    for &(a, e, i, node, w, m) in orbits.iter() {
+        let elements = Elements {
+            semi_major_axis: a,
+            eccentricity: e,
+            inclination: i,
+            longitude_of_ascending_node: node,
+            argument_of_periapsis: w,
+            mean_anomaly: m,
+        };
+        let (position, velocity) = elements.to_state(3.);
+        let found = Elements::from_state(position, velocity, 3.);
+        assert!(close(&found, &elements, 1e-9), "{:?} {:?}", found, elements);
+    }
+}
+
+/// Test the state of simple orbits against their known values.
+#[test]
+fn known_orbits() {
+    let circular = Elements {
+        semi_major_axis: 2.,
+        eccentricity: 0.,
+        inclination: PI / 2.,
+        longitude_of_ascending_node: 0.,
+        argument_of_periapsis: 0.,
+        mean_anomaly: PI / 2.,
+    };
+    // a quarter of the way round a polar orbit is over the north pole, heading back down
+    let (position, velocity) = circular.to_state(8.);
+    assert!(position.0.abs() < 1e-12 && position.1.abs() < 1e-12);
+    assert!((position.2 - 2.).abs() < 1e-12);
+    assert!((velocity.0 + 2.).abs() < 1e-12 && velocity.1.abs() < 1e-12);
+    assert!((circular.period(8.).unwrap() - 2. * PI).abs() < 1e-12);
+
+    // an eccentric orbit is at its apoapsis half way round, moving at its slowest
+    let eccentric = Elements {
+        eccentricity: 0.5,
+        mean_anomaly: PI,
+        inclination: 0.,
+        ..circular
+    };
+    let (position, velocity) = eccentric.to_state(1.);
+    assert!((position.0 + 3.).abs() < 1e-12);
+    let speed = (velocity.0 * velocity.0 + velocity.1 * velocity.1).sqrt();
+    assert!((speed - (1. / 6f64).sqrt()).abs() < 1e-12);
+
+    // retrograde orbits in the x-y plane are measured from the x axis
+    let found = Elements::from_state((1., 0., 0.), (0., -1., 0.), 1.);
+    assert!((found.inclination - PI).abs() < 1e-12);
+    assert!(found.eccentricity < 1e-12);
+    assert!(found.longitude_of_ascending_node == 0. && found.mean_anomaly.abs() < 1e-12);
+}
+
+/// Test that the solar system is built in its barycentric frame, and that the planets' elements
+/// are found again from a tree.
+#[test]
+fn solar() {
+    let entities = solar_system();
+    assert_eq!(entities.len(), 9);
+    let mass = entities.iter().map(|e| e.mass).sum::<f64>();
+    let momentum = entities.iter().map(|e| e.mass * e.vy).sum::<f64>();
+    let center = entities.iter().map(|e| e.mass * e.x).sum::<f64>() / mass;
+    assert!(momentum.abs() < 1e-15 && center.abs() < 1e-15);
+    // the Sun is pulled about 0.005 AU from the barycenter, mostly by Jupiter
+    let sun = (entities[0].x.powi(2) + entities[0].y.powi(2)).sqrt();
+    assert!(sun > 0.002 && sun < 0.01, "{}", sun);
+
+    let particles = entities
+        .iter()
+        .enumerate()
+        .map(|(id, e)| Particle {
+            x: e.x,
+            y: e.y,
+            z: e.z,
+            vx: e.vx,
+            vy: e.vy,
+            vz: e.vz,
+            radius: e.radius,
+            mass: e.mass,
+            id,
+        })
+        .collect::<Vec<_>>();
+    let tree = GravTree::new(&particles, 0.001, 2, 0.2, CalculateCollisions::No);
+    let found = orbital_elements(&tree, G_SOLAR);
+    assert_eq!(found.len(), 8);
+    let planets = planets();
+    for (particle, elements) in found {
+        assert!(particle.id > 0);
+        assert!(close(&elements, &planets[particle.id - 1].elements, 1e-9));
+    }
+    // Earth takes a year to go round
+    let earth = planets[2].elements.period(G_SOLAR * (1. + planets[2].mass));
+    assert!((earth.unwrap() - 1.).abs() < 1e-4);
+}
+
+/// Test that a body on a hyperbolic orbit is placed in a planetary system.
+#[test]
+fn flyby() {
+    let body = Body {
+        mass: 0.5,
+        radius: 0.,
+        elements: Elements {
+            semi_major_axis: -1.,
+            eccentricity: 2.,
+            inclination: 0.,
+            longitude_of_ascending_node: 0.,
+            argument_of_periapsis: 0.,
+            mean_anomaly: 0.,
+        },
+    };
+    let entities = planetary_system(1.5, 0.1, &[body], 1.);
+    assert!(body.elements.period(2.).is_none());
+    let (sun, visitor) = (&entities[0], &entities[1]);
+    // at periapsis, a (1 - e) = 1 from the central mass, at the speed sqrt(mu (e + 1) / q)
+    assert!(((visitor.x - sun.x) - 1.).abs() < 1e-12);
+    assert!(((visitor.vy - sun.vy) - 6f64.sqrt()).abs() < 1e-12);
+    assert!((1.5 * sun.x + 0.5 * visitor.x).abs() < 1e-12);
+}
@@ -0,0 +1,163 @@
+extern crate bigbang;
+use bigbang::formats::npy::{self, Array, NpyError, State};
+use bigbang::{AsEntity, Entity, GravTree};
+
+mod common;
+use common::{tree, Particle};
+
+/// Returns a tree of five entities at a time of 2.5.
+fn state_tree() -> GravTree<Particle> {
+    let entities = (0..5)
+        .map(|i| Entity {
+            x: i as f64,
+            y: -(i as f64) / 3.,
+            z: 0.1 * i as f64,
+            vx: 1.,
+            vy: i as f64,
+            vz: -0.5,
+            radius: 0.01,
+            mass: 1. + i as f64,
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+    let mut tree = tree(&entities);
+    tree.set_time(2.5);
+    tree
+}
+
+/// Returns the contents of a `.npy` file with the header `header` and data `data`.
+fn npy_file(header: &str, data: &[u8]) -> Vec<u8> {
+    let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+/// Test that arrays are written as NumPy writes them and read back as they were.
+#[test]
+fn array_round_trip() {
+    let array = Array::new(vec![2, 3], vec![1., 2., 3., 4., 5., 6.]);
+    let bytes = array.to_bytes();
+    assert!(bytes.starts_with(b"\x93NUMPY\x01\x00"));
+    // the data starts on a multiple of 64 bytes and the header ends with a newline
+    assert_eq!((bytes.len() - 6 * 8) % 64, 0);
+    assert_eq!(bytes[bytes.len() - 6 * 8 - 1], b'\n');
+    let header = String::from_utf8_lossy(&bytes[10..bytes.len() - 6 * 8]).into_owned();
+    assert!(header.contains("'descr': '<f8'"));
+    assert!(header.contains("'shape': (2, 3)"));
+    assert_eq!(Array::from_bytes(&bytes).unwrap(), array);
+
+    let scalar = Array::new(Vec::new(), vec![7.]);
+    assert_eq!(Array::from_bytes(&scalar.to_bytes()).unwrap(), scalar);
+    let vector = Array::new(vec![4], vec![1., 2., 3., 4.]);
+    let bytes = vector.to_bytes();
+    assert!(String::from_utf8_lossy(&bytes).contains("'shape': (4,)"));
+    assert_eq!(Array::from_bytes(&bytes).unwrap(), vector);
+}
+
+/// Test that arrays of other types, byte orders and memory orders are converted.
+#[test]
+fn read_other_layouts() {
+    let data = [1f32, 4., 2., 5., 3., 6.]
+        .iter()
+        .flat_map(|value| value.to_be_bytes().to_vec())
+        .collect::<Vec<_>>();
+    let bytes = npy_file(
+        "{'descr': '>f4', 'fortran_order': True, 'shape': (2, 3), }\n",
+        &data,
+    );
+    let array = Array::from_bytes(&bytes).unwrap();
+    assert_eq!(array.shape, vec![2, 3]);
+    assert_eq!(array.data, vec![1., 2., 3., 4., 5., 6.]);
+
+    let data = [-3i32, 7]
+        .iter()
+        .flat_map(|value| value.to_le_bytes().to_vec())
+        .collect::<Vec<_>>();
+    let bytes = npy_file(
+        "{'descr': '<i4', 'fortran_order': False, 'shape': (2,), }\n",
+        &data,
+    );
+    assert_eq!(Array::from_bytes(&bytes).unwrap().data, vec![-3., 7.]);
+
+    let bytes = npy_file(
+        "{'descr': '<U8', 'fortran_order': False, 'shape': (1,), }\n",
+        &[0; 32],
+    );
+    match Array::from_bytes(&bytes) {
+        Err(NpyError::Unsupported(_)) => (),
+        other => panic!("expected an unsupported type, not {:?}", other),
+    }
+}
+
+/// Test that the state of a tree survives a round trip through an archive and a directory.
+#[test]
+fn state_round_trip() {
+    let tree = state_tree();
+    let state = State::from_tree(&tree);
+    assert_eq!(state.time, 2.5);
+    assert_eq!(state.masses.len(), 5);
+    assert!(state.accelerations.iter().all(|a| a.0 != 0.));
+
+    let dir = std::env::temp_dir().join(format!("bigbang_npy_{}", std::process::id()));
+    let path = dir.with_extension("npz");
+    state.write_npz(&path).unwrap();
+    let read = State::read_npz(&path).unwrap();
+    let names = npy::read_npz(&path)
+        .unwrap()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(read, state);
+    assert_eq!(
+        names,
+        vec![
+            "time",
+            "positions",
+            "velocities",
+            "masses",
+            "radii",
+            "accelerations"
+        ]
+    );
+
+    state.write_dir(&dir).unwrap();
+    let read = State::read_dir(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(read, state);
+
+    let entities = read.entities();
+    let expected = tree.as_vec();
+    for (entity, particle) in entities.iter().zip(expected.iter()) {
+        assert!(*entity == particle.as_entity());
+    }
+}
+
+/// Test that missing arrays and corrupted archives are reported.
+#[test]
+fn bad_archives() {
+    let path = std::env::temp_dir().join(format!("bigbang_bad_{}.npz", std::process::id()));
+    npy::write_npz(&path, &[("masses", Array::new(vec![1], vec![1.]))]).unwrap();
+    match State::read_npz(&path) {
+        Err(NpyError::Malformed(reason)) => assert!(reason.contains("positions")),
+        other => panic!("expected a missing array, not {:?}", other),
+    }
+
+    let mut bytes = std::fs::read(&path).unwrap();
+    // flip a bit in the last byte of the array's data
+    let last = bytes
+        .windows(4)
+        .position(|window| window == [0x50, 0x4b, 0x01, 0x02])
+        .unwrap()
+        - 1;
+    bytes[last] ^= 1;
+    std::fs::write(&path, &bytes).unwrap();
+    let result = npy::read_npz(&path);
+    std::fs::remove_file(&path).unwrap();
+    match result {
+        Err(NpyError::Malformed(reason)) => assert!(reason.contains("corrupt")),
+        other => panic!("expected a corrupt archive, not {:?}", other),
+    }
+}
@@ -0,0 +1,129 @@
+extern crate bigbang;
+use bigbang::{
+    AsEntity, CalculateCollisions, Entity, GravTree, Integrable, PostNewtonian, Responsive,
+    SimulationResult,
+};
+
+#[derive(Clone, AsEntity, Integrable)]
+struct Body {
+    x: f64,
+    y: f64,
+    z: f64,
+    vx: f64,
+    vy: f64,
+    vz: f64,
+    radius: f64,
+    mass: f64,
+}
+
+impl Responsive for Body {
+    fn respond(&self, simulation_result: SimulationResult<Self>, time_step: f64) -> Self {
+        let (ax, ay, az) = simulation_result.gravitational_acceleration;
+        Body {
+            vx: self.vx + ax * time_step,
+            vy: self.vy + ay * time_step,
+            vz: self.vz + az * time_step,
+            ..self.clone()
+        }
+    }
+}
+
+fn entity(x: f64, vy: f64, mass: f64) -> Entity {
+    Entity {
+        x,
+        vy,
+        mass,
+        ..Default::default()
+    }
+}
+
+/// Test the 1PN correction for a test particle at rest, which weakens gravity by `4 m / (r c^2)`
+/// in harmonic coordinates.
+#[test]
+fn test_particle_at_rest() {
+    let post_newtonian = PostNewtonian::new(10., 1.);
+    let (ax, ay, az) = post_newtonian.correction(&entity(2., 0., 0.), &entity(0., 0., 3.));
+    // 4 m^2 / (r^3 c^2) = 4 * 9 / (8 * 100)
+    assert!((ax - 0.045).abs() < 1e-12);
+    assert_eq!((ay, az), (0., 0.));
+}
+
+/// Test that the radiation reaction takes energy out of a circular binary.
+#[test]
+fn radiation_reaction_opposes_motion() {
+    let post_newtonian = PostNewtonian::new(10., 1.);
+    // a circular orbit of two unit masses a distance 1 apart, in the center of mass frame
+    let speed = f64::sqrt(2.) / 2.;
+    let (star, companion) = (entity(0.5, speed, 1.), entity(-0.5, -speed, 1.));
+    let conservative = post_newtonian.correction(&star, &companion);
+    let radiative = post_newtonian
+        .with_radiation_reaction()
+        .correction(&star, &companion);
+    assert_eq!(conservative.1, 0.);
+    assert!(radiative.1 < 0.);
+}
+
+/// Test that only pairs closer than the threshold are corrected.
+#[test]
+fn threshold() {
+    let body = |x| Body {
+        x,
+        y: 0.,
+        z: 0.,
+        vx: 0.,
+        vy: 0.,
+        vz: 0.,
+        radius: 0.,
+        mass: 1.,
+    };
+    let bodies = vec![body(0.), body(10.)];
+    let newtonian = GravTree::new(&bodies, 0.1, 3, 0.2, CalculateCollisions::No).time_step();
+    let mut test_tree = GravTree::new(&bodies, 0.1, 3, 0.2, CalculateCollisions::No);
+
+    test_tree.set_post_newtonian(Some(PostNewtonian::new(1., 5.)));
+    let far = test_tree.time_step();
+    test_tree.set_post_newtonian(Some(PostNewtonian::new(1., 20.)));
+    let near = test_tree.time_step();
+
+    let vx = |tree: &GravTree<Body>| {
+        let mut entities = tree.as_vec();
+        entities.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap());
+        entities[0].vx
+    };
+    assert_eq!(vx(&far), vx(&newtonian));
+    assert!(vx(&near) < vx(&newtonian));
+}
+
+/// Test that a pair across the edge of a periodic box is corrected at its nearest image, just as
+/// the same pair in the middle of the box is.
+#[test]
+fn periodic_images() {
+    let pulled = |left: f64, right: f64, post_newtonian: Option<PostNewtonian>| {
+        let body = |x| Body {
+            x,
+            y: 0.,
+            z: 0.,
+            vx: 0.,
+            vy: 0.,
+            vz: 0.,
+            radius: 0.,
+            mass: 1.,
+        };
+        let bodies = vec![body(left), body(right)];
+        let mut test_tree = GravTree::new(&bodies, 0.1, 3, 0.2, CalculateCollisions::No);
+        test_tree.set_periodic_box(Some(10.));
+        test_tree.set_post_newtonian(post_newtonian);
+        let after_time_step = test_tree.time_step().as_vec();
+        // the velocity of the entity which started out at left
+        after_time_step
+            .iter()
+            .find(|b| (b.x - left).abs() < 1.)
+            .unwrap()
+            .vx
+    };
+    let post_newtonian = Some(PostNewtonian::new(1., 2.));
+    let across = pulled(0.5, 9.5, post_newtonian.clone());
+    let inside = pulled(4.5, 5.5, post_newtonian);
+    assert!((across + inside).abs() < 1e-12, "{} {}", across, inside);
+    assert!(inside != pulled(4.5, 5.5, None));
+}
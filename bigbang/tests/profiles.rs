@@ -0,0 +1,213 @@
+extern crate bigbang;
+use bigbang::density::{DensityEstimator, Kernel};
+use bigbang::ics::{InitialConditions, PlummerSphere, UniformSphere};
+use bigbang::profiles::{center, lagrangian_radii, radial_profile, Binning, Center};
+use bigbang::Entity;
+
+mod common;
+use common::tree;
+
+fn plummer(count: usize) -> Vec<Entity> {
+    PlummerSphere {
+        mass: 1.,
+        scale_radius: 1.,
+    }
+    .generate(count, 12)
+}
+
+/// Test the Lagrangian radii of a Plummer sphere against their known values,
+/// `a / sqrt(f^(-2/3) - 1)`.
+#[test]
+fn lagrangian() {
+    let tree = tree(&plummer(5000));
+    let radii = lagrangian_radii(&tree, &Center::CenterOfMass, &[0.1, 0.5, 0.9]);
+    for (radius, expected) in radii.iter().zip(&[0.5227, 1.3048, 3.7064]) {
+        assert!(
+            (radius / expected - 1.).abs() < 0.05,
+            "{} {}",
+            radius,
+            expected
+        );
+    }
+    // every entity is within the whole mass, and none are within none of it
+    let extremes = lagrangian_radii(&tree, &Center::CenterOfMass, &[0., 1.]);
+    let furthest = tree
+        .as_vec()
+        .iter()
+        .map(|p| (p.x * p.x + p.y * p.y + p.z * p.z).sqrt())
+        .fold(0., f64::max);
+    assert_eq!(extremes[0], 0.);
+    assert!((extremes[1] - furthest).abs() < 1e-9);
+}
+
+/// Test the density, dispersion and anisotropy of a Plummer sphere against its profile.
+#[test]
+fn plummer_profile() {
+    let tree = tree(&plummer(10_000));
+    let binning = Binning::Logarithmic {
+        min: 0.2,
+        max: 5.,
+        count: 6,
+    };
+    let shells = radial_profile(&tree, &Center::CenterOfMass, &binning);
+    assert_eq!(shells.len(), 6);
+    assert!((shells[0].inner_radius - 0.2).abs() < 1e-12);
+    assert!((shells[5].outer_radius - 5.).abs() < 1e-12);
+    for shell in shells {
+        let r = (shell.inner_radius * shell.outer_radius).sqrt();
+        let enclosed = |r: f64| r.powi(3) / (1. + r * r).powf(1.5);
+        let volume = 4. / 3.
+            * std::f64::consts::PI
+            * (shell.outer_radius.powi(3) - shell.inner_radius.powi(3));
+        let density = (enclosed(shell.outer_radius) - enclosed(shell.inner_radius)) / volume;
+        assert!(
+            (shell.density / density - 1.).abs() < 0.15,
+            "{} {}",
+            r,
+            shell.density / density
+        );
+        // the Plummer sphere is isotropic, with sigma^2 = G M / 6 sqrt(r^2 + a^2)
+        let dispersion = (1. / (6. * (1. + r * r).sqrt())).sqrt();
+        assert!(
+            (shell.velocity_dispersion / dispersion - 1.).abs() < 0.15,
+            "{} {}",
+            r,
+            shell.velocity_dispersion / dispersion
+        );
+        assert!(shell.anisotropy.abs() < 0.25, "{} {}", r, shell.anisotropy);
+        assert!(shell.radial_velocity.abs() < 0.1 * dispersion);
+    }
+}
+
+/// Test that the density center stays with the core of a cluster when a distant, spread out
+/// cloud pulls the center of mass away from it.
+#[test]
+fn density_center() {
+    let cloud = UniformSphere {
+        mass: 1.,
+        radius: 10.,
+        virial_ratio: 0.,
+    }
+    .generate(2000, 4)
+    .into_iter()
+    .map(|e| Entity {
+        x: e.x + 20.,
+        vx: e.vx + 1.,
+        ..e
+    });
+    let mut entities = plummer(2000);
+    entities.extend(cloud);
+    let tree = tree(&entities);
+
+    let (mass_center, mass_velocity) = center(&tree, &Center::CenterOfMass);
+    assert!((mass_center.0 - 10.).abs() < 0.5);
+    assert!((mass_velocity.0 - 0.5).abs() < 1e-9);
+    let estimator = DensityEstimator::new(Kernel::CubicSpline, 32);
+    let density_center = Center::DensityCenter(estimator);
+    let (position, velocity) = center(&tree, &density_center);
+    assert!(position.0.abs() < 0.2, "{:?}", position);
+    assert!(velocity.0.abs() < 0.1, "{:?}", velocity);
+
+    let fixed = Center::Fixed((20., 0., 0.), (1., 0., 0.));
+    let half = lagrangian_radii(&tree, &density_center, &[0.25])[0];
+    assert!((half / 1.3048 - 1.).abs() < 0.1, "{}", half);
+    let half = lagrangian_radii(&tree, &fixed, &[0.25])[0];
+    assert!(half > 5.);
+}
+
+/// Test shells of equal counts, and the anisotropy of radial and circular orbits.
+#[test]
+fn binning() {
+    let entities = (0..10)
+        .map(|i| {
+            let r = 1. + i as f64;
+            // the inner half fall straight in, and the outer half go round
+            let (vx, vy) = if i < 5 { (-r, 0.) } else { (0., r) };
+            Entity {
+                x: r,
+                vx,
+                vy,
+                mass: 2.,
+                ..Default::default()
+            }
+        })
+        .collect::<Vec<_>>();
+    let tree = tree(&entities);
+    let origin = Center::Fixed((0., 0., 0.), (0., 0., 0.));
+    let shells = radial_profile(&tree, &origin, &Binning::EqualCount { entities: 4 });
+    assert_eq!(
+        shells.iter().map(|s| s.count).collect::<Vec<_>>(),
+        vec![4, 4, 2]
+    );
+    assert_eq!(shells[0].inner_radius, 0.);
+    assert_eq!(shells[0].outer_radius, 4.5);
+    assert_eq!(shells[2].outer_radius, 10.);
+    assert_eq!(shells[0].mass, 8.);
+    assert_eq!(shells[0].anisotropy, 1.);
+    assert!((shells[0].radial_velocity + 2.5).abs() < 1e-12);
+    assert!((shells[0].radial_dispersion - 1.25f64.sqrt()).abs() < 1e-12);
+
+    // the last entity is too few for a shell of its own
+    let thirds = radial_profile(&tree, &origin, &Binning::EqualCount { entities: 3 });
+    assert_eq!(
+        thirds.iter().map(|s| s.count).collect::<Vec<_>>(),
+        vec![3, 3, 4]
+    );
+
+    let shells = radial_profile(
+        &tree,
+        &origin,
+        &Binning::Linear {
+            min: 5.5,
+            max: 10.,
+            count: 3,
+        },
+    );
+    assert_eq!(
+        shells.iter().map(|s| s.count).collect::<Vec<_>>(),
+        vec![1, 2, 2]
+    );
+    assert!(shells.iter().all(|s| s.anisotropy == f64::NEG_INFINITY));
+    let empty = radial_profile(
+        &tree,
+        &origin,
+        &Binning::Linear {
+            min: 20.,
+            max: 30.,
+            count: 2,
+        },
+    );
+    assert!(empty.iter().all(|s| s.count == 0 && s.anisotropy == 0.));
+}
+
+#[test]
+#[should_panic]
+fn no_shells() {
+    let tree = tree(&plummer(10));
+    let origin = Center::Fixed((0., 0., 0.), (0., 0., 0.));
+    radial_profile(
+        &tree,
+        &origin,
+        &Binning::Linear {
+            min: 0.,
+            max: 1.,
+            count: 0,
+        },
+    );
+}
+
+#[test]
+#[should_panic]
+fn logarithmic_from_zero() {
+    let tree = tree(&plummer(10));
+    let origin = Center::Fixed((0., 0., 0.), (0., 0., 0.));
+    radial_profile(
+        &tree,
+        &origin,
+        &Binning::Logarithmic {
+            min: 0.,
+            max: 1.,
+            count: 4,
+        },
+    );
+}
@@ -0,0 +1,122 @@
+extern crate bigbang;
+use bigbang::{
+    AsEntity, CalculateCollisions, Entity, GravTree, Integrable, Regularization, Responsive,
+    SimulationResult,
+};
+
+#[derive(Clone, AsEntity, Integrable)]
+struct Star {
+    x: f64,
+    y: f64,
+    z: f64,
+    vx: f64,
+    vy: f64,
+    vz: f64,
+    radius: f64,
+    mass: f64,
+}
+
+impl Star {
+    fn new(x: f64, vy: f64, mass: f64) -> Star {
+        Star {
+            x,
+            y: 0.,
+            z: 0.,
+            vx: 0.,
+            vy,
+            vz: 0.,
+            radius: 0.,
+            mass,
+        }
+    }
+}
+
+impl Responsive for Star {
+    fn respond(&self, simulation_result: SimulationResult<Self>, time_step: f64) -> Self {
+        let (ax, ay, az) = simulation_result.gravitational_acceleration;
+        let (vx, vy, vz) = (
+            self.vx + ax * time_step,
+            self.vy + ay * time_step,
+            self.vz + az * time_step,
+        );
+        Star {
+            vx,
+            vy,
+            vz,
+            x: self.x + vx * time_step,
+            y: self.y + vy * time_step,
+            z: self.z + vz * time_step,
+            ..self.clone()
+        }
+    }
+}
+
+/// Test that a very eccentric binary comes back to where it started after one whole orbit taken in
+/// a single time step.
+#[test]
+fn eccentric_binary_single_step() {
+    // two unit masses at apocenter, 1 apart, with an eccentricity of 0.9
+    let (eccentricity, separation, total_mass) = (0.9, 1., 2.);
+    let semi_major_axis = separation / (1. + eccentricity);
+    let speed = f64::sqrt(total_mass / semi_major_axis * (1. - eccentricity) / (1. + eccentricity));
+    let period = 2. * std::f64::consts::PI * f64::sqrt(semi_major_axis.powi(3) / total_mass);
+    let binary = vec![
+        Star::new(0.5, speed / 2., 1.),
+        Star::new(-0.5, -speed / 2., 1.),
+    ];
+    let mut test_tree = GravTree::new(&binary, period, 3, 0.2, CalculateCollisions::No);
+    test_tree.set_regularization(Some(Regularization::new(2.)));
+
+    let mut after_orbit = test_tree.time_step().as_vec();
+    after_orbit.sort_by(|a, b| b.x.partial_cmp(&a.x).unwrap());
+    for (star, start) in after_orbit.iter().zip(binary.iter()) {
+        assert!((star.x - start.x).abs() < 1e-6);
+        assert!(star.y.abs() < 1e-6);
+        assert!((star.vy - start.vy).abs() < 1e-6);
+    }
+}
+
+/// Test that the rest of the system feels a regularised pair as a single body at its center of
+/// mass.
+#[test]
+fn pair_is_a_composite_body() {
+    let speed = f64::sqrt(2.) / 2.;
+    let mut stars = vec![Star::new(0.5, speed, 1.), Star::new(-0.5, -speed, 1.)];
+    stars.push(Star::new(100., 0., 1.));
+    let mut test_tree = GravTree::new(&stars, 0.1, 3, 0.2, CalculateCollisions::No);
+    test_tree.set_regularization(Some(Regularization::new(2.)));
+    let composite = vec![Star::new(0., 0., 2.), Star::new(100., 0., 1.)];
+    let composite_tree = GravTree::new(&composite, 0.1, 3, 0.2, CalculateCollisions::No);
+
+    let far_star = |stars: Vec<Star>| stars.into_iter().find(|s| s.x > 50.).unwrap();
+    let regularised = far_star(test_tree.time_step().as_vec());
+    let expected = far_star(composite_tree.time_step().as_vec());
+    assert!((regularised.vx - expected.vx).abs() < 1e-12);
+}
+
+/// Test which pairs count as bound.
+#[test]
+fn bound_pairs() {
+    let regularization = Regularization::new(1.);
+    let at = |x: f64, vy: f64| Entity {
+        x,
+        vy,
+        mass: 1.,
+        ..Default::default()
+    };
+    // escape speed of the relative orbit is sqrt(2 G M / r) = 2
+    assert!(regularization.is_bound(&at(0.5, 0.9), &at(-0.5, -0.9)));
+    assert!(!regularization.is_bound(&at(0.5, 1.1), &at(-0.5, -1.1)));
+}
+
+/// Test that a pair whose perturbation is not finite still finishes its time step rather than
+/// stepping through its orbit forever.
+#[test]
+fn non_finite_perturbation_finishes() {
+    let speed = f64::sqrt(2.) / 2.;
+    let mut stars = vec![Star::new(0.5, speed, 1.), Star::new(-0.5, -speed, 1.)];
+    stars.push(Star::new(100., 0., f64::NAN));
+    let mut test_tree = GravTree::new(&stars, 0.1, 3, 0.2, CalculateCollisions::No);
+    test_tree.set_regularization(Some(Regularization::new(2.)));
+    assert_eq!(test_tree.time_step().as_vec().len(), 3);
+}
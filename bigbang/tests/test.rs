@@ -1,5 +1,5 @@
 extern crate bigbang;
-use bigbang::{AsEntity, Entity, GravTree, Responsive, SimulationResult};
+use bigbang::{AsEntity, CalculateCollisions, Entity, GravTree, Responsive, SimulationResult};
 
 #[derive(Clone, PartialEq)]
 struct MyEntity {
@@ -23,6 +23,7 @@ impl AsEntity for MyEntity {
             vz: self.vz,
             radius: self.radius,
             mass: if self.radius < 1. { 0.5 } else { 105. },
+            ..Default::default()
         };
     }
 }
@@ -78,7 +79,7 @@ fn test_traversal() {
         vec.push(entity);
     }
     let vec_clone = vec.clone();
-    let tree = GravTree::new(&vec, 0.2, 3, 0.2);
+    let tree = GravTree::new(&vec, 0.2, 3, 0.2, CalculateCollisions::Yes);
     let traversed_vec = tree.as_vec();
     let mut all_found = true;
     for i in vec_clone {
@@ -98,7 +99,13 @@ fn test_time_step() {
         vec_that_wants_to_be_a_kdtree.push(entity);
     }
 
-    let test_tree = GravTree::new(&vec_that_wants_to_be_a_kdtree, 0.2, 3, 0.2);
+    let test_tree = GravTree::new(
+        &vec_that_wants_to_be_a_kdtree,
+        0.2,
+        3,
+        0.2,
+        CalculateCollisions::Yes,
+    );
     let after_time_step = test_tree.time_step();
     assert_eq!(after_time_step.as_vec().len(), 1000);
 }
@@ -0,0 +1,115 @@
+extern crate bigbang;
+use bigbang::formats::text::{Delimiter, Field, TextError, TextFormat};
+use bigbang::Entity;
+
+fn entity(i: usize) -> Entity {
+    Entity {
+        x: i as f64 + 0.1,
+        y: -(i as f64) / 3.,
+        z: 1e-20,
+        vx: 2.5,
+        vy: i as f64 * 1e12,
+        vz: -0.,
+        radius: 0.3,
+        mass: 7.,
+        wx: 0.5,
+        moment_of_inertia: 2.,
+        collision_group: 4,
+        species: i,
+        charge: -1.,
+        ..Default::default()
+    }
+}
+
+/// Test that every field survives a round trip through CSV exactly.
+#[test]
+fn csv_round_trip() {
+    let entities = (0..3).map(entity).collect::<Vec<_>>();
+    let format = TextFormat::csv();
+    let text = format.write_string(&entities);
+    assert!(text.starts_with("x,y,z,vx,vy,vz,radius,mass,wx,"));
+    let read = format.read_str(&text).unwrap();
+    assert_eq!(read.len(), 3);
+    for (read, entity) in read.iter().zip(entities.iter()) {
+        assert_eq!(
+            format.write_string(std::slice::from_ref(read)),
+            format.write_string(std::slice::from_ref(entity))
+        );
+    }
+}
+
+/// Test that the whitespace format reads what `Entity::as_string` writes.
+#[test]
+fn reads_as_string() {
+    let entity = entity(2);
+    let read = TextFormat::whitespace()
+        .read_str(&format!(
+            "# x y z vx vy vz mass radius\n\n{}\n",
+            entity.as_string()
+        ))
+        .unwrap();
+    assert_eq!(read.len(), 1);
+    assert_eq!(read[0].as_string(), entity.as_string());
+}
+
+/// Test a catalogue with its own column order, extra columns and comments.
+#[test]
+fn column_mapping() {
+    let catalogue = "
+        % a catalogue of stars
+        name\tMASS\tx\ty\tz
+        % the sun
+        sun\t1\t0\t0\t0
+        alpha-cen\t1.1\t1.3\t-0.2\t0.7
+    ";
+    let format = TextFormat {
+        delimiter: Delimiter::Whitespace,
+        comment: Some('%'),
+        ..TextFormat::csv()
+    };
+    let stars = format.read_str(catalogue).unwrap();
+    assert_eq!(stars.len(), 2);
+    assert_eq!(stars[1].mass, 1.1);
+    assert_eq!((stars[1].x, stars[1].y, stars[1].z), (1.3, -0.2, 0.7));
+
+    // the same columns given explicitly, skipping the header
+    let format = format.with_columns(&[Field::Ignore, Field::Mass, Field::X, Field::Y, Field::Z]);
+    assert_eq!(format.read_str(catalogue).unwrap()[1].x, 1.3);
+    let written = format.write_string(&stars);
+    assert!(written.starts_with("ignore mass x y z\n0 1 0 0 0\n"));
+}
+
+/// Test that malformed rows are reported with their line number.
+#[test]
+fn errors() {
+    let format = TextFormat::csv().with_columns(&[Field::X, Field::Species]);
+    let error = |text: &str| match format.read_str(text) {
+        Err(TextError::Malformed { line, message }) => (line, message),
+        _ => panic!("read a malformed row"),
+    };
+    assert_eq!(
+        error("x,species\n1,2\n\n1,2,3\n"),
+        (4, "expected 2 columns but found 3".to_string())
+    );
+    assert_eq!(
+        error("x,species\n# comment\nfive,2\n"),
+        (3, "column 1 (x): `five` is not a number".to_string())
+    );
+    assert_eq!(
+        error("x,species\n1,-2\n"),
+        (
+            2,
+            "column 2 (species): `-2` is not a non-negative integer".to_string()
+        )
+    );
+    assert_eq!(
+        format!(
+            "{}",
+            TextError::Malformed {
+                line: 3,
+                message: "oops".to_string()
+            }
+        ),
+        "line 3: oops"
+    );
+}
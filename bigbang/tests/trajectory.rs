@@ -0,0 +1,212 @@
+extern crate bigbang;
+use bigbang::formats::text::Field;
+use bigbang::formats::trajectory::{TrajectoryError, TrajectoryReader, TrajectoryWriter};
+use bigbang::{AsEntity, CalculateCollisions, Entity, GravTree, Responsive, SimulationResult};
+use std::collections::HashMap;
+use std::io::Cursor;
+
+#[derive(Clone, AsEntity)]
+struct Particle {
+    x: f64,
+    y: f64,
+    z: f64,
+    vx: f64,
+    vy: f64,
+    vz: f64,
+    radius: f64,
+    mass: f64,
+    id: u64,
+}
+
+impl Responsive for Particle {
+    fn respond(&self, simulation_result: SimulationResult<Self>, time_step: f64) -> Self {
+        let (ax, ay, az) = simulation_result.gravitational_acceleration;
+        let (vx, vy, vz) = (
+            self.vx + ax * time_step,
+            self.vy + ay * time_step,
+            self.vz + az * time_step,
+        );
+        Particle {
+            x: self.x + vx * time_step,
+            y: self.y + vy * time_step,
+            z: self.z + vz * time_step,
+            vx,
+            vy,
+            vz,
+            ..self.clone()
+        }
+    }
+}
+
+const FIELDS: [Field; 7] = [
+    Field::X,
+    Field::Y,
+    Field::Z,
+    Field::Vx,
+    Field::Vy,
+    Field::Vz,
+    Field::Mass,
+];
+
+fn tree() -> GravTree<Particle> {
+    let particles = (0..6)
+        .map(|i| Particle {
+            x: (i % 3) as f64,
+            y: (i / 3) as f64,
+            z: 0.1 * i as f64,
+            vx: 0.,
+            vy: 0.,
+            vz: 0.,
+            radius: 0.,
+            mass: 1. + i as f64,
+            id: 100 + i,
+        })
+        .collect::<Vec<_>>();
+    GravTree::new(&particles, 0.01, 2, 0.2, CalculateCollisions::No)
+}
+
+/// Returns the recorded fields of each entity of `tree`, by ID.
+fn expected(tree: &GravTree<Particle>) -> HashMap<u64, Vec<f64>> {
+    tree.as_vec()
+        .iter()
+        .map(|particle| {
+            let entity = particle.as_entity();
+            let values = vec![
+                entity.x,
+                entity.y,
+                entity.z,
+                entity.vx,
+                entity.vy,
+                entity.vz,
+                entity.mass,
+            ];
+            (particle.id, values)
+        })
+        .collect()
+}
+
+fn values(ids: &[u64], entities: &[Entity]) -> HashMap<u64, Vec<f64>> {
+    ids.iter()
+        .zip(entities)
+        .map(|(id, e)| (*id, vec![e.x, e.y, e.z, e.vx, e.vy, e.vz, e.mass]))
+        .collect()
+}
+
+/// Test that every other step of a simulation is recorded, and read back exactly.
+#[test]
+fn round_trip() {
+    let mut tree = tree();
+    let mut writer = TrajectoryWriter::new(Cursor::new(Vec::new()), &FIELDS).unwrap();
+    writer.set_every(2);
+    writer.set_chunk_frames(2);
+    let mut states = Vec::new();
+    for _ in 0..=10 {
+        if writer.record_tree(&tree, |particle| particle.id).unwrap() {
+            states.push(expected(&tree));
+        }
+        tree = tree.time_step();
+    }
+    let bytes = writer.finish().unwrap().into_inner();
+
+    let mut reader = TrajectoryReader::new(Cursor::new(bytes)).unwrap();
+    assert_eq!(reader.fields(), &FIELDS[..]);
+    assert_eq!(reader.len(), 6);
+    let steps = reader.steps().iter().map(|s| s.0).collect::<Vec<_>>();
+    assert_eq!(steps, vec![0, 2, 4, 6, 8, 10]);
+    let frames = reader.read_all().unwrap();
+    for (frame, state) in frames.iter().zip(&states) {
+        assert_eq!(&values(&frame.ids, &frame.entities), state);
+    }
+    // the entities moved, so the frames are not all the same
+    assert!(states[0] != states[5]);
+
+    assert!(reader.frame(6).unwrap().unwrap() == frames[3]);
+    assert!(reader.frame_at(1).unwrap().unwrap() == frames[1]);
+    assert!(reader.frame(7).unwrap().is_none());
+    assert!(reader.frame_at(6).unwrap().is_none());
+}
+
+/// Test that a subset of IDs is recorded, and that entities may come and go between frames.
+#[test]
+fn changing_entities() {
+    let entity = |x: f64| Entity {
+        x,
+        mass: 2. * x,
+        ..Default::default()
+    };
+    let mut writer = TrajectoryWriter::new(Cursor::new(Vec::new()), &FIELDS).unwrap();
+    writer.set_ids(Some(&[1, 2, 3, 5]));
+    let steps = vec![
+        vec![(1, entity(1.)), (2, entity(2.)), (4, entity(4.))],
+        vec![(2, entity(2.5)), (1, entity(1.)), (3, entity(3.))],
+        vec![],
+        vec![(5, entity(5.)), (3, entity(3.25))],
+    ];
+    for (step, entities) in steps.iter().enumerate() {
+        assert!(writer.record(step as u64, 0.5, entities).unwrap());
+    }
+    let bytes = writer.finish().unwrap().into_inner();
+
+    let frames = TrajectoryReader::new(Cursor::new(bytes))
+        .unwrap()
+        .read_all()
+        .unwrap();
+    assert_eq!(frames.len(), 4);
+    for (frame, entities) in frames.iter().zip(&steps) {
+        let entities = entities
+            .iter()
+            .filter(|(id, _)| *id != 4)
+            .collect::<Vec<_>>();
+        assert_eq!(frame.ids, entities.iter().map(|e| e.0).collect::<Vec<_>>());
+        for (read, (_, written)) in frame.entities.iter().zip(entities) {
+            assert!(read == written);
+        }
+    }
+}
+
+/// Test that a file cut off in the middle of a chunk can be read up to that chunk, and that
+/// other files are refused.
+#[test]
+fn truncated() {
+    let mut writer = TrajectoryWriter::new(Cursor::new(Vec::new()), &[Field::X]).unwrap();
+    writer.set_chunk_frames(3);
+    for step in 0..7 {
+        let entities = vec![(0, Entity::default())];
+        writer.record(step, step as f64, &entities).unwrap();
+    }
+    let mut bytes = writer.finish().unwrap().into_inner();
+    bytes.truncate(bytes.len() - 1);
+    let mut reader = TrajectoryReader::new(Cursor::new(bytes)).unwrap();
+    assert_eq!(reader.len(), 6);
+    assert_eq!(reader.read_all().unwrap().len(), 6);
+
+    match TrajectoryReader::new(Cursor::new(b"not a trajectory".to_vec())) {
+        Err(TrajectoryError::NotATrajectory) => (),
+        _ => panic!("expected the file to be refused"),
+    }
+}
+
+/// Test that entities which barely move compress to a small fraction of their raw size.
+#[test]
+fn compression() {
+    let mut writer = TrajectoryWriter::new(Cursor::new(Vec::new()), &FIELDS).unwrap();
+    for step in 0..100 {
+        let entities = (0..100)
+            .map(|id| {
+                let entity = Entity {
+                    x: id as f64,
+                    y: 1. + step as f64 * 1e-3,
+                    z: -(id as f64) / 7.,
+                    vx: 1e-3,
+                    mass: 1.,
+                    ..Default::default()
+                };
+                (id, entity)
+            })
+            .collect::<Vec<_>>();
+        writer.record(step, step as f64, &entities).unwrap();
+    }
+    let bytes = writer.finish().unwrap().into_inner();
+    let raw = 100 * 100 * (8 + 8 * FIELDS.len());
+    assert!(bytes.len() * 20 < raw, "{} bytes", bytes.len());
+}
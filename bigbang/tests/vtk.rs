@@ -0,0 +1,126 @@
+extern crate bigbang;
+use bigbang::formats::vtk::{write_cells, write_particles, Collection, VtkFormat};
+use bigbang::Entity;
+use std::path::PathBuf;
+
+mod common;
+use common::tree;
+
+/// Returns an entity on the x axis, moving along the y axis.
+fn particle(x: f64, mass: f64) -> Entity {
+    Entity {
+        x,
+        vy: 0.5,
+        radius: 0.25,
+        mass,
+        ..Default::default()
+    }
+}
+
+fn output(name: &str) -> (PathBuf, impl Fn() -> String) {
+    let path = std::env::temp_dir().join(format!("bigbang_{}_{}", std::process::id(), name));
+    let read = path.clone();
+    (path, move || {
+        let contents = std::fs::read_to_string(&read).unwrap();
+        std::fs::remove_file(&read).unwrap();
+        contents
+    })
+}
+
+/// Test the legacy PolyData of two particles, one of which pulls the other.
+#[test]
+fn legacy_particles() {
+    let tree = tree(&[particle(0., 0.), particle(2., 4.)]);
+    let (path, read) = output("particles.vtk");
+    write_particles(&path, &tree, VtkFormat::Legacy).unwrap();
+    let contents = read();
+    let lines = contents.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "# vtk DataFile Version 3.0");
+    assert_eq!(lines[3], "DATASET POLYDATA");
+    assert_eq!(lines[4], "POINTS 2 double");
+    assert!(lines.contains(&"VERTICES 2 4"));
+    assert!(lines.contains(&"POINT_DATA 2"));
+    // a unit mass 2 away feels an acceleration of 4 / 2^2 towards the heavy particle
+    let acceleration = lines
+        .iter()
+        .position(|line| *line == "VECTORS acceleration double")
+        .unwrap();
+    let mut vectors = lines[acceleration + 1..acceleration + 3].to_vec();
+    vectors.sort();
+    assert_eq!(vectors, vec!["0e0 0e0 0e0", "1e0 0e0 0e0"]);
+}
+
+/// Test that the XML PolyData has every array, with a value for each particle.
+#[test]
+fn xml_particles() {
+    let entities = (0..5).map(|i| particle(i as f64, 1.)).collect::<Vec<_>>();
+    let tree = tree(&entities);
+    let (path, read) = output("particles.vtp");
+    write_particles(&path, &tree, VtkFormat::Xml).unwrap();
+    let contents = read();
+    assert!(contents.contains("<VTKFile type=\"PolyData\""));
+    assert!(contents.contains("NumberOfPoints=\"5\" NumberOfVerts=\"5\""));
+    for name in &["velocity", "mass", "radius", "acceleration", "position"] {
+        assert!(contents.contains(&format!("Name=\"{}\"", name)));
+    }
+    let mut lines = contents.lines();
+    lines.find(|line| line.contains("Name=\"radius\"")).unwrap();
+    assert_eq!(
+        lines.next().unwrap().trim(),
+        "2.5e-1 2.5e-1 2.5e-1 2.5e-1 2.5e-1"
+    );
+}
+
+/// Test that every node of the tree is written as a cell, with the root holding all of the mass.
+#[test]
+fn cells() {
+    let entities = (0..8).map(|i| particle(i as f64, 1.)).collect::<Vec<_>>();
+    let tree = tree(&entities);
+
+    let (path, read) = output("cells.vtk");
+    write_cells(&path, &tree, VtkFormat::Legacy).unwrap();
+    let contents = read();
+    let lines = contents.lines().collect::<Vec<_>>();
+    assert_eq!(lines[3], "DATASET UNSTRUCTURED_GRID");
+    let cells = lines
+        .iter()
+        .find(|line| line.starts_with("CELLS "))
+        .unwrap()
+        .split(' ')
+        .nth(1)
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+    // eight entities with at most three to a leaf need at least two levels below the root
+    assert!(cells >= 3);
+    assert!(lines.contains(&format!("POINTS {} double", 8 * cells).as_str()));
+    assert!(lines.contains(&"8 0 1 2 3 4 5 6 7"));
+    let mass = lines
+        .iter()
+        .position(|line| *line == "SCALARS mass double 1")
+        .unwrap();
+    assert_eq!(lines[mass + 2], "8e0");
+
+    let (path, read) = output("cells.vtu");
+    write_cells(&path, &tree, VtkFormat::Xml).unwrap();
+    let contents = read();
+    assert!(contents.contains(&format!("NumberOfCells=\"{}\"", cells)));
+    assert!(contents.contains("Name=\"depth\""));
+}
+
+/// Test the collection file of a time series.
+#[test]
+fn collection() {
+    let mut collection = Collection::new();
+    collection.add(0., "step_0.vtp");
+    collection.add(0.5, "step_1 & more.vtp");
+    let (path, read) = output("series.pvd");
+    collection.write(&path).unwrap();
+    let contents = read();
+    assert!(contents.contains("<VTKFile type=\"Collection\""));
+    assert!(contents.contains("timestep=\"0e0\" group=\"\" part=\"0\" file=\"step_0.vtp\""));
+    assert!(
+        contents.contains("timestep=\"5e-1\" group=\"\" part=\"0\" file=\"step_1 &amp; more.vtp\"")
+    );
+    assert!(!contents.contains("<Piece"));
+}
@@ -5,7 +5,39 @@ extern crate quote;
 extern crate syn;
 
 use crate::proc_macro::TokenStream;
-use syn::DeriveInput;
+use syn::{Data, DeriveInput, Fields, Ident};
+
+/// Fields of `bigbang::Entity` which are not required to derive `AsEntity`. If the struct has a
+/// field with one of these names, it is used, otherwise the `Entity` default is.
+const OPTIONAL_FIELDS: &[&str] = &[
+    "wx",
+    "wy",
+    "wz",
+    "moment_of_inertia",
+    "collision_group",
+    "collision_mask",
+    "species",
+    "charge",
+];
+
+/// Kinematic fields of `bigbang::Entity` which are not required to derive `Integrable`.
+const OPTIONAL_STATE_FIELDS: &[&str] = &["wx", "wy", "wz"];
+
+/// Returns the fields of the struct whose names are in `names`.
+fn fields_named(input: &DeriveInput, names: &[&str]) -> Vec<Ident> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields
+                .named
+                .iter()
+                .filter_map(|field| field.ident.clone())
+                .filter(|ident| names.contains(&ident.to_string().as_str()))
+                .collect(),
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
 
 #[proc_macro_derive(AsEntity)]
 pub fn derive_as_entity(input: TokenStream) -> TokenStream {
@@ -14,6 +46,9 @@ pub fn derive_as_entity(input: TokenStream) -> TokenStream {
     // type name
     let name = &input.ident;
 
+    // optional fields which this struct happens to have
+    let optional_fields = fields_named(&input, OPTIONAL_FIELDS);
+
     // generics
     let generics = input.generics;
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
@@ -29,7 +64,9 @@ pub fn derive_as_entity(input: TokenStream) -> TokenStream {
                     y: self.y,
                     z: self.z,
                     radius: self.radius,
-                    mass: self.mass
+                    mass: self.mass,
+                    #(#optional_fields: self.#optional_fields,)*
+                    ..Default::default()
                 }
             }
         }
@@ -37,3 +74,36 @@ pub fn derive_as_entity(input: TokenStream) -> TokenStream {
 
     TokenStream::from(expanded)
 }
+
+#[proc_macro_derive(Integrable)]
+pub fn derive_integrable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    // type name
+    let name = &input.ident;
+
+    // optional fields which this struct happens to have
+    let optional_fields = fields_named(&input, OPTIONAL_STATE_FIELDS);
+
+    // generics
+    let generics = input.generics;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let expanded = quote! {
+        impl #impl_generics Integrable for #name #ty_generics #where_clause {
+            fn with_state(&self, state: &bigbang::Entity) -> Self {
+                let mut integrated = self.clone();
+                integrated.vx = state.vx;
+                integrated.vy = state.vy;
+                integrated.vz = state.vz;
+                integrated.x = state.x;
+                integrated.y = state.y;
+                integrated.z = state.z;
+                #(integrated.#optional_fields = state.#optional_fields;)*
+                integrated
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
@@ -94,6 +94,7 @@ impl AsEntity for MyEntity {
             vz: 0.,
             radius: self.radius,
             mass: self.radius,
+            ..Default::default()
         };
     }
 }
@@ -24,6 +24,7 @@ impl AsEntity for Entity {
             vz: self.vz,
             radius: self.radius,
             mass: self.mass,
+            ..Default::default()
         }
     }
 }